@@ -0,0 +1,140 @@
+use bevy::prelude::*;
+use std::ops::Mul;
+
+// A 2D affine transform that, unlike a Bevy Transform, composes correctly when reflections are involved.
+// Stored row-major as [a, b, c, d, e, f], mapping (x, y) to (a*x + b*y + c, d*x + e*y + f).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Affine2 {
+    m: [f32; 6]
+}
+
+impl Affine2 {
+    pub const IDENTITY: Affine2 = Affine2 { m: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0] };
+
+    pub fn new(m: [f32; 6]) -> Self {
+        Affine2 { m }
+    }
+
+    pub fn translation(x: f32, y: f32) -> Self {
+        Affine2::new([1.0, 0.0, x, 0.0, 1.0, y])
+    }
+
+    pub fn rotation(angle: f32) -> Self {
+        let (s, c) = angle.sin_cos();
+        Affine2::new([c, -s, 0.0, s, c, 0.0])
+    }
+
+    pub fn scale(s: f32) -> Self {
+        Affine2::new([s, 0.0, 0.0, 0.0, s, 0.0])
+    }
+
+    // Mirror image across the x axis
+    pub fn reflect_x() -> Self {
+        Affine2::new([1.0, 0.0, 0.0, 0.0, -1.0, 0.0])
+    }
+
+    pub fn rotate_about(point: Vec2, angle: f32) -> Self {
+        Affine2::translation(point.x, point.y) * Affine2::rotation(angle) * Affine2::translation(-point.x, -point.y)
+    }
+
+    // The similarity that takes the unit segment (0,0)-(1,0) onto p-q
+    fn match_segment(p: Vec2, q: Vec2) -> Self {
+        Affine2::new([q.x - p.x, p.y - q.y, p.x, q.y - p.y, q.x - p.x, p.y])
+    }
+
+    // The orientation preserving similarity that takes p1 onto p2 and q1 onto q2
+    pub fn match_two(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> Self {
+        Affine2::match_segment(p2, q2) * Affine2::match_segment(p1, q1).inverse()
+    }
+
+    // The affine map taking the corners of one triangle onto those of another, reflecting when the two go
+    // round in opposite directions
+    pub fn map_triangle(from: [Vec2; 3], to: [Vec2; 3]) -> Self {
+        let basis = |t: [Vec2; 3]| {
+            let (u, v) = (t[1] - t[0], t[2] - t[0]);
+            Affine2::new([u.x, v.x, t[0].x, u.y, v.y, t[0].y])
+        };
+        basis(to) * basis(from).inverse()
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.m[0] * self.m[4] - self.m[1] * self.m[3]
+    }
+
+    pub fn is_reflection(&self) -> bool {
+        self.determinant() < 0.0
+    }
+
+    pub fn inverse(&self) -> Self {
+        let m = &self.m;
+        let det = self.determinant();
+        Affine2::new([
+            m[4] / det, -m[1] / det, (m[1] * m[5] - m[2] * m[4]) / det,
+            -m[3] / det, m[0] / det, (m[2] * m[3] - m[0] * m[5]) / det
+        ])
+    }
+
+    pub fn transform_point(&self, p: Vec2) -> Vec2 {
+        let m = &self.m;
+        Vec2::new(m[0] * p.x + m[1] * p.y + m[2], m[3] * p.x + m[4] * p.y + m[5])
+    }
+
+    pub fn transform_points(&self, points: &[Vec2]) -> Vec<Vec2> {
+        points.iter().map(|p| self.transform_point(*p)).collect()
+    }
+
+    // Splits this transform into an orientation preserving part and whether a reflection across the x axis
+    // has to be applied first, so that self == rigid * (reflect_x if reflected).
+    // Bevy's Transform can't represent the reflection (its rotation is a quaternion and Transform * Transform
+    // doesn't commute scale past rotation), so callers mirror the tile's points themselves and hand the
+    // rigid part to Bevy.
+    pub fn split_reflection(&self) -> (Affine2, bool) {
+        if self.is_reflection() {
+            (*self * Affine2::reflect_x(), true)
+        } else {
+            (*self, false)
+        }
+    }
+
+    pub fn uniform_scale(&self) -> f32 {
+        self.determinant().abs().sqrt()
+    }
+
+    // The rotation and translation of an orientation preserving similarity, see split_reflection.
+    // The scale is left out on purpose: shapes bake it into their points so outlines keep their width.
+    pub fn to_transform(self, z: f32) -> Transform {
+        assert!(!self.is_reflection());
+        let m = &self.m;
+        let angle = m[3].atan2(m[0]);
+        Transform {
+            translation: Vec3::new(m[2], m[5], z),
+            rotation: Quat::from_rotation_z(angle),
+            scale: Vec3::ONE
+        }
+    }
+}
+
+impl Default for Affine2 {
+    fn default() -> Self {
+        Affine2::IDENTITY
+    }
+}
+
+impl Mul<Affine2> for Affine2 {
+    type Output = Affine2;
+
+    fn mul(self, rhs: Affine2) -> Affine2 {
+        let a = &self.m;
+        let b = &rhs.m;
+        Affine2::new([
+            a[0] * b[0] + a[1] * b[3], a[0] * b[1] + a[1] * b[4], a[0] * b[2] + a[1] * b[5] + a[2],
+            a[3] * b[0] + a[4] * b[3], a[3] * b[1] + a[4] * b[4], a[3] * b[2] + a[4] * b[5] + a[5]
+        ])
+    }
+}
+
+pub fn line_intersection(p1: Vec2, q1: Vec2, p2: Vec2, q2: Vec2) -> Vec2 {
+    let d = (q2.y - p2.y) * (q1.x - p1.x) - (q2.x - p2.x) * (q1.y - p1.y);
+    let u = ((q2.x - p2.x) * (p1.y - p2.y) - (q2.y - p2.y) * (p1.x - p2.x)) / d;
+    p1 + (q1 - p1) * u
+}
@@ -0,0 +1,771 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy::ecs::system::EntityCommands;
+use bevy::input::{keyboard::KeyCode, Input};
+use num_traits::FromPrimitive;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::{Add, Sub};
+use strum_macros::Display;
+
+use crate::geometry;
+use crate::growth::{self, Growth, TypeWeights};
+use crate::multigrid::{LatticePoint, Multigrid};
+use crate::tiling::{Tiling, TilingError};
+use crate::{rhombus_points, rhombus_small_angle, spawn_tile, PenroseEnum, PointList, Tile, TileShape};
+
+// The Ammann-Beenker tiling: unit squares and 45 degree rhombs with 8-fold symmetry, either inflated by
+// the silver ratio 1 + sqrt(2) or generated as the dual of an octagrid (the multigrid with four families
+// of parallel lines).
+//
+// Squares are handled as pairs of right isosceles triangles, the diagonal between them is what the
+// arrowed-edge matching rules need on top of the outline. Every edge carries an arrow: on a rhomb it points
+// at the nearest acute corner, on a square it points away from the two corners the diagonal doesn't touch.
+
+const AB_LEG_LEN: f32 = 40.0;
+const GROWTH_STEPS: usize = 20;
+
+// Edge directions are multiples of 45 degrees, so every vertex is an integer combination of the four
+// unit vectors at 0, 45, 90 and 135 degrees and can be compared exactly
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct OctagonalPoint([i32; 4]);
+
+impl OctagonalPoint {
+    pub const ORIGIN: OctagonalPoint = OctagonalPoint([0, 0, 0, 0]);
+
+    // Unit vector at k * 45 degrees
+    pub fn unit(k: i32) -> Self {
+        let k = k.rem_euclid(8) as usize;
+        let mut coords = [0; 4];
+        if k < 4 {
+            coords[k] = 1;
+        } else {
+            coords[k - 4] = -1;
+        }
+        OctagonalPoint(coords)
+    }
+
+    // Multiply by the silver ratio, using sqrt(2) * e_k = e_(k-1) + e_(k+1)
+    pub fn inflate(&self) -> Self {
+        let mut inflated = *self;
+        for k in 0..4 {
+            let n = self.0[k];
+            inflated = inflated + OctagonalPoint::unit(k as i32 - 1).scale(n) + OctagonalPoint::unit(k as i32 + 1).scale(n);
+        }
+        inflated
+    }
+
+    fn scale(&self, n: i32) -> Self {
+        OctagonalPoint([self.0[0] * n, self.0[1] * n, self.0[2] * n, self.0[3] * n])
+    }
+
+    // The octagrid's lattice points, its normals are the same four unit vectors
+    pub fn from_lattice(point: &LatticePoint) -> Self {
+        OctagonalPoint([point[0], point[1], point[2], point[3]])
+    }
+
+    pub fn to_world(self, leg_len: f32) -> Vec2 {
+        let mut p = Vec2::ZERO;
+        for k in 0..4 {
+            let angle = f32::to_radians(45.0 * k as f32);
+            p += Vec2::new(angle.cos(), angle.sin()) * (self.0[k] as f32);
+        }
+        p * leg_len
+    }
+}
+
+impl Add for OctagonalPoint {
+    type Output = OctagonalPoint;
+    fn add(self, rhs: OctagonalPoint) -> OctagonalPoint {
+        OctagonalPoint([self.0[0] + rhs.0[0], self.0[1] + rhs.0[1], self.0[2] + rhs.0[2], self.0[3] + rhs.0[3]])
+    }
+}
+
+impl Sub for OctagonalPoint {
+    type Output = OctagonalPoint;
+    fn sub(self, rhs: OctagonalPoint) -> OctagonalPoint {
+        OctagonalPoint([self.0[0] - rhs.0[0], self.0[1] - rhs.0[1], self.0[2] - rhs.0[2], self.0[3] - rhs.0[3]])
+    }
+}
+
+fn e(k: i32) -> OctagonalPoint {
+    OctagonalPoint::unit(k)
+}
+
+// The pieces the substitution works with. Directions are multiples of 45 degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum AbPiece {
+    // Acute corner at `corner`, edges along e(direction) and e(direction + 1)
+    Rhomb { corner: OctagonalPoint, direction: i32 },
+    // Right angle at `corner`, legs along e(direction) and e(direction + 2). The triangles are chiral: the
+    // arrow on the first leg points away from the right angle and the one on the second leg towards it,
+    // the other way round when reflected.
+    Triangle { corner: OctagonalPoint, direction: i32, reflected: bool }
+}
+
+impl AbPiece {
+    fn rhomb(corner: OctagonalPoint, direction: i32) -> Self {
+        AbPiece::Rhomb { corner, direction: direction.rem_euclid(8) }
+    }
+
+    fn triangle(corner: OctagonalPoint, direction: i32, reflected: bool) -> Self {
+        AbPiece::Triangle { corner, direction: direction.rem_euclid(8), reflected }
+    }
+
+    // Replace this piece by the pieces of the next level down, scaled up by the silver ratio so that
+    // children have unit edges again. Each parent edge ends up as a unit edge at the end its arrow points
+    // to and a triangle's hypotenuse at the other end.
+    fn substitute(&self, children: &mut Vec<AbPiece>) {
+        match *self {
+            AbPiece::Rhomb { corner, direction: k } => {
+                let a = corner.inflate();
+                let b = a + e(k).inflate();
+                let c = b + e(k + 1).inflate();
+                // Right angles of the triangles along the edges
+                let x = a + e(k) + e(k + 1);
+                let y = c - e(k) - e(k + 1);
+
+                children.push(AbPiece::rhomb(a, k));
+                children.push(AbPiece::rhomb(c, k + 4));
+                children.push(AbPiece::rhomb(b, k + 2));
+                children.push(AbPiece::triangle(x, k + 2, false));
+                children.push(AbPiece::triangle(x, k + 5, true));
+                children.push(AbPiece::triangle(y, k + 1, true));
+                children.push(AbPiece::triangle(y, k + 6, false));
+            },
+            AbPiece::Triangle { corner, direction: k, reflected: false } => {
+                let centre = corner.inflate() + e(k + 1);
+                let side = centre + e(k + 2);
+
+                children.push(AbPiece::triangle(centre, k, true));
+                children.push(AbPiece::triangle(centre, k + 5, false));
+                children.push(AbPiece::rhomb(centre, k + 7));
+                children.push(AbPiece::triangle(side, k + 3, false));
+                children.push(AbPiece::rhomb(side, k + 5));
+            },
+            AbPiece::Triangle { corner, direction: k, reflected: true } => {
+                // The mirror image of the above across the bisector of the right angle
+                let centre = corner.inflate() + e(k + 1);
+                let side = centre + e(k);
+
+                children.push(AbPiece::triangle(centre, k, false));
+                children.push(AbPiece::triangle(centre, k + 3, true));
+                children.push(AbPiece::rhomb(centre, k + 2));
+                children.push(AbPiece::triangle(side, k + 5, true));
+                children.push(AbPiece::rhomb(side, k + 4));
+            }
+        }
+    }
+
+    fn get_vertices(&self) -> Vec<OctagonalPoint> {
+        match *self {
+            AbPiece::Rhomb { corner, direction: k } => vec![corner, corner + e(k), corner + e(k) + e(k + 1), corner + e(k + 1)],
+            AbPiece::Triangle { corner, direction: k, .. } => vec![corner, corner + e(k), corner + e(k + 2)]
+        }
+    }
+
+    // (start, end, arrow target) for each edge carrying an arrow. A triangle's hypotenuse is the inside of
+    // a square so it carries none.
+    fn get_arrows(&self) -> Vec<(OctagonalPoint, OctagonalPoint, OctagonalPoint)> {
+        let v = self.get_vertices();
+        match *self {
+            AbPiece::Rhomb { .. } => vec![
+                (v[0], v[1], v[0]), (v[1], v[2], v[2]), (v[2], v[3], v[2]), (v[3], v[0], v[0])
+            ],
+            AbPiece::Triangle { reflected: false, .. } => vec![(v[0], v[1], v[1]), (v[2], v[0], v[0])],
+            AbPiece::Triangle { reflected: true, .. } => vec![(v[0], v[1], v[0]), (v[2], v[0], v[2])]
+        }
+    }
+}
+
+// The 8 rhombs meeting at the centre of the classic octagonal patch
+fn make_star() -> Vec<AbPiece> {
+    (0..8).map(|k| AbPiece::rhomb(OctagonalPoint::ORIGIN, k)).collect()
+}
+
+fn substitute_all(pieces: &[AbPiece]) -> Vec<AbPiece> {
+    let mut children = Vec::new();
+    for piece in pieces {
+        piece.substitute(&mut children);
+    }
+    children
+}
+
+fn sorted_edge(p: OctagonalPoint, q: OctagonalPoint) -> (OctagonalPoint, OctagonalPoint) {
+    if p < q { (p, q) } else { (q, p) }
+}
+
+// Every edge shared by two pieces has to carry arrows pointing the same way
+fn count_arrow_mismatches(arrows: impl Iterator<Item = (OctagonalPoint, OctagonalPoint, OctagonalPoint)>) -> usize {
+    let mut targets: HashMap<(OctagonalPoint, OctagonalPoint), OctagonalPoint> = HashMap::new();
+    let mut mismatches = 0;
+    for (start, end, target) in arrows {
+        let edge = sorted_edge(start, end);
+        match targets.get(&edge) {
+            Some(existing) if *existing != target => mismatches += 1,
+            Some(_) => {},
+            None => { targets.insert(edge, target); }
+        }
+    }
+    mismatches
+}
+
+// Turn octagrid quads into pieces. Rhombs are fully determined by their shape, but how a square splits
+// into triangles is only known from the arrows around it, so squares take it from their neighbours,
+// starting next to rhombs and spreading out from there.
+fn pieces_from_quads(quads: &[[OctagonalPoint; 4]]) -> Vec<AbPiece> {
+    let mut pieces = Vec::new();
+    let mut squares = Vec::new();
+    for quad in quads {
+        let along = quad[1] - quad[0];
+        let across = quad[3] - quad[0];
+        let j = (0..8).find(|k| e(*k) == along).unwrap();
+        let k = (0..8).find(|k| e(*k) == across).unwrap();
+        match (k - j).rem_euclid(8) {
+            1 => pieces.push(AbPiece::rhomb(quad[0], j)),
+            3 => pieces.push(AbPiece::rhomb(quad[1], j + 3)),
+            _ => squares.push(*quad)
+        }
+    }
+
+    let mut targets: HashMap<(OctagonalPoint, OctagonalPoint), OctagonalPoint> = HashMap::new();
+    for piece in &pieces {
+        for (start, end, target) in piece.get_arrows() {
+            targets.insert(sorted_edge(start, end), target);
+        }
+    }
+
+    let score = |halves: &[AbPiece; 2], targets: &HashMap<(OctagonalPoint, OctagonalPoint), OctagonalPoint>| -> (i32, i32) {
+        let mut agree = 0;
+        let mut disagree = 0;
+        for half in halves {
+            for (start, end, target) in half.get_arrows() {
+                match targets.get(&sorted_edge(start, end)) {
+                    Some(existing) if *existing == target => agree += 1,
+                    Some(_) => disagree += 1,
+                    None => {}
+                }
+            }
+        }
+        (agree, disagree)
+    };
+
+    let mut undecided: VecDeque<[OctagonalPoint; 4]> = squares.into_iter().collect();
+    let mut stalled = 0;
+    while let Some(quad) = undecided.pop_front() {
+        let options: Vec<[AbPiece; 2]> = (0..4).map(|i| square_halves(quad[i], quad[(i + 1) % 4] - quad[i])).collect();
+        let scores: Vec<(i32, i32)> = options.iter().map(|halves| score(halves, &targets)).collect();
+        let known = scores[0].0 + scores[0].1 > 0;
+        if !known && stalled <= undecided.len() {
+            // Nothing around this square is decided yet, come back to it later
+            undecided.push_back(quad);
+            stalled += 1;
+            continue;
+        }
+        stalled = 0;
+
+        let choice = (0..4).max_by_key(|i| scores[*i].0 - scores[*i].1).unwrap();
+        for half in options[choice].iter() {
+            for (start, end, target) in half.get_arrows() {
+                targets.entry(sorted_edge(start, end)).or_insert(target);
+            }
+            pieces.push(*half);
+        }
+    }
+
+    pieces
+}
+
+// A square is a triangle and its reflection sharing the hypotenuse, given by the first triangle's right
+// angle and first leg
+fn square_halves(corner: OctagonalPoint, leg: OctagonalPoint) -> [AbPiece; 2] {
+    let k = (0..8).find(|k| e(*k) == leg).unwrap();
+    [AbPiece::triangle(corner, k, false), AbPiece::triangle(corner + e(k) + e(k + 2), k + 4, true)]
+}
+
+#[derive(Display, Clone, Copy, Primitive, PartialEq)]
+pub enum AmmannBeenkerType {
+    Square = 0,
+    Rhomb = 1,
+    // Half a square, only left at the boundary of a patch
+    HalfSquare = 2,
+
+    Count = 3
+}
+
+impl PenroseEnum for AmmannBeenkerType {
+    fn get_all() -> Vec<Self> {
+        let mut vec = Vec::new();
+        for i in 0..(AmmannBeenkerType::Count as usize) {
+            vec.push(AmmannBeenkerType::from_usize(i).unwrap());
+        }
+        vec
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AbPose {
+    // Steps of 45 degrees the long diagonal is turned by, on top of 22.5 degrees for rhombs
+    orientation: i32,
+    left: OctagonalPoint
+}
+
+// Tiles are stored like the Penrose rhombs: centred on the origin with the long diagonal (for squares, the
+// one splitting it into triangles) along the x axis, points ordered left, top, right, bottom. A rhomb's
+// arrows point at its left and right corners, a square's at its left corner and away from its right one.
+#[derive(Clone)]
+pub struct AmmannBeenkerTile {
+    ab_type: AmmannBeenkerType,
+    // Only used for half squares, which are mirrored so their right angle ends up below the diagonal
+    reflected: bool,
+    leg_len: f32,
+    pose: AbPose,
+    used_side_flags: u32
+}
+
+impl AmmannBeenkerTile {
+    // Direction of each side, going round the points, in steps of 45 degrees on top of the orientation. A
+    // half square only has the first two, and the diagonal back to its left corner.
+    const SIDE_DIRECTIONS: [[i32; 4]; 3] = [
+        // Square directions
+        [1, 7, 5, 3],
+        // Rhomb directions
+        [1, 0, 5, 4],
+        // Half square directions
+        [1, 7, 5, 3]
+    ];
+
+    // Whether the arrow on each side points at the corner the side ends at rather than the one it starts at.
+    // A half square's diagonal is the inside of a square and carries none.
+    const ARROWS_TO_END: [[bool; 4]; 3] = [
+        [false, false, true, true],
+        [false, true, false, true],
+        [false, false, false, false]
+    ];
+
+    fn new(ab_type: AmmannBeenkerType, reflected: bool, pose: AbPose, leg_len: f32) -> Self {
+        AmmannBeenkerTile {
+            ab_type,
+            reflected,
+            leg_len,
+            pose,
+            used_side_flags: 0
+        }
+    }
+
+    // The tile with its left and right corners at the given points
+    fn new_from_diagonal(ab_type: AmmannBeenkerType, reflected: bool, left: OctagonalPoint, right: OctagonalPoint, leg_len: f32) -> Self {
+        let mut tile = AmmannBeenkerTile::new(ab_type, reflected, AbPose { orientation: 0, left }, leg_len);
+        for orientation in 0..8 {
+            tile.pose.orientation = orientation;
+            if tile.get_lattice_points()[2] == right {
+                break;
+            }
+        }
+        tile
+    }
+
+    fn get_small_angle(&self) -> f32 {
+        rhombus_small_angle(&AmmannBeenkerTile::SIDE_DIRECTIONS[self.ab_type as usize], 8)
+    }
+
+    // Half squares are mirrored across the diagonal, which turns each side direction the other way
+    fn get_side_direction(&self, side: usize) -> i32 {
+        let direction = AmmannBeenkerTile::SIDE_DIRECTIONS[self.ab_type as usize][side];
+        self.pose.orientation + if self.reflected { -direction } else { direction }
+    }
+
+    // Lattice points of the corners, in the same order as get_points
+    fn get_lattice_points(&self) -> Vec<OctagonalPoint> {
+        let mut points = vec![self.pose.left];
+        for side in 0..(self.get_side_count() - 1) {
+            points.push(points[side] + e(self.get_side_direction(side)));
+        }
+        points
+    }
+
+    fn get_num_arrows(&self) -> usize {
+        if self.ab_type == AmmannBeenkerType::HalfSquare { 2 } else { 4 }
+    }
+
+    // (start, end, arrow target) for each side carrying an arrow, like AbPiece::get_arrows
+    fn get_arrows(&self) -> Vec<(OctagonalPoint, OctagonalPoint, OctagonalPoint)> {
+        let points = self.get_lattice_points();
+        let arrows_to_end = &AmmannBeenkerTile::ARROWS_TO_END[self.ab_type as usize];
+        (0..self.get_num_arrows()).map(|side| {
+            let (start, end) = (points[side], points[(side + 1) % points.len()]);
+            (start, end, if arrows_to_end[side] { end } else { start })
+        }).collect()
+    }
+
+    fn get_color(&self) -> Color {
+        match self.ab_type {
+            AmmannBeenkerType::Square => Color::rgb_u8(250, 200, 80),
+            AmmannBeenkerType::Rhomb => Color::rgb_u8(60, 120, 200),
+            AmmannBeenkerType::HalfSquare => Color::rgb_u8(250, 225, 160),
+            AmmannBeenkerType::Count => panic!("Invalid type")
+        }
+    }
+}
+
+impl TileShape<AmmannBeenkerType> for AmmannBeenkerTile {
+    fn get_points(&self) -> PointList {
+        let points = rhombus_points(self.get_small_angle(), self.leg_len);
+        match self.ab_type {
+            AmmannBeenkerType::HalfSquare if self.reflected => vec![points[0], points[3], points[2]],
+            AmmannBeenkerType::HalfSquare => vec![points[0], points[1], points[2]],
+            _ => points
+        }
+    }
+
+    fn get_type(&self) -> AmmannBeenkerType {
+        self.ab_type
+    }
+
+    fn insert_shape_component(&self, transform: Transform, entity_commands: &mut EntityCommands) {
+        entity_commands.insert_bundle(
+            GeometryBuilder::build_as(
+                &shapes::Polygon {
+                    points: self.get_points(),
+                    closed: true
+                },
+                ShapeColors::outlined(self.get_color(), Color::BLACK),
+                DrawMode::Outlined {
+                    fill_options: FillOptions::default(),
+                    outline_options: StrokeOptions::default().with_line_width(2.0),
+                },
+                transform
+            )
+        );
+    }
+
+    // The arrows of the matching rules, drawn at each edge's midpoint, plus the diagonal of a square
+    fn spawn_dots_entities(&self, parent: Entity, commands: &mut Commands) {
+        let points = self.get_points();
+        let mut children = Vec::new();
+
+        // (start, end, arrow target) as indices into the points
+        let arrows_to_end = &AmmannBeenkerTile::ARROWS_TO_END[self.ab_type as usize];
+        let sides: Vec<(usize, usize, usize)> = (0..self.get_num_arrows()).map(|side| {
+            let end = (side + 1) % points.len();
+            (side, end, if arrows_to_end[side] { end } else { side })
+        }).collect();
+        for (start, end, target) in sides {
+            let mid = (points[start] + points[end]) / 2.0;
+            let towards = (points[target] - mid).normalize() * 7.0;
+            let across = towards.perp() * 0.6;
+
+            let mut entity = commands.spawn();
+            entity.insert_bundle(
+                GeometryBuilder::build_as(
+                    &shapes::Polygon {
+                        points: vec![mid + towards, mid - towards * 0.5 + across, mid - towards * 0.5 - across],
+                        closed: true
+                    },
+                    ShapeColors::new(Color::BLACK),
+                    DrawMode::Fill(FillOptions::default()),
+                    Transform::from_xyz(0.0, 0.0, 1.0)
+                )
+            );
+            children.push(entity.id());
+        }
+
+        if self.ab_type != AmmannBeenkerType::Rhomb {
+            let mut entity = commands.spawn();
+            entity.insert_bundle(
+                GeometryBuilder::build_as(
+                    &shapes::Polygon {
+                        points: vec![points[0], points[2]],
+                        closed: false
+                    },
+                    ShapeColors::new(Color::DARK_GRAY),
+                    DrawMode::Stroke(StrokeOptions::default().with_line_width(1.0)),
+                    Transform::from_xyz(0.0, 0.0, 1.0)
+                )
+            );
+            children.push(entity.id());
+        }
+
+        commands.entity(parent).push_children(&children);
+    }
+}
+
+// Squares and rhombs grown edge by edge. Every side has unit length, so any side can go on any other and the
+// arrows are the whole matching rule. Half squares only come from cutting a substituted patch off, growth
+// never puts them down.
+impl Tile<AmmannBeenkerType> for AmmannBeenkerTile {
+    type Vertex = OctagonalPoint;
+
+    fn new_random(weights: &TypeWeights) -> Self {
+        let candidates = vec![(AmmannBeenkerType::Square, 0), (AmmannBeenkerType::Rhomb, 0)];
+        let ab_type = weights.choose(&candidates).unwrap_or(AmmannBeenkerType::Square);
+        AmmannBeenkerTile::new(ab_type, false, AbPose::default(), AB_LEG_LEN)
+    }
+
+    fn get_num_sides() -> usize {
+        4
+    }
+
+    fn get_side_count(&self) -> usize {
+        if self.ab_type == AmmannBeenkerType::HalfSquare { 3 } else { 4 }
+    }
+
+    fn get_side_flags(&self) -> u32 {
+        self.used_side_flags
+    }
+
+    fn get_side_flags_mut(&mut self) -> &mut u32 {
+        &mut self.used_side_flags
+    }
+
+    // A side with its arrow the other way round. A square has two of each, so the one that goes down depends
+    // on the side it goes on and squares turn up both ways round.
+    fn get_matching_side(onto_type: AmmannBeenkerType, onto_side: u8, other_type: AmmannBeenkerType) -> Result<u8, TilingError> {
+        for ab_type in &[onto_type, other_type] {
+            if *ab_type as usize >= AmmannBeenkerType::Count as usize {
+                return Err(TilingError::InvalidType(ab_type.to_string()));
+            }
+        }
+        if onto_side as usize >= 4 || (onto_type == AmmannBeenkerType::HalfSquare && onto_side >= 3) {
+            return Err(TilingError::InvalidSide(onto_side));
+        }
+        let to_end = !AmmannBeenkerTile::ARROWS_TO_END[onto_type as usize][onto_side as usize];
+        Ok(match other_type {
+            AmmannBeenkerType::Rhomb if to_end => 1,
+            AmmannBeenkerType::Rhomb => 0,
+            AmmannBeenkerType::Square => (if to_end { 2 } else { 0 }) + onto_side % 2,
+            _ => 0
+        })
+    }
+
+    fn sides_can_meet(onto_type: AmmannBeenkerType, onto_side: u8, other_type: AmmannBeenkerType, _other_side: u8) -> Result<bool, TilingError> {
+        AmmannBeenkerTile::get_matching_side(onto_type, onto_side, other_type)?;
+        Ok(true)
+    }
+
+    // The shared side runs opposite ways round the two tiles, so agreeing arrows point at opposite ends
+    fn sides_agree(onto_type: AmmannBeenkerType, onto_side: u8, other_type: AmmannBeenkerType, other_side: u8) -> bool {
+        if onto_type == AmmannBeenkerType::HalfSquare || other_type == AmmannBeenkerType::HalfSquare {
+            return false;
+        }
+        AmmannBeenkerTile::ARROWS_TO_END[onto_type as usize][onto_side as usize]
+            != AmmannBeenkerTile::ARROWS_TO_END[other_type as usize][other_side as usize]
+    }
+
+    fn get_connected_tile(&self, side: u8, other_type: AmmannBeenkerType) -> Result<Self, TilingError> {
+        let other_side = AmmannBeenkerTile::get_matching_side(self.ab_type, side, other_type)? as usize;
+        let direction = self.get_side_direction(side as usize) + 4;
+        let orientation = (direction - AmmannBeenkerTile::SIDE_DIRECTIONS[other_type as usize][other_side]).rem_euclid(8);
+        let corner = self.get_lattice_points()[(side as usize + 1) % self.get_side_count()];
+
+        let mut tile = AmmannBeenkerTile::new(other_type, false, AbPose { orientation, left: corner }, self.leg_len);
+        // Walk back from the shared corner to the new tile's left corner
+        tile.pose.left = corner - (tile.get_lattice_points()[other_side] - corner);
+        Ok(tile)
+    }
+
+    // Rendering transform derived from the pose, nothing is accumulated in floating point
+    fn get_transform(&self) -> Transform {
+        let points = self.get_lattice_points();
+        let left = points[0].to_world(self.leg_len);
+        let right = points[2].to_world(self.leg_len);
+        let base_angle = match self.ab_type {
+            AmmannBeenkerType::Rhomb => 22.5,
+            _ => 0.0
+        };
+        Transform {
+            translation: ((left + right) / 2.0).extend(0.0),
+            rotation: Quat::from_rotation_z(f32::to_radians(45.0 * self.pose.orientation as f32 + base_angle)),
+            scale: Vec3::ONE
+        }
+    }
+
+    fn get_vertices(&self) -> Vec<OctagonalPoint> {
+        self.get_lattice_points()
+    }
+}
+
+// Pairs triangles back up into squares. Returns (type, reflected, left corner, right corner) for everything
+// that should be drawn.
+fn assemble_tiles(pieces: &[AbPiece]) -> Vec<(AmmannBeenkerType, bool, OctagonalPoint, OctagonalPoint)> {
+    let triangles: HashSet<(OctagonalPoint, i32)> = pieces.iter().filter_map(|piece| match *piece {
+        AbPiece::Triangle { corner, direction, .. } => Some((corner, direction)),
+        _ => None
+    }).collect();
+
+    let mut tiles = Vec::new();
+    for piece in pieces {
+        match *piece {
+            AbPiece::Rhomb { corner, direction: k } => {
+                tiles.push((AmmannBeenkerType::Rhomb, false, corner, corner + e(k) + e(k + 1)));
+            },
+            AbPiece::Triangle { corner, direction: k, reflected } => {
+                let (first, second) = (corner + e(k), corner + e(k + 2));
+                let paired = triangles.contains(&(first + e(k + 2), (k + 4).rem_euclid(8)));
+                if !paired {
+                    // Mirrored so the left corner is always where the arrows point
+                    if reflected {
+                        tiles.push((AmmannBeenkerType::HalfSquare, true, second, first));
+                    } else {
+                        tiles.push((AmmannBeenkerType::HalfSquare, false, first, second));
+                    }
+                } else if !reflected {
+                    tiles.push((AmmannBeenkerType::Square, false, first, second));
+                }
+            }
+        }
+    }
+    tiles
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum AbSource {
+    Substitution,
+    Octagrid
+}
+
+pub struct AmmannBeenkerTiler {
+    source: AbSource,
+    levels: Vec<Vec<AbPiece>>,
+    current_level: usize,
+    pieces: Vec<AbPiece>,
+    // Tiles grown one at a time instead, with the arrows checked as each goes down
+    tiling: Tiling<AmmannBeenkerType, AmmannBeenkerTile>,
+    growth: Growth<AmmannBeenkerType, AmmannBeenkerTile>,
+    entities: Vec<Entity>
+}
+
+impl Default for AmmannBeenkerTiler {
+    fn default() -> Self {
+        AmmannBeenkerTiler {
+            source: AbSource::Substitution,
+            levels: vec![make_star()],
+            current_level: 0,
+            pieces: Vec::new(),
+            tiling: Tiling::default(),
+            growth: Growth::default(),
+            entities: Vec::new()
+        }
+    }
+}
+
+impl AmmannBeenkerTiler {
+    fn clear(&mut self, commands: &mut Commands) {
+        for entity in self.entities.drain(..) {
+            commands.entity(entity).despawn_recursive();
+        }
+        self.pieces.clear();
+        self.tiling = Tiling::default();
+    }
+
+    fn show_pieces(&mut self, pieces: Vec<AbPiece>, commands: &mut Commands) {
+        self.clear(commands);
+
+        let tiles = assemble_tiles(&pieces);
+        for (ab_type, reflected, left, right) in &tiles {
+            let tile = AmmannBeenkerTile::new_from_diagonal(*ab_type, *reflected, *left, *right, AB_LEG_LEN);
+            let entity = commands.spawn().id();
+            spawn_tile(&tile, entity, commands);
+            self.entities.push(entity);
+        }
+
+        for ab_type in AmmannBeenkerType::get_all() {
+            let count = tiles.iter().filter(|(t, _, _, _)| *t == ab_type).count();
+            println!("  {}: {}", ab_type, count);
+        }
+        self.pieces = pieces;
+    }
+
+    fn show_level(&mut self, level: usize, commands: &mut Commands) {
+        while self.levels.len() <= level {
+            let next = substitute_all(self.levels.last().unwrap());
+            self.levels.push(next);
+        }
+        self.source = AbSource::Substitution;
+        self.current_level = level;
+        println!("Substitution level {}", level);
+        let pieces = self.levels[level].clone();
+        self.show_pieces(pieces, commands);
+    }
+
+    fn show_octagrid(&mut self, radius: i32, commands: &mut Commands) {
+        let grid = Multigrid::new_random(4);
+        self.source = AbSource::Octagrid;
+        println!("Octagrid radius {} offsets {:?}", radius, grid.get_offsets());
+        let quads: Vec<[OctagonalPoint; 4]> = grid.dual_tiles(radius).iter().map(|rhomb| {
+            let mut quad = [OctagonalPoint::ORIGIN; 4];
+            for (i, vertex) in rhomb.vertices.iter().enumerate() {
+                quad[i] = OctagonalPoint::from_lattice(vertex);
+            }
+            quad
+        }).collect();
+        let pieces = pieces_from_quads(&quads);
+        self.show_pieces(pieces, commands);
+    }
+
+    // Grow from a single tile in place of the substituted patch, or carry on growing
+    fn grow(&mut self, steps: usize, commands: &mut Commands) {
+        if self.tiling.get_topology().get_num_tiles() == 0 {
+            self.clear(commands);
+        }
+        for entity in growth::grow(&mut self.tiling, &mut self.growth, steps, commands) {
+            self.spawn_tile(entity, commands);
+        }
+    }
+
+    fn spawn_tile(&mut self, entity: Entity, commands: &mut Commands) {
+        if let Some(tile) = self.tiling.get_tile(entity) {
+            spawn_tile(tile, entity, commands);
+            self.entities.push(entity);
+        }
+    }
+
+    fn verify(&self) {
+        if self.tiling.get_topology().get_num_tiles() > 0 {
+            let polygons: Vec<Vec<Vec2>> = self.tiling.get_tiles().map(
+                |(_, tile)| tile.get_lattice_points().iter().map(|p| p.to_world(1.0)).collect()
+            ).collect();
+            let overlaps = geometry::find_overlapping_pairs(&polygons).len();
+            let mismatches = count_arrow_mismatches(self.tiling.get_tiles().flat_map(|(_, tile)| tile.get_arrows()));
+            println!("{} grown tiles, {} overlapping pairs, {} edges with mismatched arrows", polygons.len(), overlaps, mismatches);
+            return;
+        }
+
+        let polygons: Vec<Vec<Vec2>> = self.pieces.iter().map(
+            |piece| piece.get_vertices().iter().map(|p| p.to_world(1.0)).collect()
+        ).collect();
+        let overlaps = geometry::find_overlapping_pairs(&polygons).len();
+        let mismatches = count_arrow_mismatches(self.pieces.iter().flat_map(|piece| piece.get_arrows()));
+        println!("{} pieces, {} overlapping pairs, {} edges with mismatched arrows", self.pieces.len(), overlaps, mismatches);
+    }
+}
+
+pub fn setup_ammann_beenker(mut commands: Commands) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+
+    let mut tiler = AmmannBeenkerTiler::default();
+    tiler.show_level(0, &mut commands);
+    commands.insert_resource(tiler);
+}
+
+pub fn ammann_beenker_controls(
+    mut tiler: ResMut<AmmannBeenkerTiler>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        let level = if tiler.source == AbSource::Substitution { tiler.current_level + 1 } else { tiler.current_level };
+        tiler.show_level(level, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::U) && tiler.current_level > 0 {
+        let level = tiler.current_level - 1;
+        tiler.show_level(level, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::G) {
+        tiler.show_octagrid(6, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::Return) {
+        tiler.grow(GROWTH_STEPS, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::S) {
+        tiler.growth.next_strategy();
+        println!("Growth strategy: {}", tiler.growth.get_strategy_name());
+    } else if keyboard_input.just_pressed(KeyCode::V) {
+        tiler.verify();
+    }
+}
@@ -0,0 +1,512 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::lattice::PentagonalPoint;
+use crate::multigrid::RhombAngles;
+use crate::tiling::Tiling;
+use crate::Rhombus;
+
+// Composition, or de-substitution: grouping the tiles of a patch into the supertiles, a golden ratio larger,
+// that they are the substitution of, and then those into supertiles again as far up as the patch allows.
+// It works on Robinson triangles, the halves of the rhombs, since a rhomb supertile doesn't cut cleanly
+// into whole rhombs but a triangle supertile does:
+//  - a skinny half, with a 36 degree apex, is one skinny and one fat half
+//  - a fat half, with a 108 degree apex, is one skinny and two fat halves
+// Every supertile holds exactly one skinny half, and given that half there are two places its supertile
+// can be. A place is ruled out when a triangle it needs is missing where the patch has something else.
+// When both are ruled out the patch can't be part of any Penrose tiling, whether or not its edges match.
+
+const MAX_LEVELS: usize = 12;
+
+const EPSILON: f32 = 0.0001;
+
+const OUTLINE_WIDTH: f32 = 3.0;
+
+// The halves of a rhomb share the diagonal from b to c, listed the same way round in both. Which corner
+// comes first is what carries the matching rules.
+const FAT_B_CORNER: usize = 2;
+const SKINNY_B_CORNER: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RobinsonTriangle {
+    pub fat: bool,
+    pub a: PentagonalPoint,
+    pub b: PentagonalPoint,
+    pub c: PentagonalPoint
+}
+
+impl RobinsonTriangle {
+    fn new(fat: bool, a: PentagonalPoint, b: PentagonalPoint, c: PentagonalPoint) -> Self {
+        RobinsonTriangle {
+            fat,
+            a,
+            b,
+            c
+        }
+    }
+
+    // The halves of a fat rhomb have their apex at an obtuse corner, those of a skinny one at an acute corner
+    pub fn split_rhombus(tile: &Rhombus) -> [RobinsonTriangle; 2] {
+        let points = tile.get_lattice_points();
+        let (fat, b_corner) = match tile.penrose_type {
+            RhombAngles::PENROSE_SKINNY => (false, SKINNY_B_CORNER),
+            _ => (true, FAT_B_CORNER)
+        };
+        let (b, c) = (points[b_corner], points[(b_corner + 2) % 4]);
+        [
+            RobinsonTriangle::new(fat, points[(b_corner + 1) % 4], b, c),
+            RobinsonTriangle::new(fat, points[(b_corner + 3) % 4], b, c)
+        ]
+    }
+
+    pub fn get_world_points(&self, leg_len: f32) -> [Vec2; 3] {
+        [self.a.to_world(leg_len), self.b.to_world(leg_len), self.c.to_world(leg_len)]
+    }
+
+    pub fn get_centre(&self, leg_len: f32) -> Vec2 {
+        let points = self.get_world_points(leg_len);
+        (points[0] + points[1] + points[2]) / 3.0
+    }
+
+    fn contains(&self, point: Vec2) -> bool {
+        let points = self.get_world_points(1.0);
+        let sides: Vec<f32> = (0..3).map(|i| {
+            let (start, end) = (points[i], points[(i + 1) % 3]);
+            (end - start).perp_dot(point - start)
+        }).collect();
+        sides.iter().all(|s| *s > EPSILON) || sides.iter().all(|s| *s < -EPSILON)
+    }
+
+    // The two supertiles a skinny half can belong to, each with all its children
+    fn get_parent_candidates(&self) -> [(RobinsonTriangle, Vec<RobinsonTriangle>); 2] {
+        let (x, y, z) = (self.a, self.b, self.c);
+        let far = y.times_phi().times_phi() - z.times_phi();
+
+        // The skinny half is the corner of a skinny supertile at its old b corner...
+        let skinny_parent = RobinsonTriangle::new(false, far, z, x);
+        let skinny_children = vec![*self, RobinsonTriangle::new(true, y, x, far)];
+
+        // ...or the middle of a fat one, cut off by a line between its legs
+        let c = x.times_phi() - far.over_phi();
+        let fat_parent = RobinsonTriangle::new(true, z, far, c);
+        let fat_children = vec![RobinsonTriangle::new(true, x, c, z), RobinsonTriangle::new(true, y, x, far), *self];
+
+        [(skinny_parent, skinny_children), (fat_parent, fat_children)]
+    }
+
+    // The skinny half this fat half would sit next to in either supertile, the inverse of the far corner above
+    fn get_skinny_neighbour(&self) -> RobinsonTriangle {
+        let (y, x, far) = (self.a, self.b, self.c);
+        RobinsonTriangle::new(false, x, y, y.times_phi() - far.over_phi())
+    }
+}
+
+impl fmt::Display for RobinsonTriangle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} half", if self.fat { "fat" } else { "skinny" })
+    }
+}
+
+pub struct Supertile {
+    pub triangle: RobinsonTriangle,
+    // Index into the level above, if this one could be composed further
+    pub parent: Option<usize>,
+    // Whether all the children are in the patch, not just enough of them to place it
+    pub complete: bool
+}
+
+pub enum CompositionFailure {
+    // Neither supertile the skinny half could belong to fits with the triangles around it
+    NoParent { level: usize, triangle: RobinsonTriangle },
+    // The triangle ends up in two supertiles at once
+    Overlap { level: usize, triangle: RobinsonTriangle }
+}
+
+impl CompositionFailure {
+    pub fn get_level(&self) -> usize {
+        match self {
+            CompositionFailure::NoParent { level, .. } | CompositionFailure::Overlap { level, .. } => *level
+        }
+    }
+
+    pub fn get_triangle(&self) -> &RobinsonTriangle {
+        match self {
+            CompositionFailure::NoParent { triangle, .. } | CompositionFailure::Overlap { triangle, .. } => triangle
+        }
+    }
+}
+
+impl fmt::Display for CompositionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompositionFailure::NoParent { level, triangle } =>
+                write!(f, "level {}: no supertile fits the {}", level, triangle),
+            CompositionFailure::Overlap { level, triangle } =>
+                write!(f, "level {}: the {} is in two supertiles", level, triangle)
+        }
+    }
+}
+
+// Triangles bucketed by the cells their bounding boxes cover, to find what lies under a point
+struct TriangleGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>
+}
+
+impl TriangleGrid {
+    fn new(triangles: &[RobinsonTriangle]) -> Self {
+        let cell_size = triangles.first().map_or(1.0, |t| (t.b - t.a).to_world(1.0).length());
+        let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, triangle) in triangles.iter().enumerate() {
+            let points = triangle.get_world_points(1.0);
+            let min = points[0].min(points[1]).min(points[2]) / cell_size;
+            let max = points[0].max(points[1]).max(points[2]) / cell_size;
+            for x in (min.x.floor() as i32)..=(max.x.floor() as i32) {
+                for y in (min.y.floor() as i32)..=(max.y.floor() as i32) {
+                    cells.entry((x, y)).or_default().push(i);
+                }
+            }
+        }
+        TriangleGrid {
+            cell_size,
+            cells
+        }
+    }
+
+    fn covers(&self, triangles: &[RobinsonTriangle], point: Vec2) -> bool {
+        let cell = ((point.x / self.cell_size).floor() as i32, (point.y / self.cell_size).floor() as i32);
+        self.cells.get(&cell).is_some_and(|indices| indices.iter().any(|i| triangles[*i].contains(point)))
+    }
+}
+
+pub struct Composition {
+    // The halves of the tiles first, then each level of supertiles
+    pub levels: Vec<Vec<Supertile>>,
+    // Each tile's halves in the first level
+    pub tiles: HashMap<Entity, [usize; 2]>,
+    pub failures: Vec<CompositionFailure>,
+    // For every triangle in every level, the rhomb it is half of, numbered from 0 within the level
+    rhombs: Vec<Vec<usize>>,
+    leg_len: f32
+}
+
+impl Composition {
+    pub fn new(tiling: &Tiling<RhombAngles, Rhombus>) -> Self {
+        let mut halves = Vec::new();
+        let mut tiles = HashMap::new();
+        let mut leg_len = 1.0;
+        for (entity, tile) in tiling.get_tiles() {
+            leg_len = tile.leg_len;
+            tiles.insert(*entity, [halves.len(), halves.len() + 1]);
+            for triangle in RobinsonTriangle::split_rhombus(tile).iter() {
+                halves.push(Supertile {
+                    triangle: *triangle,
+                    parent: None,
+                    complete: true
+                });
+            }
+        }
+
+        let mut composition = Composition {
+            levels: vec![halves],
+            tiles,
+            failures: Vec::new(),
+            rhombs: Vec::new(),
+            leg_len
+        };
+        while composition.levels.len() <= MAX_LEVELS {
+            let level = composition.levels.len() - 1;
+            let triangles: Vec<RobinsonTriangle> = composition.levels[level].iter().map(|s| s.triangle).collect();
+            let (parents, parent_indices) = compose_level(level, &triangles, &mut composition.failures);
+            if parents.is_empty() || composition.failures.iter().any(|failure| failure.get_level() == level) {
+                break;
+            }
+            for (supertile, parent) in composition.levels[level].iter_mut().zip(parent_indices) {
+                supertile.parent = parent;
+            }
+            composition.levels.push(parents);
+        }
+        composition.rhombs = composition.levels.iter().map(|level| pair_halves(level)).collect();
+        composition
+    }
+
+    // Levels above the tiles that were composed without a failure
+    pub fn get_depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    // The supertile rhombs the tile lies in at each level above it, two where it straddles a supertile edge
+    pub fn get_label(&self, entity: Entity) -> Vec<Vec<usize>> {
+        let mut label = Vec::new();
+        let mut indices: Vec<usize> = self.tiles.get(&entity).map_or(Vec::new(), |halves| halves.to_vec());
+        for level in 1..self.levels.len() {
+            indices = indices.iter().filter_map(|i| self.levels[level - 1][*i].parent).collect();
+            if indices.is_empty() {
+                break;
+            }
+            let mut rhombs: Vec<usize> = indices.iter().map(|i| self.rhombs[level][*i]).collect();
+            rhombs.sort();
+            rhombs.dedup();
+            label.push(rhombs);
+        }
+        label
+    }
+
+    // Outlines of the supertile rhombs at a level. A rhomb with only one half composed is drawn open along
+    // the diagonal.
+    pub fn get_outlines(&self, level: usize) -> Vec<(Vec<Vec2>, bool)> {
+        let mut halves: Vec<Vec<&RobinsonTriangle>> = Vec::new();
+        if let (Some(supertiles), Some(rhombs)) = (self.levels.get(level), self.rhombs.get(level)) {
+            for (supertile, rhomb) in supertiles.iter().zip(rhombs) {
+                if halves.len() <= *rhomb {
+                    halves.resize(*rhomb + 1, Vec::new());
+                }
+                halves[*rhomb].push(&supertile.triangle);
+            }
+        }
+
+        halves.iter().map(|triangles| {
+            let first = triangles[0];
+            let points = match triangles.get(1) {
+                Some(second) => vec![first.a, first.b, second.a, first.c],
+                None => vec![first.b, first.a, first.c]
+            };
+            (points.iter().map(|p| p.to_world(self.leg_len)).collect(), triangles.len() > 1)
+        }).collect()
+    }
+
+    // The entity of the tile a failure at the bottom level is in
+    fn find_tile(&self, triangle: &RobinsonTriangle) -> Option<Entity> {
+        self.tiles.iter()
+            .find(|(_, halves)| halves.iter().any(|i| self.levels[0][*i].triangle == *triangle))
+            .map(|(entity, _)| *entity)
+    }
+
+    pub fn print_report(&self) {
+        println!("Composition of {} tiles:", self.tiles.len());
+        for (level, supertiles) in self.levels.iter().enumerate().skip(1) {
+            let complete = supertiles.iter().filter(|s| s.complete).count();
+            let loose = self.levels[level - 1].iter().filter(|s| s.parent.is_none()).count();
+            println!("  level {}: {} supertiles, {} complete, {} triangles below left over at the edge",
+                level, supertiles.len(), complete, loose);
+        }
+        if self.failures.is_empty() {
+            println!("  composes {} levels up, as far as the patch allows", self.get_depth());
+        } else {
+            self.print_failures();
+        }
+    }
+
+    pub fn print_labels(&self) {
+        let mut entities: Vec<&Entity> = self.tiles.keys().collect();
+        entities.sort_by_key(|entity| entity.id());
+        for entity in entities {
+            let levels: Vec<String> = self.get_label(*entity).iter().enumerate().map(|(level, rhombs)| {
+                let rhombs: Vec<String> = rhombs.iter().map(|rhomb| rhomb.to_string()).collect();
+                format!("{}:{}", level + 1, rhombs.join("/"))
+            }).collect();
+            if levels.is_empty() {
+                println!("  {:?} is in no supertile, out at the edge", entity);
+            } else {
+                println!("  {:?} in supertiles {}", entity, levels.join(" "));
+            }
+        }
+    }
+
+    fn print_failures(&self) {
+        println!("  fails, the patch is not part of any Penrose tiling:");
+        for failure in &self.failures {
+            let centre = failure.get_triangle().get_centre(self.leg_len);
+            match self.find_tile(failure.get_triangle()).filter(|_| failure.get_level() == 0) {
+                Some(entity) => println!("    {} at ({:.0}, {:.0}) in {:?}", failure, centre.x, centre.y, entity),
+                None => println!("    {} at ({:.0}, {:.0})", failure, centre.x, centre.y)
+            }
+        }
+    }
+}
+
+// Draws the supertile outlines for one level over the tiles, level 0 shows none
+#[derive(Default)]
+pub struct SupertileOverlay {
+    level: usize,
+    outlines: Vec<Entity>
+}
+
+impl SupertileOverlay {
+    pub fn get_level(&self) -> usize {
+        self.level
+    }
+
+    pub fn next_level(&mut self, tiling: &Tiling<RhombAngles, Rhombus>, commands: &mut Commands) {
+        self.level += 1;
+        self.update(tiling, commands);
+    }
+
+    pub fn previous_level(&mut self, tiling: &Tiling<RhombAngles, Rhombus>, commands: &mut Commands) {
+        self.level = self.level.saturating_sub(1);
+        self.update(tiling, commands);
+    }
+
+    // Composes the tiling again, stepping the level down if the patch no longer reaches it
+    pub fn update(&mut self, tiling: &Tiling<RhombAngles, Rhombus>, commands: &mut Commands) {
+        self.clear(commands);
+        if self.level == 0 {
+            return;
+        }
+
+        let composition = Composition::new(tiling);
+        self.level = self.level.min(composition.get_depth());
+        let color = Color::hsl(60.0 * self.level as f32, 0.8, 0.4);
+        for (points, closed) in composition.get_outlines(self.level) {
+            let mut entity = commands.spawn();
+            entity.insert_bundle(
+                GeometryBuilder::build_as(
+                    &shapes::Polygon {
+                        points,
+                        closed
+                    },
+                    ShapeColors::new(color),
+                    DrawMode::Stroke(StrokeOptions::default().with_line_width(OUTLINE_WIDTH)),
+                    Transform::from_xyz(0.0, 0.0, 2.5)
+                )
+            );
+            self.outlines.push(entity.id());
+        }
+    }
+
+    pub fn clear(&mut self, commands: &mut Commands) {
+        for entity in self.outlines.drain(..) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Numbers the rhombs of a level, the two halves of one share their diagonal
+fn pair_halves(level: &[Supertile]) -> Vec<usize> {
+    let mut rhombs = HashMap::new();
+    level.iter().map(|supertile| {
+        let next = rhombs.len();
+        *rhombs.entry((supertile.triangle.b, supertile.triangle.c)).or_insert(next)
+    }).collect()
+}
+
+// Both supertiles can hold the skinny half and its fat neighbour across the leg, they differ in whether the fat
+// half across the base goes with them. That half can't if it already belongs with its own skinny neighbour,
+// and has to if it can't, no other supertile could hold it.
+fn resolve_candidates(triangle: &RobinsonTriangle, indices: &HashMap<RobinsonTriangle, usize>, grid: &TriangleGrid,
+    triangles: &[RobinsonTriangle]) -> Option<usize> {
+    let (_, fat_children) = &triangle.get_parent_candidates()[1];
+    let across_base = fat_children[0];
+    if !indices.contains_key(&across_base) {
+        return None;
+    }
+    let neighbour = across_base.get_skinny_neighbour();
+    if indices.contains_key(&neighbour) {
+        Some(0)
+    } else if grid.covers(triangles, neighbour.get_centre(1.0)) {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+// The supertiles one level up, and the index of each triangle's supertile
+fn compose_level(level: usize, triangles: &[RobinsonTriangle], failures: &mut Vec<CompositionFailure>) -> (Vec<Supertile>, Vec<Option<usize>>) {
+    let indices: HashMap<RobinsonTriangle, usize> = triangles.iter().enumerate().map(|(i, t)| (*t, i)).collect();
+    let grid = TriangleGrid::new(triangles);
+
+    let mut parents = Vec::new();
+    let mut parent_indices = vec![None; triangles.len()];
+    for triangle in triangles.iter().filter(|t| !t.fat) {
+        let mut candidates: Vec<(RobinsonTriangle, Vec<RobinsonTriangle>)> = triangle.get_parent_candidates().iter()
+            .filter(|(_, children)| children.iter().all(|child| {
+                indices.contains_key(child) || !grid.covers(triangles, child.get_centre(1.0))
+            }))
+            .cloned()
+            .collect();
+        let (parent, children) = match candidates.len() {
+            0 => {
+                failures.push(CompositionFailure::NoParent { level, triangle: *triangle });
+                continue;
+            },
+            1 => candidates.remove(0),
+            _ => match resolve_candidates(triangle, &indices, &grid, triangles) {
+                Some(i) => candidates.remove(i),
+                // Out at the edge of the patch both can still fit
+                None => continue
+            }
+        };
+
+        let index = parents.len();
+        let mut complete = true;
+        for child in &children {
+            match indices.get(child) {
+                Some(i) if parent_indices[*i].is_some() => failures.push(CompositionFailure::Overlap { level, triangle: *child }),
+                Some(i) => parent_indices[*i] = Some(index),
+                None => complete = false
+            }
+        }
+        parents.push(Supertile {
+            triangle: parent,
+            parent: None,
+            complete
+        });
+    }
+    (parents, parent_indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry;
+    use crate::growth::TypeWeights;
+    use crate::seeds::SeedLibrary;
+
+    fn compose_seed(name: &str) -> Composition {
+        let tiles = SeedLibrary::new(name).unwrap().build(&TypeWeights::default()).unwrap();
+        let mut tiling = Tiling::<RhombAngles, Rhombus>::default();
+        for (i, tile) in tiles.into_iter().enumerate() {
+            tiling.place(tile, Entity::new(i as u32)).unwrap();
+        }
+        Composition::new(&tiling)
+    }
+
+    fn get_area(triangle: &RobinsonTriangle) -> f32 {
+        geometry::signed_area(&triangle.get_world_points(1.0)).abs()
+    }
+
+    // The halves of a skinny rhomb, one in each of its two possible supertiles
+    #[test]
+    fn parent_candidates_are_covered_by_their_children() {
+        let skinny = RobinsonTriangle::split_rhombus(&Rhombus::new_skinny());
+        for half in skinny.iter() {
+            for (parent, children) in half.get_parent_candidates().iter() {
+                assert!(children.contains(half));
+                let area: f32 = children.iter().map(get_area).sum();
+                assert!((area - get_area(parent)).abs() < 0.001);
+                assert!(children.iter().all(|child| parent.contains(child.get_centre(1.0))));
+                let neighbour = children.iter().find(|child| child.fat && child.a == half.b).unwrap();
+                assert_eq!(neighbour.get_skinny_neighbour(), *half);
+            }
+        }
+    }
+
+    #[test]
+    fn legal_seeds_compose() {
+        for name in &["sun", "star", "s3", "s4", "jack", "queen", "king", "deuce", "decapod-0"] {
+            let composition = compose_seed(name);
+            assert!(composition.failures.is_empty(), "{} failed to compose", name);
+        }
+        assert!(compose_seed("cartwheel").get_depth() >= 2);
+    }
+
+    // A decagon tiling that has to break a matching rule can't be part of any Penrose tiling
+    #[test]
+    fn broken_decapod_fails_to_compose() {
+        let composition = compose_seed("decapod-1");
+        assert!(!composition.failures.is_empty());
+        assert!(composition.failures.iter().all(|failure| failure.get_level() == 0));
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+// A half-edge mesh (doubly connected edge list) over exact vertex keys. Faces are added and removed one at
+// a time as tiles are placed and undone, and adjacency, vertex fans and boundary loops are then answered
+// by following pointers instead of searching geometry.
+//
+// Half-edges come in twin pairs stored next to each other, so the twin of h is h ^ 1. A half-edge without
+// a face is on the boundary, and boundary half-edges are linked with next/prev into loops just like the
+// half-edges of a face. Every vertex keeps an outgoing half-edge, a boundary one if it has any.
+// All faces have to be added with the same winding.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VertexId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct HalfEdgeId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FaceId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MeshError {
+    // Fewer than three vertices, or the same vertex twice
+    DegenerateFace,
+    // The vertex is already surrounded by faces
+    ComplexVertex,
+    // The half-edge already belongs to a face, the new face would overlap it
+    ComplexEdge,
+    // The faces around a vertex can't be reordered to make room for the new one
+    PatchRelinkFailed,
+    UnknownFace
+}
+
+struct Vertex<K> {
+    key: K,
+    half_edge: Option<HalfEdgeId>
+}
+
+#[derive(Clone, Copy)]
+struct HalfEdge {
+    // The vertex this half-edge points to
+    to: VertexId,
+    next: HalfEdgeId,
+    prev: HalfEdgeId,
+    face: Option<FaceId>
+}
+
+struct Face<F> {
+    data: F,
+    // The half-edge leaving the first vertex the face was added with
+    half_edge: HalfEdgeId
+}
+
+pub struct HalfEdgeMesh<K, F> {
+    vertices: Vec<Option<Vertex<K>>>,
+    half_edges: Vec<Option<HalfEdge>>,
+    faces: Vec<Option<Face<F>>>,
+    vertex_lookup: HashMap<K, VertexId>,
+    half_edge_lookup: HashMap<(VertexId, VertexId), HalfEdgeId>,
+    free_vertices: Vec<VertexId>,
+    free_edges: Vec<usize>,
+    free_faces: Vec<FaceId>
+}
+
+impl<K: Copy + Eq + Hash + Debug, F: Copy> Default for HalfEdgeMesh<K, F> {
+    fn default() -> Self {
+        HalfEdgeMesh {
+            vertices: Vec::new(),
+            half_edges: Vec::new(),
+            faces: Vec::new(),
+            vertex_lookup: HashMap::new(),
+            half_edge_lookup: HashMap::new(),
+            free_vertices: Vec::new(),
+            free_edges: Vec::new(),
+            free_faces: Vec::new()
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash + Debug, F: Copy> HalfEdgeMesh<K, F> {
+    pub fn twin(h: HalfEdgeId) -> HalfEdgeId {
+        HalfEdgeId(h.0 ^ 1)
+    }
+
+    fn half_edge(&self, h: HalfEdgeId) -> &HalfEdge {
+        self.half_edges[h.0].as_ref().unwrap()
+    }
+
+    fn half_edge_mut(&mut self, h: HalfEdgeId) -> &mut HalfEdge {
+        self.half_edges[h.0].as_mut().unwrap()
+    }
+
+    fn vertex(&self, v: VertexId) -> &Vertex<K> {
+        self.vertices[v.0].as_ref().unwrap()
+    }
+
+    fn vertex_mut(&mut self, v: VertexId) -> &mut Vertex<K> {
+        self.vertices[v.0].as_mut().unwrap()
+    }
+
+    pub fn next(&self, h: HalfEdgeId) -> HalfEdgeId {
+        self.half_edge(h).next
+    }
+
+    pub fn prev(&self, h: HalfEdgeId) -> HalfEdgeId {
+        self.half_edge(h).prev
+    }
+
+    pub fn get_face(&self, h: HalfEdgeId) -> Option<FaceId> {
+        self.half_edge(h).face
+    }
+
+    pub fn is_boundary(&self, h: HalfEdgeId) -> bool {
+        self.half_edge(h).face.is_none()
+    }
+
+    pub fn get_target(&self, h: HalfEdgeId) -> VertexId {
+        self.half_edge(h).to
+    }
+
+    pub fn get_origin(&self, h: HalfEdgeId) -> VertexId {
+        self.half_edge(HalfEdgeMesh::<K, F>::twin(h)).to
+    }
+
+    pub fn get_key(&self, v: VertexId) -> K {
+        self.vertex(v).key
+    }
+
+    pub fn find_vertex(&self, key: &K) -> Option<VertexId> {
+        self.vertex_lookup.get(key).copied()
+    }
+
+    pub fn find_half_edge(&self, from: &K, to: &K) -> Option<HalfEdgeId> {
+        let from = self.find_vertex(from)?;
+        let to = self.find_vertex(to)?;
+        self.half_edge_lookup.get(&(from, to)).copied()
+    }
+
+    pub fn get_face_data(&self, f: FaceId) -> Option<&F> {
+        self.faces.get(f.0).and_then(|face| face.as_ref()).map(|face| &face.data)
+    }
+
+    pub fn get_num_vertices(&self) -> usize {
+        self.vertex_lookup.len()
+    }
+
+    pub fn get_num_faces(&self) -> usize {
+        self.faces.len() - self.free_faces.len()
+    }
+
+    fn is_boundary_vertex(&self, v: VertexId) -> bool {
+        match self.vertex(v).half_edge {
+            Some(h) => self.is_boundary(h),
+            None => true
+        }
+    }
+
+    fn set_next(&mut self, h: HalfEdgeId, next: HalfEdgeId) {
+        self.half_edge_mut(h).next = next;
+        self.half_edge_mut(next).prev = h;
+    }
+
+    fn get_or_add_vertex(&mut self, key: K) -> VertexId {
+        if let Some(v) = self.find_vertex(&key) {
+            return v;
+        }
+
+        let vertex = Vertex { key, half_edge: None };
+        let v = match self.free_vertices.pop() {
+            Some(v) => {
+                self.vertices[v.0] = Some(vertex);
+                v
+            },
+            None => {
+                self.vertices.push(Some(vertex));
+                VertexId(self.vertices.len() - 1)
+            }
+        };
+        self.vertex_lookup.insert(key, v);
+        v
+    }
+
+    // A new unlinked twin pair, returns the half-edge from -> to
+    fn add_edge(&mut self, from: VertexId, to: VertexId) -> HalfEdgeId {
+        let edge = match self.free_edges.pop() {
+            Some(edge) => edge,
+            None => {
+                self.half_edges.push(None);
+                self.half_edges.push(None);
+                self.half_edges.len() / 2 - 1
+            }
+        };
+
+        let h = HalfEdgeId(2 * edge);
+        let t = HalfEdgeId(2 * edge + 1);
+        self.half_edges[h.0] = Some(HalfEdge { to, next: h, prev: h, face: None });
+        self.half_edges[t.0] = Some(HalfEdge { to: from, next: t, prev: t, face: None });
+        self.half_edge_lookup.insert((from, to), h);
+        self.half_edge_lookup.insert((to, from), t);
+        h
+    }
+
+    fn remove_edge(&mut self, h: HalfEdgeId) {
+        let t = HalfEdgeMesh::<K, F>::twin(h);
+        let (from, to) = (self.get_origin(h), self.get_target(h));
+        self.half_edge_lookup.remove(&(from, to));
+        self.half_edge_lookup.remove(&(to, from));
+        self.half_edges[h.0] = None;
+        self.half_edges[t.0] = None;
+        self.free_edges.push(h.0 / 2);
+    }
+
+    // Prefer a boundary half-edge as the vertex's outgoing one, so boundary checks stay O(1)
+    fn adjust_outgoing_half_edge(&mut self, v: VertexId) {
+        let outgoing = self.get_outgoing_half_edges(v);
+        if let Some(h) = outgoing.into_iter().find(|h| self.is_boundary(*h)) {
+            self.vertex_mut(v).half_edge = Some(h);
+        }
+    }
+
+    pub fn add_face(&mut self, keys: &[K], data: F) -> Result<FaceId, MeshError> {
+        let n = keys.len();
+        if n < 3 {
+            return Err(MeshError::DegenerateFace);
+        }
+        for i in 0..n {
+            if keys[(i + 1)..].contains(&keys[i]) {
+                return Err(MeshError::DegenerateFace);
+            }
+        }
+
+        // Check everything before changing anything, so a failed add leaves the mesh as it was
+        let vertices: Vec<Option<VertexId>> = keys.iter().map(|key| self.find_vertex(key)).collect();
+        let mut existing: Vec<Option<HalfEdgeId>> = Vec::with_capacity(n);
+        for i in 0..n {
+            if let Some(v) = vertices[i] {
+                if !self.is_boundary_vertex(v) {
+                    return Err(MeshError::ComplexVertex);
+                }
+            }
+            let h = match (vertices[i], vertices[(i + 1) % n]) {
+                (Some(from), Some(to)) => self.half_edge_lookup.get(&(from, to)).copied(),
+                _ => None
+            };
+            if let Some(h) = h {
+                if !self.is_boundary(h) {
+                    return Err(MeshError::ComplexEdge);
+                }
+            }
+            existing.push(h);
+        }
+
+        // Where the new face joins two existing half-edges that aren't consecutive around their vertex,
+        // the patch between them has to be moved to another gap in the vertex's fan
+        let mut next_cache: Vec<(HalfEdgeId, HalfEdgeId)> = Vec::new();
+        for i in 0..n {
+            let ii = (i + 1) % n;
+            if let (Some(inner_prev), Some(inner_next)) = (existing[i], existing[ii]) {
+                if self.next(inner_prev) != inner_next {
+                    let outer_prev = HalfEdgeMesh::<K, F>::twin(inner_next);
+                    let mut boundary_prev = outer_prev;
+                    loop {
+                        boundary_prev = HalfEdgeMesh::<K, F>::twin(self.next(boundary_prev));
+                        if self.is_boundary(boundary_prev) && boundary_prev != inner_prev {
+                            break;
+                        }
+                        if boundary_prev == outer_prev {
+                            return Err(MeshError::PatchRelinkFailed);
+                        }
+                    }
+                    let boundary_next = self.next(boundary_prev);
+                    if boundary_next == inner_next {
+                        return Err(MeshError::PatchRelinkFailed);
+                    }
+
+                    let patch_start = self.next(inner_prev);
+                    let patch_end = self.prev(inner_next);
+                    next_cache.push((boundary_prev, patch_start));
+                    next_cache.push((patch_end, boundary_next));
+                    next_cache.push((inner_prev, inner_next));
+                }
+            }
+        }
+
+        let vertices: Vec<VertexId> = keys.iter().map(|key| self.get_or_add_vertex(*key)).collect();
+        let is_new: Vec<bool> = existing.iter().map(|h| h.is_none()).collect();
+        let half_edges: Vec<HalfEdgeId> = (0..n).map(|i| match existing[i] {
+            Some(h) => h,
+            None => self.add_edge(vertices[i], vertices[(i + 1) % n])
+        }).collect();
+
+        let face = Face { data, half_edge: half_edges[0] };
+        let f = match self.free_faces.pop() {
+            Some(f) => {
+                self.faces[f.0] = Some(face);
+                f
+            },
+            None => {
+                self.faces.push(Some(face));
+                FaceId(self.faces.len() - 1)
+            }
+        };
+
+        let mut needs_adjust = vec![false; n];
+        for i in 0..n {
+            let ii = (i + 1) % n;
+            let v = vertices[ii];
+            let inner_prev = half_edges[i];
+            let inner_next = half_edges[ii];
+            let outer_prev = HalfEdgeMesh::<K, F>::twin(inner_next);
+            let outer_next = HalfEdgeMesh::<K, F>::twin(inner_prev);
+
+            match (is_new[i], is_new[ii]) {
+                (true, false) => {
+                    let boundary_prev = self.prev(inner_next);
+                    next_cache.push((boundary_prev, outer_next));
+                    self.vertex_mut(v).half_edge = Some(outer_next);
+                },
+                (false, true) => {
+                    let boundary_next = self.next(inner_prev);
+                    next_cache.push((outer_prev, boundary_next));
+                    self.vertex_mut(v).half_edge = Some(boundary_next);
+                },
+                (true, true) => {
+                    match self.vertex(v).half_edge {
+                        None => {
+                            self.vertex_mut(v).half_edge = Some(outer_next);
+                            next_cache.push((outer_prev, outer_next));
+                        },
+                        Some(boundary_next) => {
+                            let boundary_prev = self.prev(boundary_next);
+                            next_cache.push((boundary_prev, outer_next));
+                            next_cache.push((outer_prev, boundary_next));
+                        }
+                    }
+                },
+                (false, false) => {
+                    needs_adjust[ii] = true;
+                }
+            }
+
+            next_cache.push((inner_prev, inner_next));
+            self.half_edge_mut(inner_prev).face = Some(f);
+        }
+
+        for (h, next) in next_cache {
+            self.set_next(h, next);
+        }
+
+        for i in 0..n {
+            if needs_adjust[i] {
+                self.adjust_outgoing_half_edge(vertices[i]);
+            }
+        }
+
+        Ok(f)
+    }
+
+    pub fn remove_face(&mut self, f: FaceId) -> Result<F, MeshError> {
+        let data = match self.get_face_data(f) {
+            Some(data) => *data,
+            None => return Err(MeshError::UnknownFace)
+        };
+
+        let half_edges = self.get_face_half_edges(f);
+        let mut vertices = Vec::new();
+        let mut dead_edges = Vec::new();
+        for h in &half_edges {
+            self.half_edge_mut(*h).face = None;
+            vertices.push(self.get_target(*h));
+            if self.is_boundary(HalfEdgeMesh::<K, F>::twin(*h)) {
+                dead_edges.push(*h);
+            }
+        }
+
+        for h0 in dead_edges {
+            let h1 = HalfEdgeMesh::<K, F>::twin(h0);
+            let v0 = self.get_target(h0);
+            let v1 = self.get_target(h1);
+            let (next0, prev0) = (self.next(h0), self.prev(h0));
+            let (next1, prev1) = (self.next(h1), self.prev(h1));
+
+            self.set_next(prev0, next1);
+            self.set_next(prev1, next0);
+
+            if self.vertex(v0).half_edge == Some(h1) {
+                self.vertex_mut(v0).half_edge = if next0 == h1 { None } else { Some(next0) };
+            }
+            if self.vertex(v1).half_edge == Some(h0) {
+                self.vertex_mut(v1).half_edge = if next1 == h0 { None } else { Some(next1) };
+            }
+
+            self.remove_edge(h0);
+        }
+
+        for v in vertices {
+            if self.vertex(v).half_edge.is_none() {
+                let key = self.vertex(v).key;
+                self.vertex_lookup.remove(&key);
+                self.vertices[v.0] = None;
+                self.free_vertices.push(v);
+            } else {
+                self.adjust_outgoing_half_edge(v);
+            }
+        }
+
+        self.faces[f.0] = None;
+        self.free_faces.push(f);
+        Ok(data)
+    }
+
+    // The face's half-edges in the order its vertices were given
+    pub fn get_face_half_edges(&self, f: FaceId) -> Vec<HalfEdgeId> {
+        let start = self.faces[f.0].as_ref().unwrap().half_edge;
+        let mut half_edges = vec![start];
+        let mut h = self.next(start);
+        while h != start {
+            half_edges.push(h);
+            h = self.next(h);
+        }
+        half_edges
+    }
+
+    pub fn get_face_vertices(&self, f: FaceId) -> Vec<K> {
+        self.get_face_half_edges(f).iter().map(|h| self.get_key(self.get_origin(*h))).collect()
+    }
+
+    // Index of a half-edge within its face, matching the order the face's vertices were given
+    pub fn get_face_side(&self, h: HalfEdgeId) -> Option<usize> {
+        let f = self.get_face(h)?;
+        self.get_face_half_edges(f).iter().position(|e| *e == h)
+    }
+
+    pub fn get_outgoing_half_edges(&self, v: VertexId) -> Vec<HalfEdgeId> {
+        let mut outgoing = Vec::new();
+        if let Some(start) = self.vertex(v).half_edge {
+            let mut h = start;
+            loop {
+                outgoing.push(h);
+                h = self.next(HalfEdgeMesh::<K, F>::twin(h));
+                if h == start {
+                    break;
+                }
+            }
+        }
+        outgoing
+    }
+
+    // The faces around a vertex, in order
+    pub fn get_vertex_fan(&self, key: &K) -> Vec<FaceId> {
+        match self.find_vertex(key) {
+            Some(v) => self.get_outgoing_half_edges(v).iter().filter_map(|h| self.get_face(*h)).collect(),
+            None => Vec::new()
+        }
+    }
+
+    pub fn get_boundary_loops(&self) -> Vec<Vec<K>> {
+        let mut visited = vec![false; self.half_edges.len()];
+        let mut loops = Vec::new();
+        for i in 0..self.half_edges.len() {
+            let start = HalfEdgeId(i);
+            if visited[i] || self.half_edges[i].is_none() || !self.is_boundary(start) {
+                continue;
+            }
+
+            let mut boundary = Vec::new();
+            let mut h = start;
+            loop {
+                visited[h.0] = true;
+                boundary.push(self.get_key(self.get_origin(h)));
+                h = self.next(h);
+                if h == start {
+                    break;
+                }
+            }
+            loops.push(boundary);
+        }
+        loops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two unit squares side by side, anticlockwise, sharing the edge from (1, 0) to (1, 1)
+    fn two_squares() -> (HalfEdgeMesh<(i32, i32), u32>, FaceId, FaceId) {
+        let mut mesh = HalfEdgeMesh::default();
+        let left = mesh.add_face(&[(0, 0), (1, 0), (1, 1), (0, 1)], 0).unwrap();
+        let right = mesh.add_face(&[(1, 0), (2, 0), (2, 1), (1, 1)], 1).unwrap();
+        (mesh, left, right)
+    }
+
+    #[test]
+    fn shared_edge_links_the_faces() {
+        let (mesh, left, right) = two_squares();
+        assert_eq!(mesh.get_num_faces(), 2);
+        assert_eq!(mesh.get_num_vertices(), 6);
+
+        let h = mesh.find_half_edge(&(1, 0), &(1, 1)).unwrap();
+        assert_eq!(mesh.get_face(h), Some(left));
+        assert_eq!(mesh.get_face_side(h), Some(1));
+        let twin = HalfEdgeMesh::<(i32, i32), u32>::twin(h);
+        assert_eq!(mesh.get_face(twin), Some(right));
+        assert_eq!(mesh.get_face_side(twin), Some(3));
+        assert_eq!(mesh.get_face_vertices(right), vec![(1, 0), (2, 0), (2, 1), (1, 1)]);
+        assert_eq!(mesh.get_face_data(right), Some(&1));
+    }
+
+    #[test]
+    fn boundary_is_one_loop_round_both_faces() {
+        let (mesh, left, right) = two_squares();
+        let loops = mesh.get_boundary_loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 6);
+        assert!(!loops[0].contains(&(3, 3)));
+
+        let mut fan = mesh.get_vertex_fan(&(1, 0));
+        fan.sort();
+        assert_eq!(fan, vec![left, right]);
+        assert_eq!(mesh.get_vertex_fan(&(0, 0)), vec![left]);
+    }
+
+    #[test]
+    fn overlapping_and_degenerate_faces_are_refused() {
+        let (mut mesh, _, _) = two_squares();
+        // Uses the left square's half-edge from (0, 0) to (1, 0) the same way round
+        assert_eq!(mesh.add_face(&[(0, 0), (1, 0), (1, -1)], 2), Err(MeshError::ComplexEdge));
+        assert_eq!(mesh.add_face(&[(0, 0), (0, -1)], 2), Err(MeshError::DegenerateFace));
+        assert_eq!(mesh.add_face(&[(0, 0), (0, -1), (0, 0)], 2), Err(MeshError::DegenerateFace));
+        assert_eq!(mesh.get_num_faces(), 2);
+        assert_eq!(mesh.get_num_vertices(), 6);
+    }
+
+    #[test]
+    fn removing_a_face_frees_what_only_it_used() {
+        let (mut mesh, left, right) = two_squares();
+        assert_eq!(mesh.remove_face(right), Ok(1));
+        assert_eq!(mesh.get_num_faces(), 1);
+        assert_eq!(mesh.get_num_vertices(), 4);
+        assert!(mesh.find_vertex(&(2, 0)).is_none());
+        let h = mesh.find_half_edge(&(1, 1), &(1, 0)).unwrap();
+        assert!(mesh.is_boundary(h));
+        assert_eq!(mesh.get_boundary_loops()[0].len(), 4);
+        assert_eq!(mesh.remove_face(right), Err(MeshError::UnknownFace));
+
+        // The freed slots are reused
+        let again = mesh.add_face(&[(1, 0), (2, 0), (2, 1), (1, 1)], 3).unwrap();
+        assert_eq!(mesh.get_face_data(again), Some(&3));
+        assert_eq!(mesh.get_vertex_fan(&(1, 1)).len(), 2);
+        assert!(mesh.remove_face(left).is_ok());
+    }
+}
@@ -0,0 +1,243 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use std::collections::{HashMap, HashSet};
+
+use crate::tiling::TilingError;
+use crate::{PenroseEnum, PenroseTiler, Tile, TileTopology};
+
+// The open edges of the tiling, one for every free side of a placed tile, with the tile types that can
+// legally go on each. Each edge is also indexed by the vertices of every tile that could be placed on it,
+// whether or not that tile is currently legal, and by the vertices its corners are linked to. A tile added
+// or removed can only change the edges whose candidates touch one of its vertices, so only those are
+// recomputed.
+
+pub struct OpenEdge<P: PenroseEnum, T: Tile<P>> {
+    pub entity: Entity,
+    pub side: u8,
+    pub tile: T,
+    pub start: Vec2,
+    pub end: Vec2,
+    // Angle already filled by tiles at each end of the edge
+    pub filled_angles: (f32, f32),
+    pub legal_types: Vec<P>,
+    // When the edge was opened, counting edges opened before it. Recomputing an edge keeps its age.
+    pub age: u64,
+    candidate_vertices: Vec<T::Vertex>
+}
+
+impl<P: PenroseEnum, T: Tile<P>> OpenEdge<P, T> {
+    // Past 180 degrees the boundary turns back in, so the edge sits in a notch of the patch
+    pub fn is_concave(&self) -> bool {
+        let epsilon = 0.001;
+        self.filled_angles.0 > std::f32::consts::PI + epsilon || self.filled_angles.1 > std::f32::consts::PI + epsilon
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.legal_types.is_empty()
+    }
+
+    fn new(tile: &T, entity: Entity, side: u8, topology: &TileTopology<P, T::Vertex>) -> Result<Self, TilingError> {
+        let points = tile.get_points();
+        let transform = tile.get_transform();
+        if side as usize >= points.len() {
+            return Err(TilingError::InvalidSide(side));
+        }
+        let next = (side as usize + 1) % points.len();
+        let vertices = tile.get_vertices();
+
+        let mut candidate_vertices = Vec::new();
+        for penrose_type in tile.get_neighbour_types() {
+            let candidate = tile.get_connected_tile(side, penrose_type)?;
+            candidate_vertices.extend(candidate.get_vertices());
+            candidate_vertices.extend(candidate.get_linked_vertices().into_iter().map(|(_, vertex)| vertex));
+        }
+
+        Ok(OpenEdge {
+            entity,
+            side,
+            tile: tile.clone(),
+            start: (transform * points[side as usize].extend(0.0)).truncate(),
+            end: (transform * points[next].extend(0.0)).truncate(),
+            filled_angles: (topology.get_filled_angle(&vertices[side as usize]), topology.get_filled_angle(&vertices[next])),
+            legal_types: PenroseTiler::get_allowed_types_on_side(tile, side, topology)?,
+            age: 0,
+            candidate_vertices
+        })
+    }
+}
+
+// The edges a tile added or removed closes and the ones it opens or changes, worked out before the frontier
+// is touched so that a failure leaves it as it was
+pub struct FrontierChange<P: PenroseEnum, T: Tile<P>> {
+    closed: Vec<(Entity, u8)>,
+    opened: Vec<OpenEdge<P, T>>
+}
+
+pub struct Frontier<P: PenroseEnum, T: Tile<P>> {
+    edges: HashMap<(Entity, u8), OpenEdge<P, T>>,
+    touching: HashMap<T::Vertex, HashSet<(Entity, u8)>>,
+    num_opened: u64,
+    show_overlay: bool,
+    overlay: Vec<Entity>
+}
+
+impl<P: PenroseEnum, T: Tile<P>> Default for Frontier<P, T> {
+    fn default() -> Self {
+        Frontier {
+            edges: HashMap::new(),
+            touching: HashMap::new(),
+            num_opened: 0,
+            show_overlay: false,
+            overlay: Vec::new()
+        }
+    }
+}
+
+impl<P: PenroseEnum, T: Tile<P>> Frontier<P, T> {
+    pub fn get_open_edges(&self) -> impl Iterator<Item = &OpenEdge<P, T>> {
+        self.edges.values()
+    }
+
+    pub fn is_open(&self, entity: Entity, side: u8) -> bool {
+        self.edges.contains_key(&(entity, side))
+    }
+
+    // Indexes an edge by its candidates, replacing the edge on the same side if there is one
+    fn insert(&mut self, mut edge: OpenEdge<P, T>) {
+        let key = (edge.entity, edge.side);
+        edge.age = match self.edges.get(&key) {
+            Some(existing) => existing.age,
+            None => {
+                self.num_opened += 1;
+                self.num_opened
+            }
+        };
+        self.close(key.0, key.1);
+        for vertex in &edge.candidate_vertices {
+            self.touching.entry(*vertex).or_default().insert(key);
+        }
+        self.edges.insert(key, edge);
+    }
+
+    pub fn close(&mut self, entity: Entity, side: u8) {
+        if let Some(edge) = self.edges.remove(&(entity, side)) {
+            for vertex in &edge.candidate_vertices {
+                if let Some(keys) = self.touching.get_mut(vertex) {
+                    keys.remove(&(entity, side));
+                    if keys.is_empty() {
+                        self.touching.remove(vertex);
+                    }
+                }
+            }
+        }
+    }
+
+    // Recompute every open edge that has a candidate touching one of the vertices, other than those closing
+    fn refresh_around(&self, vertices: &[T::Vertex], closed: &[(Entity, u8)], topology: &TileTopology<P, T::Vertex>)
+        -> Result<Vec<OpenEdge<P, T>>, TilingError> {
+        let mut keys = HashSet::new();
+        for vertex in vertices {
+            if let Some(touching) = self.touching.get(vertex) {
+                keys.extend(touching.iter().copied().filter(|key| !closed.contains(key)));
+            }
+        }
+
+        keys.into_iter().map(|(entity, side)| OpenEdge::new(&self.edges[&(entity, side)].tile, entity, side, topology)).collect()
+    }
+
+    // Call after the tile has been added to the topology
+    pub fn add_tile(&self, tile: &T, entity: Entity, topology: &TileTopology<P, T::Vertex>) -> Result<FrontierChange<P, T>, TilingError> {
+        let neighbours = topology.get_neighbours(entity);
+        let mut change = FrontierChange {
+            closed: Vec::new(),
+            opened: Vec::new()
+        };
+        for side in 0..tile.get_side_count() {
+            let side = side as u8;
+            match neighbours.iter().find(|(s, _)| *s == side) {
+                Some((_, data)) => change.closed.push((data.entity, data.side)),
+                None => change.opened.push(OpenEdge::new(tile, entity, side, topology)?)
+            }
+        }
+        change.opened.extend(self.refresh_around(&tile.get_vertices(), &change.closed, topology)?);
+        Ok(change)
+    }
+
+    // Call after the tile has been removed from the topology, with the sides of its neighbours that it
+    // covered, already marked free
+    pub fn remove_tile(&self, tile: &T, entity: Entity, uncovered: &[(T, Entity, u8)], topology: &TileTopology<P, T::Vertex>)
+        -> Result<FrontierChange<P, T>, TilingError> {
+        let closed: Vec<(Entity, u8)> = (0..tile.get_side_count()).map(|side| (entity, side as u8)).collect();
+        let mut opened = self.refresh_around(&tile.get_vertices(), &closed, topology)?;
+        for (neighbour, neighbour_entity, side) in uncovered {
+            opened.push(OpenEdge::new(neighbour, *neighbour_entity, *side, topology)?);
+        }
+        Ok(FrontierChange {
+            closed,
+            opened
+        })
+    }
+
+    // Can't fail, everything that could was done working out the change
+    pub fn commit(&mut self, change: FrontierChange<P, T>) {
+        for (entity, side) in change.closed {
+            self.close(entity, side);
+        }
+        for edge in change.opened {
+            self.insert(edge);
+        }
+    }
+
+    pub fn toggle_overlay(&mut self, commands: &mut Commands) {
+        self.show_overlay = !self.show_overlay;
+        self.update_overlay(commands);
+    }
+
+    // Open edges drawn on top of the tiles: green if convex, orange if concave, red if nothing fits
+    pub fn update_overlay(&mut self, commands: &mut Commands) {
+        self.clear_overlay(commands);
+        if !self.show_overlay {
+            return;
+        }
+
+        for edge in self.edges.values() {
+            let color = if edge.is_dead() {
+                Color::RED
+            } else if edge.is_concave() {
+                Color::ORANGE
+            } else {
+                Color::GREEN
+            };
+            let mut entity = commands.spawn();
+            entity.insert_bundle(
+                GeometryBuilder::build_as(
+                    &shapes::Polygon {
+                        points: vec![edge.start, edge.end],
+                        closed: false
+                    },
+                    ShapeColors::new(color),
+                    DrawMode::Stroke(StrokeOptions::default().with_line_width(4.0)),
+                    Transform::from_xyz(0.0, 0.0, 2.0)
+                )
+            );
+            self.overlay.push(entity.id());
+        }
+    }
+
+    pub fn clear_overlay(&mut self, commands: &mut Commands) {
+        for entity in self.overlay.drain(..) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    pub fn print(&self) {
+        let concave = self.edges.values().filter(|edge| edge.is_concave()).count();
+        let dead = self.edges.values().filter(|edge| edge.is_dead()).count();
+        println!("Frontier: {} open edges, {} concave, {} with no legal tile", self.edges.len(), concave, dead);
+        for edge in self.edges.values() {
+            let types: Vec<String> = edge.legal_types.iter().map(|t| t.to_string()).collect();
+            println!("  {:?} side {}: filled {:.0}°/{:.0}° legal [{}]", edge.entity, edge.side,
+                edge.filled_angles.0.to_degrees(), edge.filled_angles.1.to_degrees(), types.join(", "));
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use bevy::math::Vec2;
+
+// Overlaps smaller than this are treated as tiles touching along a shared edge
+const OVERLAP_EPSILON: f32 = 0.004;
+
+pub fn signed_area(points: &[Vec2]) -> f32 {
+    let mut area = 0.0;
+    for i in 0..points.len() {
+        let j = (i + 1) % points.len();
+        area += points[i].x * points[j].y - points[j].x * points[i].y;
+    }
+    area / 2.0
+}
+
+fn cross(o: Vec2, a: Vec2, b: Vec2) -> f32 {
+    (a - o).perp_dot(b - o)
+}
+
+fn point_in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+}
+
+// Ear clipping triangulation of a simple polygon, convex or not, in either winding order.
+// Triangles are returned counter-clockwise.
+pub fn triangulate(points: &[Vec2]) -> Vec<[Vec2; 3]> {
+    if points.len() < 3 {
+        return Vec::new();
+    }
+
+    let mut indices: Vec<usize> = (0..points.len()).collect();
+    if signed_area(points) < 0.0 {
+        indices.reverse();
+    }
+
+    let mut triangles = Vec::new();
+    while indices.len() > 3 {
+        let n = indices.len();
+        let mut clipped = false;
+        for i in 0..n {
+            let a = points[indices[(i + n - 1) % n]];
+            let b = points[indices[i]];
+            let c = points[indices[(i + 1) % n]];
+            if cross(a, b, c) <= 0.0 {
+                // Reflex (or degenerate) corner, can't be an ear
+                continue;
+            }
+
+            let contains_other = indices.iter().any(|&k| {
+                let p = points[k];
+                p != a && p != b && p != c && point_in_triangle(p, a, b, c)
+            });
+            if contains_other {
+                continue;
+            }
+
+            triangles.push([a, b, c]);
+            indices.remove(i);
+            clipped = true;
+            break;
+        }
+
+        if !clipped {
+            // Only happens for self intersecting input, fall back to a fan so we still cover the shape
+            break;
+        }
+    }
+
+    for i in 1..(indices.len() - 1) {
+        triangles.push([points[indices[0]], points[indices[i]], points[indices[i + 1]]]);
+    }
+
+    triangles
+}
+
+fn is_separating_axis(normal: Vec2, points_a: &[Vec2], points_b: &[Vec2]) -> bool {
+    let mut min_a = f32::INFINITY;
+    let mut max_a = f32::NEG_INFINITY;
+    let mut min_b = f32::INFINITY;
+    let mut max_b = f32::NEG_INFINITY;
+
+    for v in points_a {
+        let projection = v.dot(normal);
+        min_a = min_a.min(projection);
+        max_a = max_a.max(projection);
+    }
+
+    for v in points_b {
+        let projection = v.dot(normal);
+        min_b = min_b.min(projection);
+        max_b = max_b.max(projection);
+    }
+
+    let overlap = max_a - min_b > OVERLAP_EPSILON && max_b - min_a > OVERLAP_EPSILON;
+    !overlap
+}
+
+pub fn convex_polygons_overlap(points_a: &[Vec2], points_b: &[Vec2]) -> bool {
+    for points in [points_a, points_b].iter() {
+        for i in 0..points.len() {
+            let edge = points[(i + 1) % points.len()] - points[i];
+            if edge.length_squared() == 0.0 {
+                continue;
+            }
+            if is_separating_axis(edge.normalize().perp(), points_a, points_b) {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+// Whether a point lies inside a simple polygon, convex or not, in either winding order
+pub fn contains_point(points: &[Vec2], p: Vec2) -> bool {
+    triangulate(points).iter().any(|t| point_in_triangle(p, t[0], t[1], t[2]))
+}
+
+// No reflex corners. Straight corners, where a side has been split, are allowed.
+pub fn is_convex(points: &[Vec2]) -> bool {
+    let n = points.len();
+    let turns: Vec<f32> = (0..n).map(|i| cross(points[(i + n - 1) % n], points[i], points[(i + 1) % n])).collect();
+    turns.iter().all(|turn| *turn >= 0.0) || turns.iter().all(|turn| *turn <= 0.0)
+}
+
+// Overlap test for arbitrary simple polygons: decompose both into triangles and look for any pair
+// of triangles that overlap by more than OVERLAP_EPSILON. Tiles sharing an edge don't count. Convex
+// polygons are tested whole.
+pub fn polygons_overlap(points_a: &[Vec2], points_b: &[Vec2]) -> bool {
+    if is_convex(points_a) && is_convex(points_b) {
+        return convex_polygons_overlap(points_a, points_b);
+    }
+
+    let triangles_a = triangulate(points_a);
+    let triangles_b = triangulate(points_b);
+    triangles_a.iter().any(|ta| {
+        triangles_b.iter().any(|tb| convex_polygons_overlap(ta, tb))
+    })
+}
+
+// Anticlockwise hull of a set of points, without points in the middle of its sides
+pub fn convex_hull(points: &[Vec2]) -> Vec<Vec2> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(std::cmp::Ordering::Equal)
+        .then(a.y.partial_cmp(&b.y).unwrap_or(std::cmp::Ordering::Equal)));
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    // Lower hull left to right, then upper hull right to left
+    let mut hull: Vec<Vec2> = Vec::new();
+    for pass in 0..2 {
+        let start = hull.len();
+        for p in sorted.iter() {
+            while hull.len() >= start + 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], *p) <= OVERLAP_EPSILON {
+                hull.pop();
+            }
+            hull.push(*p);
+        }
+        hull.pop();
+        if pass == 0 {
+            sorted.reverse();
+        }
+    }
+    hull
+}
+
+// The part of a convex polygon inside another, both anticlockwise. Empty if they don't meet.
+pub fn clip_convex(subject: &[Vec2], clip: &[Vec2]) -> Vec<Vec2> {
+    let mut output = subject.to_vec();
+    for i in 0..clip.len() {
+        let (a, b) = (clip[i], clip[(i + 1) % clip.len()]);
+        let input = std::mem::take(&mut output);
+        for j in 0..input.len() {
+            let (p, q) = (input[j], input[(j + 1) % input.len()]);
+            let (side_p, side_q) = (cross(a, b, p), cross(a, b, q));
+            if side_p >= 0.0 {
+                output.push(p);
+            }
+            if (side_p >= 0.0) != (side_q >= 0.0) {
+                output.push(p + (q - p) * (side_p / (side_p - side_q)));
+            }
+        }
+    }
+    output
+}
+
+fn bounding_box(points: &[Vec2]) -> (Vec2, Vec2) {
+    let mut min = Vec2::splat(f32::INFINITY);
+    let mut max = Vec2::splat(f32::NEG_INFINITY);
+    for p in points {
+        min = min.min(*p);
+        max = max.max(*p);
+    }
+    (min, max)
+}
+
+// All pairs of polygons in a patch that overlap, only testing pairs whose bounding boxes meet
+pub fn find_overlapping_pairs(polygons: &[Vec<Vec2>]) -> Vec<(usize, usize)> {
+    let bounds: Vec<(Vec2, Vec2)> = polygons.iter().map(|points| bounding_box(points)).collect();
+
+    let mut pairs = Vec::new();
+    for i in 0..polygons.len() {
+        for j in (i + 1)..polygons.len() {
+            let (min_a, max_a) = bounds[i];
+            let (min_b, max_b) = bounds[j];
+            if max_a.x < min_b.x || max_b.x < min_a.x || max_a.y < min_b.y || max_b.y < min_a.y {
+                continue;
+            }
+            if polygons_overlap(&polygons[i], &polygons[j]) {
+                pairs.push((i, j));
+            }
+        }
+    }
+
+    pairs
+}
@@ -0,0 +1,390 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy::ecs::system::EntityCommands;
+use bevy::input::{keyboard::KeyCode, Input};
+use num_traits::FromPrimitive;
+use strum_macros::Display;
+
+use crate::geometry;
+use crate::growth::{self, FullestCorners, Growth, TypeWeights};
+use crate::lattice::PentagonalPoint;
+use crate::tiling::{Tiling, TilingError};
+use crate::{spawn_tile, PenroseEnum, PointList, Tile, TileShape};
+
+// Girih tiles: the decagon, pentagon, elongated hexagon, bowtie and rhombus behind the strapwork of
+// medieval Islamic architecture, after Lu and Steinhardt, "Decagonal and quasi-crystalline tilings in
+// medieval Islamic architecture" (2007). All sides have the same length and run in the ten directions of
+// the Penrose rhombs, so corners are points of the same pentagonal lattice and compare exactly. Their
+// decagonal subdivision, which gives the quasi-periodic patterns, is the girih rule of the substitution
+// tilings.
+//
+// The tiles are scaffolding for the strapwork drawn on them. Every side is crossed at its midpoint by two
+// straps at 54 degrees to it, so the straps carry on unbroken from tile to tile whichever sides meet. Inside
+// a tile a strap runs straight to another side's midpoint if one lies in its path, otherwise it meets the
+// strap mirroring it from a neighbouring side.
+
+const GIRIH_LEG_LEN: f32 = 40.0;
+const STRAP_ANGLE: f32 = 54.0;
+const STRAP_WIDTH: f32 = 3.0;
+const GROWTH_STEPS: usize = 20;
+// Weight for every side past the first that a new tile would share, so notches fill before the patch
+// grows outwards
+const BOND_WEIGHT: f32 = 4.0;
+
+#[derive(Display, Clone, Copy, Primitive, PartialEq)]
+pub enum GirihType {
+    Decagon = 0,
+    Pentagon = 1,
+    Hexagon = 2,
+    Bowtie = 3,
+    Rhombus = 4,
+
+    Count = 5
+}
+
+impl PenroseEnum for GirihType {
+    fn get_all() -> Vec<Self> {
+        let mut vec = Vec::new();
+        for i in 0..(GirihType::Count as usize) {
+            vec.push(GirihType::from_usize(i).unwrap());
+        }
+        vec
+    }
+}
+
+impl GirihType {
+    // Interior angle at each corner in steps of 36 degrees, anticlockwise from the corner the first side
+    // starts at
+    fn get_corner_angles(&self) -> &'static [i32] {
+        match self {
+            GirihType::Decagon => &[4; 10],
+            GirihType::Pentagon => &[3; 5],
+            GirihType::Hexagon => &[2, 4, 4, 2, 4, 4],
+            GirihType::Bowtie => &[2, 2, 6, 2, 2, 6],
+            GirihType::Rhombus => &[2, 3, 2, 3],
+            GirihType::Count => panic!("Invalid type")
+        }
+    }
+
+    // Direction of each side in steps of 36 degrees, turning by the exterior angle at every corner
+    pub fn get_side_directions(&self) -> Vec<i32> {
+        let mut direction = 0;
+        let mut directions = vec![direction];
+        for angle in &self.get_corner_angles()[1..] {
+            direction += 5 - angle;
+            directions.push(direction);
+        }
+        directions
+    }
+
+    pub fn get_color(&self) -> Color {
+        match self {
+            GirihType::Decagon => Color::rgb_u8(40, 110, 160),
+            GirihType::Pentagon => Color::rgb_u8(230, 190, 90),
+            GirihType::Hexagon => Color::rgb_u8(90, 160, 120),
+            GirihType::Bowtie => Color::rgb_u8(200, 90, 70),
+            GirihType::Rhombus => Color::rgb_u8(240, 230, 200),
+            GirihType::Count => panic!("Invalid type")
+        }
+    }
+}
+
+// Direction of the first side in steps of 36 degrees and the lattice point it starts at
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+struct GirihPose {
+    direction: i32,
+    corner: PentagonalPoint
+}
+
+#[derive(Clone)]
+pub struct GirihTile {
+    girih_type: GirihType,
+    pose: GirihPose,
+    leg_len: f32,
+    used_side_flags: u32
+}
+
+impl GirihTile {
+    fn new(girih_type: GirihType, pose: GirihPose) -> Self {
+        GirihTile {
+            girih_type,
+            pose,
+            leg_len: GIRIH_LEG_LEN,
+            used_side_flags: 0
+        }
+    }
+
+    fn get_lattice_points(&self) -> Vec<PentagonalPoint> {
+        let directions = self.girih_type.get_side_directions();
+        let mut points = vec![self.pose.corner];
+        for direction in &directions[..directions.len() - 1] {
+            let last = points[points.len() - 1];
+            points.push(last + PentagonalPoint::unit(self.pose.direction + direction));
+        }
+        points
+    }
+
+    fn get_centre(&self) -> Vec2 {
+        let points = self.get_lattice_points();
+        points.iter().fold(Vec2::ZERO, |sum, p| sum + p.to_world(self.leg_len)) / points.len() as f32
+    }
+}
+
+// Strap segments inside a tile with the given corners
+fn get_strapwork(points: &[Vec2]) -> Vec<(Vec2, Vec2)> {
+    let epsilon = 0.001 * points[0].distance(points[1]);
+
+    // (side, midpoint, direction) for the two straps leaving each side
+    let mut straps = Vec::new();
+    for side in 0..points.len() {
+        let start = points[side];
+        let end = points[(side + 1) % points.len()];
+        let angle = (end.y - start.y).atan2(end.x - start.x);
+        for strap_angle in [STRAP_ANGLE, 180.0 - STRAP_ANGLE].iter() {
+            let direction = angle + strap_angle.to_radians();
+            straps.push((side, (start + end) / 2.0, Vec2::new(direction.cos(), direction.sin())));
+        }
+    }
+
+    // Straps running into each other along the same line come first, then pairs meeting at the same
+    // distance from both midpoints, nearest first
+    let mut meetings: Vec<(f32, usize, usize, Vec2)> = Vec::new();
+    for i in 0..straps.len() {
+        for j in (i + 1)..straps.len() {
+            let (side_i, start_i, direction_i) = straps[i];
+            let (side_j, start_j, direction_j) = straps[j];
+            if side_i == side_j {
+                continue;
+            }
+            let between = start_j - start_i;
+            let denominator = direction_i.perp_dot(direction_j);
+            if denominator.abs() < epsilon {
+                if between.perp_dot(direction_i).abs() < epsilon && between.dot(direction_i) > 0.0 && direction_i.dot(direction_j) < 0.0 {
+                    meetings.push((0.0, i, j, start_j));
+                }
+                continue;
+            }
+            let t_i = between.perp_dot(direction_j) / denominator;
+            let t_j = between.perp_dot(direction_i) / denominator;
+            let meeting = start_i + direction_i * t_i;
+            if t_i > epsilon && (t_i - t_j).abs() < epsilon && geometry::contains_point(points, meeting) {
+                meetings.push((t_i, i, j, meeting));
+            }
+        }
+    }
+    meetings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut ended = vec![false; straps.len()];
+    let mut segments = Vec::new();
+    for (_, i, j, meeting) in meetings {
+        if ended[i] || ended[j] {
+            continue;
+        }
+        ended[i] = true;
+        ended[j] = true;
+        segments.push((straps[i].1, meeting));
+        if meeting != straps[j].1 {
+            segments.push((straps[j].1, meeting));
+        }
+    }
+    segments
+}
+
+impl TileShape<GirihType> for GirihTile {
+    fn get_points(&self) -> PointList {
+        let centre = self.get_centre();
+        self.get_lattice_points().iter().map(|p| p.to_world(self.leg_len) - centre).collect()
+    }
+
+    fn get_type(&self) -> GirihType {
+        self.girih_type
+    }
+
+    fn insert_shape_component(&self, transform: Transform, entity_commands: &mut EntityCommands) {
+        entity_commands.insert_bundle(
+            GeometryBuilder::build_as(
+                &shapes::Polygon {
+                    points: self.get_points(),
+                    closed: true
+                },
+                ShapeColors::outlined(self.girih_type.get_color(), Color::BLACK),
+                DrawMode::Outlined {
+                    fill_options: FillOptions::default(),
+                    outline_options: StrokeOptions::default().with_line_width(1.0),
+                },
+                transform
+            )
+        );
+    }
+
+    // The strapwork in place of the matching rule dots
+    fn spawn_dots_entities(&self, parent: Entity, commands: &mut Commands) {
+        let mut children = Vec::new();
+        for (start, end) in get_strapwork(&self.get_points()) {
+            let mut entity = commands.spawn();
+            entity.insert_bundle(
+                GeometryBuilder::build_as(
+                    &shapes::Polygon {
+                        points: vec![start, end],
+                        closed: false
+                    },
+                    ShapeColors::new(Color::rgb_u8(60, 40, 20)),
+                    DrawMode::Stroke(StrokeOptions::default().with_line_width(STRAP_WIDTH)),
+                    Transform::from_xyz(0.0, 0.0, 1.0)
+                )
+            );
+            children.push(entity.id());
+        }
+        commands.entity(parent).push_children(&children);
+    }
+}
+
+impl Tile<GirihType> for GirihTile {
+    type Vertex = PentagonalPoint;
+
+    fn new_random(weights: &TypeWeights) -> Self {
+        let candidates: Vec<(GirihType, usize)> = GirihType::get_all().into_iter().map(|t| (t, 0)).collect();
+        GirihTile::new(weights.choose(&candidates).unwrap_or(GirihType::Decagon), GirihPose::default())
+    }
+
+    fn get_num_sides() -> usize {
+        10
+    }
+
+    fn get_side_count(&self) -> usize {
+        self.girih_type.get_corner_angles().len()
+    }
+
+    fn get_side_flags(&self) -> u32 {
+        self.used_side_flags
+    }
+
+    fn get_side_flags_mut(&mut self) -> &mut u32 {
+        &mut self.used_side_flags
+    }
+
+    // The straps cross every side the same way, so any side can meet any other. New tiles are put down by
+    // their first side.
+    fn get_matching_side(onto_type: GirihType, onto_side: u8, other_type: GirihType) -> Result<u8, TilingError> {
+        for girih_type in &[onto_type, other_type] {
+            if *girih_type as usize >= GirihType::Count as usize {
+                return Err(TilingError::InvalidType(girih_type.to_string()));
+            }
+        }
+        if onto_side as usize >= onto_type.get_corner_angles().len() {
+            return Err(TilingError::InvalidSide(onto_side));
+        }
+        Ok(0)
+    }
+
+    fn sides_can_meet(onto_type: GirihType, onto_side: u8, other_type: GirihType, _other_side: u8) -> Result<bool, TilingError> {
+        GirihTile::get_matching_side(onto_type, onto_side, other_type)?;
+        Ok(true)
+    }
+
+    // The shared side runs the opposite way round the new tile, starting where this tile's side ends
+    fn get_connected_tile(&self, side: u8, other_type: GirihType) -> Result<Self, TilingError> {
+        let other_side = GirihTile::get_matching_side(self.girih_type, side, other_type)?;
+        let direction = self.pose.direction + self.girih_type.get_side_directions()[side as usize] + 5;
+        let other_direction = direction - other_type.get_side_directions()[other_side as usize];
+        let corner = self.get_lattice_points()[(side as usize + 1) % self.get_side_count()];
+
+        let mut tile = GirihTile::new(other_type, GirihPose { direction: other_direction.rem_euclid(10), corner });
+        // Walk back from the shared corner to the new tile's first corner
+        tile.pose.corner = corner - (tile.get_lattice_points()[other_side as usize] - tile.pose.corner);
+        Ok(tile)
+    }
+
+    fn get_transform(&self) -> Transform {
+        Transform::from_translation(self.get_centre().extend(0.0))
+    }
+
+    fn get_vertices(&self) -> Vec<PentagonalPoint> {
+        self.get_lattice_points()
+    }
+}
+
+pub struct GirihTiler {
+    tiling: Tiling<GirihType, GirihTile>,
+    growth: Growth<GirihType, GirihTile>,
+    entities: Vec<Entity>
+}
+
+impl Default for GirihTiler {
+    fn default() -> Self {
+        GirihTiler {
+            tiling: Tiling::default(),
+            growth: Growth::with_strategies(TypeWeights::parse("", BOND_WEIGHT).unwrap_or_default(), vec![Box::new(FullestCorners)]),
+            entities: Vec::new()
+        }
+    }
+}
+
+impl GirihTiler {
+    fn spawn_tile(&mut self, entity: Entity, commands: &mut Commands) {
+        if let Some(tile) = self.tiling.get_tile(entity) {
+            spawn_tile(tile, entity, commands);
+            self.entities.push(entity);
+        }
+    }
+
+    // Start again from a single decagon
+    fn restart(&mut self, commands: &mut Commands) {
+        for entity in self.entities.drain(..) {
+            commands.entity(entity).despawn_recursive();
+        }
+        self.tiling = Tiling::default();
+        self.growth.reset_statistics();
+
+        let entity = commands.spawn().id();
+        match self.tiling.place(GirihTile::new(GirihType::Decagon, GirihPose::default()), entity) {
+            Ok(_) => self.spawn_tile(entity, commands),
+            Err(error) => println!("Can't place the first decagon: {}", error)
+        }
+    }
+
+    // Add tiles one at a time on the open edge with the most angle already filled at its ends, where the
+    // fewest shapes fit, skipping edges nothing can go on
+    fn grow(&mut self, steps: usize, commands: &mut Commands) {
+        for entity in growth::grow(&mut self.tiling, &mut self.growth, steps, commands) {
+            self.spawn_tile(entity, commands);
+        }
+        self.print_counts();
+    }
+
+    fn print_counts(&self) {
+        println!("{} girih tiles", self.entities.len());
+        for girih_type in GirihType::get_all() {
+            let count = self.tiling.get_tiles().filter(|(_, tile)| tile.get_type() == girih_type).count();
+            println!("  {}: {}", girih_type, count);
+        }
+    }
+
+    fn verify(&self) {
+        let polygons: Vec<Vec<Vec2>> = self.tiling.get_tiles().map(|(_, tile)| {
+            tile.get_lattice_points().iter().map(|p| p.to_world(1.0)).collect()
+        }).collect();
+        let overlaps = geometry::find_overlapping_pairs(&polygons).len();
+        let dead = self.tiling.get_frontier().get_open_edges().filter(|edge| edge.is_dead()).count();
+        println!("{} tiles, {} overlapping pairs, {} open edges nothing can go on", polygons.len(), overlaps, dead);
+    }
+}
+
+pub fn setup_girih(mut commands: Commands, mut tiler: ResMut<GirihTiler>) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    tiler.restart(&mut commands);
+}
+
+pub fn girih_controls(
+    mut tiler: ResMut<GirihTiler>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        tiler.grow(GROWTH_STEPS, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::R) {
+        tiler.restart(&mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::V) {
+        tiler.verify();
+    }
+}
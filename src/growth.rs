@@ -0,0 +1,478 @@
+use bevy::prelude::{Commands, Entity};
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::frontier::{Frontier, OpenEdge};
+use crate::tiling::{Tiling, TilingError, UndoRecord, SCRATCH_ENTITY};
+use crate::{PenroseEnum, PenroseTiler, Tile};
+
+// How growth picks where to put the next tile. A strategy only orders the open edges that still have a
+// legal type, growth then tries them in that order until one takes a tile without overlapping anything.
+
+pub trait GrowthStrategy<P: PenroseEnum, T: Tile<P>> : Send + Sync {
+    fn get_name(&self) -> &'static str;
+    fn order_edges(&mut self, edges: &mut Vec<&OpenEdge<P, T>>);
+}
+
+// Uniformly random edge
+pub struct RandomFrontier;
+
+impl<P: PenroseEnum, T: Tile<P>> GrowthStrategy<P, T> for RandomFrontier {
+    fn get_name(&self) -> &'static str {
+        "random frontier"
+    }
+
+    fn order_edges(&mut self, edges: &mut Vec<&OpenEdge<P, T>>) {
+        edges.shuffle(&mut rand::thread_rng());
+    }
+}
+
+// Edge closest to the origin first, so the patch grows in rings
+pub struct Radial;
+
+impl<P: PenroseEnum, T: Tile<P>> GrowthStrategy<P, T> for Radial {
+    fn get_name(&self) -> &'static str {
+        "breadth-first radial"
+    }
+
+    fn order_edges(&mut self, edges: &mut Vec<&OpenEdge<P, T>>) {
+        edges.shuffle(&mut rand::thread_rng());
+        edges.sort_by(|a, b| {
+            let distance_a = ((a.start + a.end) / 2.0).length();
+            let distance_b = ((b.start + b.end) / 2.0).length();
+            distance_a.partial_cmp(&distance_b).unwrap_or(Ordering::Equal)
+        });
+    }
+}
+
+// Edge with the fewest legal types first, ties broken at random
+pub struct MostConstrained;
+
+impl<P: PenroseEnum, T: Tile<P>> GrowthStrategy<P, T> for MostConstrained {
+    fn get_name(&self) -> &'static str {
+        "most constrained first"
+    }
+
+    fn order_edges(&mut self, edges: &mut Vec<&OpenEdge<P, T>>) {
+        edges.shuffle(&mut rand::thread_rng());
+        edges.sort_by_key(|edge| edge.legal_types.len());
+    }
+}
+
+// Edge with the most angle already filled at its ends first, where the fewest shapes fit, then the oldest
+pub struct FullestCorners;
+
+impl<P: PenroseEnum, T: Tile<P>> GrowthStrategy<P, T> for FullestCorners {
+    fn get_name(&self) -> &'static str {
+        "fullest corners first"
+    }
+
+    fn order_edges(&mut self, edges: &mut Vec<&OpenEdge<P, T>>) {
+        edges.sort_by(|a, b| {
+            let filled_a = a.filled_angles.0 + a.filled_angles.1;
+            let filled_b = b.filled_angles.0 + b.filled_angles.1;
+            filled_b.partial_cmp(&filled_a).unwrap_or(Ordering::Equal).then(a.age.cmp(&b.age))
+        });
+    }
+}
+
+// Edge that has been open the longest first
+pub struct OldestEdge;
+
+impl<P: PenroseEnum, T: Tile<P>> GrowthStrategy<P, T> for OldestEdge {
+    fn get_name(&self) -> &'static str {
+        "oldest edge first"
+    }
+
+    fn order_edges(&mut self, edges: &mut Vec<&OpenEdge<P, T>>) {
+        edges.sort_by_key(|edge| edge.age);
+    }
+}
+
+// How likely each tile type is to be chosen once growth has an edge. A type weighs its base weight, times
+// bond_weight for every side of the new tile past the first that would meet a placed tile. With a bond weight
+// above one, tiles that fill notches win, as in kinetic growth models, below one they avoid them.
+pub struct TypeWeights {
+    // By type name without degree signs, types not listed weigh one
+    per_type: HashMap<String, f32>,
+    bond_weight: f32
+}
+
+impl Default for TypeWeights {
+    fn default() -> Self {
+        TypeWeights {
+            per_type: HashMap::new(),
+            bond_weight: 1.0
+        }
+    }
+}
+
+impl TypeWeights {
+    // From a list like `72/108=1.618,36/144=1`
+    pub fn parse(per_type: &str, bond_weight: f32) -> Result<Self, String> {
+        let mut weights = HashMap::new();
+        for entry in per_type.split(',').filter(|entry| !entry.is_empty()) {
+            let mut parts = entry.splitn(2, '=');
+            let name = TypeWeights::get_key(parts.next().unwrap_or(""));
+            let weight: f32 = parts.next()
+                .and_then(|weight| weight.trim().parse().ok())
+                .ok_or(format!("expected name=weight, got '{}'", entry))?;
+            if weight < 0.0 || !weight.is_finite() {
+                return Err(format!("weight of {} must be a non-negative number", name));
+            }
+            weights.insert(name, weight);
+        }
+        if bond_weight < 0.0 || !bond_weight.is_finite() {
+            return Err("bond weight must be a non-negative number".to_string());
+        }
+
+        Ok(TypeWeights {
+            per_type: weights,
+            bond_weight
+        })
+    }
+
+    // Angle pairs can be given as 72/108 as well as 72°/108°
+    fn get_key(name: &str) -> String {
+        name.trim().to_lowercase().replace('°', "")
+    }
+
+    pub fn get_weight<P: PenroseEnum>(&self, penrose_type: P, matched_sides: usize) -> f32 {
+        let base = self.per_type.get(&TypeWeights::get_key(&penrose_type.to_string())).copied().unwrap_or(1.0);
+        base * self.bond_weight.powi(matched_sides.max(1) as i32 - 1)
+    }
+
+    // Candidates are the types that fit with the number of their sides that would meet placed tiles. None if
+    // there are none or they all weigh nothing.
+    pub fn choose<P: PenroseEnum>(&self, candidates: &[(P, usize)]) -> Option<P> {
+        let weights: Vec<f32> = candidates.iter().map(|(penrose_type, matched)| self.get_weight(*penrose_type, *matched)).collect();
+        let index = WeightedIndex::new(&weights).ok()?.sample(&mut rand::thread_rng());
+        Some(candidates[index].0)
+    }
+
+    pub fn print(&self) {
+        let mut per_type: Vec<String> = self.per_type.iter().map(|(name, weight)| format!("{}={}", name, weight)).collect();
+        per_type.sort();
+        println!("Type weights [{}], bond weight {}", per_type.join(", "), self.bond_weight);
+    }
+}
+
+#[derive(Default)]
+pub struct GrowthStats {
+    pub placed: usize,
+    // Edges tried that couldn't take any tile without an overlap
+    pub failed_edges: usize,
+    // Growth steps where no edge could take a tile at all
+    pub dead_ends: usize,
+    // Tiles placed by propagation because nothing else fitted
+    pub forced: usize,
+    // Tiles placed by type name, forced or not
+    pub placed_types: BTreeMap<String, usize>
+}
+
+impl GrowthStats {
+    pub fn add_placed<P: PenroseEnum>(&mut self, penrose_type: P) {
+        self.placed += 1;
+        *self.placed_types.entry(penrose_type.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn add_forced<P: PenroseEnum>(&mut self, penrose_type: P) {
+        self.forced += 1;
+        *self.placed_types.entry(penrose_type.to_string()).or_insert(0) += 1;
+    }
+}
+
+pub struct Growth<P: PenroseEnum, T: Tile<P>> {
+    strategies: Vec<Box<dyn GrowthStrategy<P, T>>>,
+    current: usize,
+    pub weights: TypeWeights,
+    // Fill forced edges after every growth step
+    pub propagate: bool,
+    pub stats: GrowthStats
+}
+
+impl<P: PenroseEnum, T: Tile<P>> Default for Growth<P, T> {
+    fn default() -> Self {
+        Growth {
+            strategies: vec![
+                Box::new(RandomFrontier),
+                Box::new(Radial),
+                Box::new(MostConstrained),
+                Box::new(OldestEdge)
+            ],
+            current: 0,
+            weights: TypeWeights::default(),
+            propagate: false,
+            stats: GrowthStats::default()
+        }
+    }
+}
+
+impl<P: PenroseEnum, T: Tile<P>> Growth<P, T> {
+    pub fn with_weights(weights: TypeWeights) -> Self {
+        Growth {
+            weights,
+            ..Growth::default()
+        }
+    }
+
+    // Only the given strategies, the first one to start with
+    pub fn with_strategies(weights: TypeWeights, strategies: Vec<Box<dyn GrowthStrategy<P, T>>>) -> Self {
+        Growth {
+            strategies,
+            weights,
+            ..Growth::default()
+        }
+    }
+
+    pub fn get_strategy_name(&self) -> &'static str {
+        self.strategies[self.current].get_name()
+    }
+
+    // Switching strategy starts the statistics again so strategies can be compared
+    pub fn next_strategy(&mut self) {
+        self.current = (self.current + 1) % self.strategies.len();
+        self.reset_statistics();
+    }
+
+    pub fn reset_statistics(&mut self) {
+        self.stats = GrowthStats::default();
+    }
+
+    // Open edges that still have a legal type, in the order the current strategy wants them tried
+    pub fn order_edges<'a>(&mut self, frontier: &'a Frontier<P, T>) -> Vec<&'a OpenEdge<P, T>> {
+        let mut edges: Vec<&OpenEdge<P, T>> = frontier.get_open_edges().filter(|edge| !edge.is_dead()).collect();
+        self.strategies[self.current].order_edges(&mut edges);
+        edges
+    }
+
+    // Dead-end rate and patch shape, a ragged patch has a long boundary and many dead edges for its size
+    pub fn print_statistics(&self, frontier: &Frontier<P, T>, num_tiles: usize) {
+        let stats = &self.stats;
+        let attempts = stats.placed + stats.failed_edges;
+        println!("Growth with {}: {} placed, {} forced, {} failed edges ({:.3} of attempts), {} dead ends",
+            self.get_strategy_name(), stats.placed, stats.forced, stats.failed_edges,
+            if attempts > 0 { stats.failed_edges as f32 / attempts as f32 } else { 0.0 }, stats.dead_ends);
+        let placed_types: Vec<String> = stats.placed_types.iter().map(|(name, count)| format!("{} {}", name, count)).collect();
+        println!("  placed by type: {}", placed_types.join(", "));
+        self.weights.print();
+
+        let edges: Vec<&OpenEdge<P, T>> = frontier.get_open_edges().collect();
+        let max_radius = edges.iter().map(|edge| edge.start.length().max(edge.end.length())).fold(0.0, f32::max);
+        let boundary: f32 = edges.iter().map(|edge| (edge.end - edge.start).length()).sum();
+        let dead = edges.iter().filter(|edge| edge.is_dead()).count();
+        println!("  {} tiles, {} open edges ({} dead), boundary {:.0}, max radius {:.0}",
+            num_tiles, edges.len(), dead, boundary, max_radius);
+    }
+}
+
+// Add up to steps tiles one at a time, each on the first edge in the strategy's order that takes a type the
+// weights pick without an overlap. An empty tiling starts from a random tile. Returns the entities of the
+// placed tiles for the caller to draw.
+pub fn grow<P: PenroseEnum, T: Tile<P>>(
+    tiling: &mut Tiling<P, T>,
+    growth: &mut Growth<P, T>,
+    steps: usize,
+    commands: &mut Commands
+) -> Vec<Entity> {
+    let mut placed_entities = Vec::new();
+    if tiling.get_topology().get_num_tiles() == 0 {
+        growth.reset_statistics();
+        let entity = commands.spawn().id();
+        match tiling.place(T::new_random(&growth.weights), entity) {
+            Ok(_) => placed_entities.push(entity),
+            Err(error) => {
+                commands.entity(entity).despawn();
+                println!("Can't place the first tile: {}", error);
+                return placed_entities;
+            }
+        }
+    }
+
+    for _ in 0..steps {
+        let edges: Vec<(Entity, u8)> = growth.order_edges(tiling.get_frontier()).iter()
+            .map(|edge| (edge.entity, edge.side))
+            .collect();
+
+        let mut placed = false;
+        for (on_entity, side) in edges {
+            let checks = match tiling.inspect_side(on_entity, side) {
+                Ok(checks) => checks,
+                Err(error) => {
+                    println!("Can't inspect side {} of {:?}: {}", side, on_entity, error);
+                    continue;
+                }
+            };
+            let on_tile = tiling.get_tile(on_entity).unwrap();
+            let candidates: Vec<(P, usize)> = checks.into_iter().filter(|(_, check)| check.is_allowed()).filter_map(|(penrose_type, _)| {
+                let tile = on_tile.get_connected_tile(side, penrose_type).ok()?;
+                Some((penrose_type, PenroseTiler::count_matched_sides(&tile, tiling.get_topology())))
+            }).collect();
+            let penrose_type = match growth.weights.choose(&candidates) {
+                Some(penrose_type) => penrose_type,
+                None => {
+                    growth.stats.failed_edges += 1;
+                    continue;
+                }
+            };
+
+            let entity = commands.spawn().id();
+            match tiling.place_on(on_entity, side, penrose_type, entity) {
+                Ok(_) => {
+                    placed_entities.push(entity);
+                    growth.stats.add_placed(penrose_type);
+                    placed = true;
+                    break;
+                },
+                Err(error) => {
+                    commands.entity(entity).despawn();
+                    println!("Placement rejected: {}", error);
+                }
+            }
+        }
+        if !placed {
+            growth.stats.dead_ends += 1;
+            println!("No open edge takes a tile");
+            break;
+        }
+    }
+    growth.print_statistics(tiling.get_frontier(), tiling.get_topology().get_num_tiles());
+    placed_entities
+}
+
+// A filled cell with the types that also fitted it and haven't been tried yet
+struct RowPlacement<P: PenroseEnum, T: Tile<P>> {
+    record: UndoRecord<T>,
+    untried: Vec<P>
+}
+
+pub enum SearchStep {
+    Placed(Entity),
+    Removed(Entity),
+    // Every choice for the first tile has been tried
+    Stuck
+}
+
+// Depth-first search for a patch of a lattice tiling, filled in rows of fixed width that run forwards and
+// back in turn, so each cell is put on a side of the cell before it with the types the frontier allows
+// there. Turning at the end of a row keeps the cells that constrain each other close together in the
+// search. A cell nothing fits in sends the search back to try the next type that fitted the cell before
+// it. Types that would leave an open edge nothing fits are skipped. For sets whose rules can't be
+// satisfied by placing tiles greedily.
+pub struct RowSearch<P: PenroseEnum, T: Tile<P>> {
+    row_width: usize,
+    // Sides of a tile the next tile in its row goes on, in rows running forwards and back
+    along_sides: (u8, u8),
+    // Side of the last tile of a row the first tile of the next row goes on
+    up_side: u8,
+    placements: Vec<RowPlacement<P, T>>,
+    // Types still to try in the next cell when the search has come back to it
+    pending: Option<Vec<P>>,
+    pub backtracks: usize
+}
+
+impl<P: PenroseEnum + PartialEq, T: Tile<P>> RowSearch<P, T> {
+    pub fn new(row_width: usize, along_sides: (u8, u8), up_side: u8) -> Self {
+        RowSearch {
+            row_width,
+            along_sides,
+            up_side,
+            placements: Vec::new(),
+            pending: None,
+            backtracks: 0
+        }
+    }
+
+    pub fn get_num_placed(&self) -> usize {
+        self.placements.len()
+    }
+
+    pub fn get_entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.placements.iter().map(|placement| placement.record.entity)
+    }
+
+    // The side of the last placed tile the next cell is across
+    fn get_anchor(&self) -> Option<(Entity, u8)> {
+        let index = self.placements.len();
+        let side = if index.is_multiple_of(self.row_width) {
+            self.up_side
+        } else if (index / self.row_width).is_multiple_of(2) {
+            self.along_sides.0
+        } else {
+            self.along_sides.1
+        };
+        self.placements.last().map(|placement| (placement.record.entity, side))
+    }
+
+    // The types that don't leave an open edge anywhere that nothing can go on, which would only be found
+    // out rows later
+    fn get_live_types(tiling: &mut Tiling<P, T>, entity: Entity, side: u8, legal_types: Vec<P>) -> Result<Vec<P>, TilingError> {
+        let count_dead = |tiling: &Tiling<P, T>| tiling.get_frontier().get_open_edges().filter(|edge| edge.is_dead()).count();
+        let dead = count_dead(tiling);
+        let mut live_types = Vec::new();
+        for penrose_type in legal_types {
+            let record = tiling.place_on(entity, side, penrose_type, Entity::new(SCRATCH_ENTITY))?;
+            let live = count_dead(tiling) <= dead;
+            tiling.undo(&record)?;
+            if live {
+                live_types.push(penrose_type);
+            }
+        }
+        Ok(live_types)
+    }
+
+    // Fill the next cell, or when nothing is left to try there take the last tile away again. The first cell
+    // takes one of first_types, placed by first_tile.
+    pub fn step(
+        &mut self,
+        tiling: &mut Tiling<P, T>,
+        weights: &TypeWeights,
+        first_types: &[P],
+        first_tile: impl FnOnce(P) -> T,
+        new_entity: impl FnOnce() -> Entity
+    ) -> Result<SearchStep, TilingError> {
+        let anchor = self.get_anchor();
+        let mut untried = match self.pending.take() {
+            Some(untried) => untried,
+            None => {
+                let mut legal_types = match anchor {
+                    None => first_types.to_vec(),
+                    Some((entity, side)) => tiling.get_frontier().get_open_edges()
+                        .find(|edge| edge.entity == entity && edge.side == side)
+                        .map(|edge| edge.legal_types.clone())
+                        .unwrap_or_default()
+                };
+                legal_types.retain(|penrose_type| weights.get_weight(*penrose_type, 0) > 0.0);
+                match anchor {
+                    None => legal_types,
+                    Some((entity, side)) => RowSearch::<P, T>::get_live_types(tiling, entity, side, legal_types)?
+                }
+            }
+        };
+
+        if untried.is_empty() {
+            let placement = match self.placements.pop() {
+                Some(placement) => placement,
+                None => return Ok(SearchStep::Stuck)
+            };
+            tiling.undo(&placement.record)?;
+            self.pending = Some(placement.untried);
+            self.backtracks += 1;
+            return Ok(SearchStep::Removed(placement.record.entity));
+        }
+
+        let candidates: Vec<(P, usize)> = untried.iter().map(|penrose_type| (*penrose_type, 0)).collect();
+        let penrose_type = weights.choose(&candidates).unwrap_or(untried[0]);
+        untried.retain(|t| *t != penrose_type);
+
+        let entity = new_entity();
+        let record = match anchor {
+            None => tiling.place(first_tile(penrose_type), entity)?,
+            Some((on_entity, side)) => tiling.place_on(on_entity, side, penrose_type, entity)?
+        };
+        self.placements.push(RowPlacement { record, untried });
+        Ok(SearchStep::Placed(entity))
+    }
+}
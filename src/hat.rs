@@ -0,0 +1,683 @@
+use bevy::prelude::*;
+use bevy::input::{keyboard::KeyCode, Input};
+use std::collections::HashMap;
+use std::sync::Arc;
+use strum_macros::Display;
+
+use crate::affine::{Affine2, line_intersection};
+use crate::geometry;
+use crate::growth::{self, Growth, TypeWeights};
+use crate::outline_tile::{OutlineTile, OutlineType};
+use crate::tiling::{Tiling, TilingError};
+use crate::{spawn_tile, PenroseEnum, Tile, TileShape};
+
+// The Smith, Myers, Kaplan and Goodman-Strauss "hat" monotile, generated with the H, T, P, F metatile
+// substitution from "An aperiodic monotile" (2023). The construction follows Craig Kaplan's reference
+// implementation (https://cs.uwaterloo.ca/~csk/hat/).
+
+const SQRT_3_OVER_2: f32 = 0.866_025_4;
+
+// Size of the final patch on screen, applied on top of the metatile coordinates
+const HAT_WORLD_SCALE: f32 = 40.0;
+const GROWTH_STEPS: usize = 20;
+
+fn hex_point(x: f32, y: f32) -> Vec2 {
+    Vec2::new(x + 0.5 * y, SQRT_3_OVER_2 * y)
+}
+
+// Inverse of hex_point, rounded to the lattice, in units of the given size
+fn to_lattice(p: Vec2, size: f32) -> (i32, i32) {
+    let y = p.y / (SQRT_3_OVER_2 * size);
+    let x = p.x / size - 0.5 * y;
+    (x.round() as i32, y.round() as i32)
+}
+
+lazy_static! {
+    // The side of length 2 is split at its middle, where a neighbouring hat can have a corner
+    static ref HAT_OUTLINE: Vec<Vec2> = vec![
+        hex_point(0.0, 0.0), hex_point(-1.0, -1.0), hex_point(0.0, -2.0), hex_point(1.0, -2.0),
+        hex_point(2.0, -2.0), hex_point(2.0, -1.0), hex_point(4.0, -2.0), hex_point(5.0, -1.0),
+        hex_point(4.0, 0.0), hex_point(3.0, 0.0), hex_point(2.0, 2.0), hex_point(0.0, 3.0),
+        hex_point(0.0, 2.0), hex_point(-1.0, 2.0)
+    ];
+}
+
+// Hats of the substituted patch are labelled with the metatile they end up in, H1 being the reflected hat
+// at the heart of every H. The labels only colour the patch.
+#[derive(Display, Clone, Copy, PartialEq)]
+pub enum HatType {
+    H1,
+    H,
+    T,
+    P,
+    F
+}
+
+impl PenroseEnum for HatType {
+    fn get_all() -> Vec<Self> {
+        vec![HatType::H1, HatType::H, HatType::T, HatType::P, HatType::F]
+    }
+}
+
+impl OutlineType for HatType {
+    fn get_outline() -> &'static [Vec2] {
+        &HAT_OUTLINE
+    }
+
+    fn get_color(&self) -> Color {
+        match self {
+            HatType::H1 => Color::rgb_u8(0, 137, 212),
+            HatType::H => Color::rgb_u8(148, 205, 235),
+            HatType::T => Color::rgb_u8(251, 251, 251),
+            HatType::P => Color::rgb_u8(250, 250, 250),
+            HatType::F => Color::rgb_u8(191, 191, 191)
+        }
+    }
+}
+
+pub type HatTile = OutlineTile<HatType>;
+
+// A grown hat has no metatile to be labelled by, only a handedness
+#[derive(Display, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GrownHatType {
+    Hat,
+    ReflectedHat
+}
+
+impl PenroseEnum for GrownHatType {
+    fn get_all() -> Vec<Self> {
+        vec![GrownHatType::Hat, GrownHatType::ReflectedHat]
+    }
+}
+
+impl OutlineType for GrownHatType {
+    fn get_outline() -> &'static [Vec2] {
+        &HAT_OUTLINE
+    }
+
+    fn get_color(&self) -> Color {
+        match self {
+            GrownHatType::Hat => Color::rgb_u8(148, 205, 235),
+            GrownHatType::ReflectedHat => Color::rgb_u8(0, 137, 212)
+        }
+    }
+}
+
+pub type GrownHatTile = OutlineTile<GrownHatType>;
+
+// The lattice step along a side turned a sixth of a turn anticlockwise
+fn turn_sixth(step: (i32, i32)) -> (i32, i32) {
+    (-step.1, step.0 + step.1)
+}
+
+impl GrownHatTile {
+    fn get_chirality(hat_type: GrownHatType) -> Affine2 {
+        if hat_type == GrownHatType::ReflectedHat { Affine2::reflect_x() } else { Affine2::IDENTITY }
+    }
+
+    // Lattice steps along the sides of an unturned hat of the given type
+    fn get_side_steps(hat_type: GrownHatType) -> Vec<(i32, i32)> {
+        let vertices = GrownHatTile::new(hat_type, &GrownHatTile::get_chirality(hat_type)).0.get_vertices();
+        (0..vertices.len()).map(|i| {
+            let next = vertices[(i + 1) % vertices.len()];
+            (next.0 - vertices[i].0, next.1 - vertices[i].1)
+        }).collect()
+    }
+}
+
+// Side pairs of two hats that can meet, as (type, side, other type, other side). Hats are only ever turned
+// by sixths of a turn, so two sides can meet when some such turn makes one run the other way along the
+// same length.
+fn list_side_pairs() -> Vec<(GrownHatType, u8, GrownHatType, u8)> {
+    let mut pairs = Vec::new();
+    for onto_type in GrownHatType::get_all() {
+        for other_type in GrownHatType::get_all() {
+            let onto_steps = GrownHatTile::get_side_steps(onto_type);
+            let other_steps = GrownHatTile::get_side_steps(other_type);
+            for (onto_side, onto_step) in onto_steps.iter().enumerate() {
+                for (other_side, other_step) in other_steps.iter().enumerate() {
+                    let mut turned = *other_step;
+                    let mut meets = false;
+                    for _ in 0..6 {
+                        meets |= turned == (-onto_step.0, -onto_step.1);
+                        turned = turn_sixth(turned);
+                    }
+                    if meets {
+                        pairs.push((onto_type, onto_side as u8, other_type, other_side as u8));
+                    }
+                }
+            }
+        }
+    }
+    pairs
+}
+
+// How often each side pair meets in a substituted patch of the given level
+fn count_side_pairs_in_patch(level: usize) -> HashMap<(GrownHatType, u8, GrownHatType, u8), usize> {
+    let mut metatiles = make_initial_metatiles();
+    for _ in 0..level {
+        metatiles = construct_metatiles(&construct_patch(&metatiles));
+    }
+    let mut hats = Vec::new();
+    metatiles[MetaTileType::H as usize].collect_hats(Affine2::IDENTITY, &mut hats);
+
+    // Metatiles are recentred, so the corners are off the lattice and are compared rounded instead
+    let key = |p: Vec2| ((p.x * 1000.0).round() as i64, (p.y * 1000.0).round() as i64);
+    let mut sides = HashMap::new();
+    for (_, placement) in &hats {
+        let hat_type = if placement.is_reflection() { GrownHatType::ReflectedHat } else { GrownHatType::Hat };
+        let points = GrownHatTile::new(hat_type, placement).0.get_world_points();
+        for side in 0..points.len() {
+            sides.insert((key(points[side]), key(points[(side + 1) % points.len()])), (hat_type, side as u8));
+        }
+    }
+
+    let mut counts = HashMap::new();
+    for ((start, end), (onto_type, onto_side)) in &sides {
+        if let Some((other_type, other_side)) = sides.get(&(*end, *start)) {
+            *counts.entry((*onto_type, *onto_side, *other_type, *other_side)).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+lazy_static! {
+    static ref HAT_SIDE_PAIRS: Vec<(GrownHatType, u8, GrownHatType, u8)> = list_side_pairs();
+
+    // The side a new hat is put down by, the one of the pairs that meets most often in a substituted patch
+    // or the first of them for a side that never meets a hat of the other type there
+    static ref MATCHING_SIDES: HashMap<(GrownHatType, u8, GrownHatType), u8> = {
+        let counts = count_side_pairs_in_patch(2);
+        let mut best: HashMap<(GrownHatType, u8, GrownHatType), (usize, u8)> = HashMap::new();
+        for (onto_type, onto_side, other_type, other_side) in HAT_SIDE_PAIRS.iter() {
+            let count = counts.get(&(*onto_type, *onto_side, *other_type, *other_side)).copied().unwrap_or(0);
+            let entry = best.entry((*onto_type, *onto_side, *other_type)).or_insert((count, *other_side));
+            if count > entry.0 {
+                *entry = (count, *other_side);
+            }
+        }
+        best.into_iter().map(|(key, (_, other_side))| (key, other_side)).collect()
+    };
+}
+
+// Hats grown through the tiling. Corners are on the triangular lattice the outline is drawn on, so they
+// compare exactly. There are no matching rules beyond the shape: any two sides can meet that a turn of the
+// new hat lines up, and a new hat is put down by the pairing a hat tiling uses most.
+impl Tile<GrownHatType> for GrownHatTile {
+    type Vertex = (i32, i32);
+
+    fn new_random(weights: &TypeWeights) -> Self {
+        let candidates: Vec<(GrownHatType, usize)> = GrownHatType::get_all().into_iter().map(|t| (t, 0)).collect();
+        let hat_type = weights.choose(&candidates).unwrap_or(GrownHatType::Hat);
+        GrownHatTile::new(hat_type, &(Affine2::scale(HAT_WORLD_SCALE) * GrownHatTile::get_chirality(hat_type))).0
+    }
+
+    fn get_num_sides() -> usize {
+        HAT_OUTLINE.len()
+    }
+
+    fn get_side_flags(&self) -> u32 {
+        self.used_side_flags
+    }
+
+    fn get_side_flags_mut(&mut self) -> &mut u32 {
+        &mut self.used_side_flags
+    }
+
+    fn get_matching_side(onto_type: GrownHatType, onto_side: u8, other_type: GrownHatType) -> Result<u8, TilingError> {
+        MATCHING_SIDES.get(&(onto_type, onto_side, other_type)).copied().ok_or(TilingError::InvalidSide(onto_side))
+    }
+
+    fn sides_can_meet(onto_type: GrownHatType, onto_side: u8, other_type: GrownHatType, other_side: u8) -> Result<bool, TilingError> {
+        if onto_side as usize >= HAT_OUTLINE.len() {
+            return Err(TilingError::InvalidSide(onto_side));
+        }
+        Ok(HAT_SIDE_PAIRS.contains(&(onto_type, onto_side, other_type, other_side)))
+    }
+
+    // Placed from the lattice corners of the side rather than the float ones, so errors don't build up
+    // from tile to tile
+    fn get_connected_tile(&self, side: u8, other_type: GrownHatType) -> Result<Self, TilingError> {
+        let other_side = GrownHatTile::get_matching_side(self.get_type(), side, other_type)? as usize;
+        let num_sides = HAT_OUTLINE.len();
+        let size = self.get_placement().uniform_scale();
+        let vertices = self.get_vertices();
+        let start = vertices[(side as usize + 1) % num_sides];
+        let end = vertices[side as usize];
+
+        let chirality = GrownHatTile::get_chirality(other_type);
+        let outline = GrownHatTile::new(other_type, &chirality).0.get_world_points();
+        let placement = Affine2::match_two(
+            outline[other_side], outline[(other_side + 1) % num_sides],
+            hex_point(start.0 as f32, start.1 as f32) * size, hex_point(end.0 as f32, end.1 as f32) * size
+        ) * chirality;
+        Ok(GrownHatTile::new(other_type, &placement).0)
+    }
+
+    fn get_transform(&self) -> Transform {
+        self.get_rigid_transform()
+    }
+
+    fn get_vertices(&self) -> Vec<(i32, i32)> {
+        let size = self.get_placement().uniform_scale();
+        self.get_world_points().iter().map(|p| to_lattice(*p, size)).collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MetaTileType {
+    H = 0,
+    T = 1,
+    P = 2,
+    F = 3
+}
+
+#[derive(Clone)]
+enum HatGeometry {
+    Hat(HatType),
+    Meta(Arc<MetaTile>)
+}
+
+impl HatGeometry {
+    fn get_shape(&self) -> &[Vec2] {
+        match self {
+            HatGeometry::Hat(_) => &HAT_OUTLINE,
+            HatGeometry::Meta(meta) => &meta.shape
+        }
+    }
+}
+
+struct MetaTile {
+    shape: Vec<Vec2>,
+    children: Vec<(Affine2, HatGeometry)>
+}
+
+impl MetaTile {
+    fn new(shape: Vec<Vec2>) -> Self {
+        MetaTile {
+            shape,
+            children: Vec::new()
+        }
+    }
+
+    fn add_child(&mut self, transform: Affine2, geometry: HatGeometry) {
+        self.children.push((transform, geometry));
+    }
+
+    fn eval_child(&self, child: usize, point: usize) -> Vec2 {
+        let (transform, geometry) = &self.children[child];
+        transform.transform_point(geometry.get_shape()[point])
+    }
+
+    // Move the outline's centroid to the origin so supertiles don't drift further away every level
+    fn recentre(&mut self) {
+        let mut centre = Vec2::ZERO;
+        for p in &self.shape {
+            centre += *p;
+        }
+        centre /= self.shape.len() as f32;
+
+        for p in self.shape.iter_mut() {
+            *p -= centre;
+        }
+
+        let to_centre = Affine2::translation(-centre.x, -centre.y);
+        for (transform, _) in self.children.iter_mut() {
+            *transform = to_centre * *transform;
+        }
+    }
+
+    fn collect_hats(&self, transform: Affine2, hats: &mut Vec<(HatType, Affine2)>) {
+        for (child_transform, geometry) in &self.children {
+            let child_transform = transform * *child_transform;
+            match geometry {
+                HatGeometry::Hat(hat_type) => hats.push((*hat_type, child_transform)),
+                HatGeometry::Meta(meta) => meta.collect_hats(child_transform, hats)
+            }
+        }
+    }
+}
+
+type MetaTileSet = [Arc<MetaTile>; 4];
+
+fn make_initial_metatiles() -> MetaTileSet {
+    let hat = &HAT_OUTLINE;
+    let r3 = SQRT_3_OVER_2;
+
+    let h_outline = vec![
+        Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0), Vec2::new(4.5, r3),
+        Vec2::new(2.5, 5.0 * r3), Vec2::new(1.5, 5.0 * r3), Vec2::new(-0.5, r3)
+    ];
+    let mut h = MetaTile::new(h_outline.clone());
+    h.add_child(Affine2::match_two(hat[6], hat[8], h_outline[5], h_outline[0]), HatGeometry::Hat(HatType::H));
+    h.add_child(Affine2::match_two(hat[10], hat[12], h_outline[1], h_outline[2]), HatGeometry::Hat(HatType::H));
+    h.add_child(Affine2::match_two(hat[6], hat[8], h_outline[3], h_outline[4]), HatGeometry::Hat(HatType::H));
+    h.add_child(
+        Affine2::translation(2.5, r3)
+            * Affine2::new([-0.5, -r3, 0.0, r3, -0.5, 0.0])
+            * Affine2::new([0.5, 0.0, 0.0, 0.0, -0.5, 0.0]),
+        HatGeometry::Hat(HatType::H1)
+    );
+
+    let mut t = MetaTile::new(vec![Vec2::new(0.0, 0.0), Vec2::new(3.0, 0.0), Vec2::new(1.5, 3.0 * r3)]);
+    t.add_child(Affine2::new([0.5, 0.0, 0.5, 0.0, 0.5, r3]), HatGeometry::Hat(HatType::T));
+
+    let mut p = MetaTile::new(vec![
+        Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0), Vec2::new(3.0, 2.0 * r3), Vec2::new(-1.0, 2.0 * r3)
+    ]);
+    p.add_child(Affine2::new([0.5, 0.0, 1.5, 0.0, 0.5, r3]), HatGeometry::Hat(HatType::P));
+    p.add_child(
+        Affine2::translation(0.0, 2.0 * r3)
+            * Affine2::new([0.5, r3, 0.0, -r3, 0.5, 0.0])
+            * Affine2::scale(0.5),
+        HatGeometry::Hat(HatType::P)
+    );
+
+    let mut f = MetaTile::new(vec![
+        Vec2::new(0.0, 0.0), Vec2::new(3.0, 0.0), Vec2::new(3.5, r3), Vec2::new(3.0, 2.0 * r3), Vec2::new(-1.0, 2.0 * r3)
+    ]);
+    f.add_child(Affine2::new([0.5, 0.0, 1.5, 0.0, 0.5, r3]), HatGeometry::Hat(HatType::F));
+    f.add_child(
+        Affine2::translation(0.0, 2.0 * r3)
+            * Affine2::new([0.5, r3, 0.0, -r3, 0.5, 0.0])
+            * Affine2::scale(0.5),
+        HatGeometry::Hat(HatType::F)
+    );
+
+    [Arc::new(h), Arc::new(t), Arc::new(p), Arc::new(f)]
+}
+
+// How each metatile of the intermediate patch is attached to the ones placed before it
+enum PatchRule {
+    // The first metatile, placed as is
+    Root(MetaTileType),
+    // Edge `edge` of an earlier metatile is glued to edge `new_edge` of the new one
+    Edge { child: usize, edge: usize, new_type: MetaTileType, new_edge: usize },
+    // The new metatile's edge spans a vertex of each of two earlier metatiles
+    Span { child_p: usize, vertex_p: usize, child_q: usize, vertex_q: usize, new_type: MetaTileType, new_edge: usize }
+}
+
+const fn edge(child: usize, edge: usize, new_type: MetaTileType, new_edge: usize) -> PatchRule {
+    PatchRule::Edge { child, edge, new_type, new_edge }
+}
+
+const fn span(child_p: usize, vertex_p: usize, child_q: usize, vertex_q: usize, new_type: MetaTileType, new_edge: usize) -> PatchRule {
+    PatchRule::Span {
+        child_p,
+        vertex_p,
+        child_q,
+        vertex_q,
+        new_type,
+        new_edge
+    }
+}
+
+const PATCH_RULES: [PatchRule; 29] = [
+    PatchRule::Root(MetaTileType::H),
+    edge(0, 0, MetaTileType::P, 2),
+    edge(1, 0, MetaTileType::H, 2),
+    edge(2, 0, MetaTileType::P, 2),
+    edge(3, 0, MetaTileType::H, 2),
+    edge(4, 4, MetaTileType::P, 2),
+    edge(0, 4, MetaTileType::F, 3),
+    edge(2, 4, MetaTileType::F, 3),
+    span(4, 1, 3, 2, MetaTileType::F, 0),
+    edge(8, 3, MetaTileType::H, 0),
+    edge(9, 2, MetaTileType::P, 0),
+    edge(10, 2, MetaTileType::H, 0),
+    edge(11, 4, MetaTileType::P, 2),
+    edge(12, 0, MetaTileType::H, 2),
+    edge(13, 0, MetaTileType::F, 3),
+    edge(14, 2, MetaTileType::F, 1),
+    edge(15, 3, MetaTileType::H, 4),
+    edge(8, 2, MetaTileType::F, 1),
+    edge(17, 3, MetaTileType::H, 0),
+    edge(18, 2, MetaTileType::P, 0),
+    edge(19, 2, MetaTileType::H, 2),
+    edge(20, 4, MetaTileType::F, 3),
+    edge(20, 0, MetaTileType::P, 2),
+    edge(22, 0, MetaTileType::H, 2),
+    edge(23, 4, MetaTileType::F, 3),
+    edge(23, 0, MetaTileType::F, 3),
+    edge(16, 0, MetaTileType::P, 2),
+    span(9, 4, 0, 2, MetaTileType::T, 2),
+    edge(4, 0, MetaTileType::F, 3)
+];
+
+// Glue copies of the current metatiles together into a patch big enough to contain one of each
+// of the next level's metatiles
+fn construct_patch(metatiles: &MetaTileSet) -> MetaTile {
+    let mut patch = MetaTile::new(Vec::new());
+
+    for rule in PATCH_RULES.iter() {
+        match *rule {
+            PatchRule::Root(meta_type) => {
+                patch.add_child(Affine2::IDENTITY, HatGeometry::Meta(metatiles[meta_type as usize].clone()));
+            },
+            PatchRule::Edge { child, edge, new_type, new_edge } => {
+                let (transform, geometry) = &patch.children[child];
+                let shape = geometry.get_shape();
+                let p = transform.transform_point(shape[(edge + 1) % shape.len()]);
+                let q = transform.transform_point(shape[edge]);
+
+                let new_shape = &metatiles[new_type as usize].shape;
+                let placement = Affine2::match_two(new_shape[new_edge], new_shape[(new_edge + 1) % new_shape.len()], p, q);
+                patch.add_child(placement, HatGeometry::Meta(metatiles[new_type as usize].clone()));
+            },
+            PatchRule::Span { child_p, vertex_p, child_q, vertex_q, new_type, new_edge } => {
+                let p = patch.eval_child(child_q, vertex_q);
+                let q = patch.eval_child(child_p, vertex_p);
+
+                let new_shape = &metatiles[new_type as usize].shape;
+                let placement = Affine2::match_two(new_shape[new_edge], new_shape[(new_edge + 1) % new_shape.len()], p, q);
+                patch.add_child(placement, HatGeometry::Meta(metatiles[new_type as usize].clone()));
+            }
+        }
+    }
+
+    patch
+}
+
+// Carve the next level's H, T, P and F metatiles out of the patch
+fn construct_metatiles(patch: &MetaTile) -> MetaTileSet {
+    let third_turn = -2.0 * std::f32::consts::PI / 3.0;
+    let sixth_turn = -std::f32::consts::PI / 3.0;
+
+    let bps1 = patch.eval_child(8, 2);
+    let bps2 = patch.eval_child(21, 2);
+    let rbps = Affine2::rotate_about(bps1, third_turn).transform_point(bps2);
+
+    let p72 = patch.eval_child(7, 2);
+    let p252 = patch.eval_child(25, 2);
+
+    let llc = line_intersection(bps1, rbps, patch.eval_child(6, 2), p72);
+    let mut w = patch.eval_child(6, 2) - llc;
+
+    let mut h_outline = vec![llc, bps1];
+    w = Affine2::rotation(sixth_turn).transform_point(w);
+    h_outline.push(h_outline[1] + w);
+    h_outline.push(patch.eval_child(14, 2));
+    w = Affine2::rotation(sixth_turn).transform_point(w);
+    h_outline.push(h_outline[3] - w);
+    h_outline.push(patch.eval_child(6, 2));
+
+    let copy_children = |outline: Vec<Vec2>, children: &[usize]| {
+        let mut meta = MetaTile::new(outline);
+        for &child in children {
+            let (transform, geometry) = &patch.children[child];
+            meta.add_child(*transform, geometry.clone());
+        }
+        meta
+    };
+
+    let t_a = h_outline[2];
+    let t_b = h_outline[1] + (h_outline[4] - h_outline[5]);
+    let t_c = Affine2::rotate_about(t_b, sixth_turn).transform_point(t_a);
+
+    let mut h = copy_children(h_outline, &[0, 9, 16, 27, 26, 6, 1, 8, 10, 15]);
+    let mut t = copy_children(vec![t_b, t_c, t_a], &[11]);
+    let mut p = copy_children(vec![p72, p72 + (bps1 - llc), bps1, llc], &[7, 2, 3, 4, 28]);
+    let mut f = copy_children(
+        vec![bps2, patch.eval_child(24, 2), patch.eval_child(25, 0), p252, p252 + (llc - bps1)],
+        &[21, 20, 22, 23, 24, 25]
+    );
+
+    h.recentre();
+    t.recentre();
+    p.recentre();
+    f.recentre();
+
+    [Arc::new(h), Arc::new(t), Arc::new(p), Arc::new(f)]
+}
+
+pub struct HatTiler {
+    levels: Vec<MetaTileSet>,
+    current_level: usize,
+    hats: Vec<(HatType, Affine2)>,
+    // Hats grown one at a time instead, with the same growth as the Penrose rhombs
+    tiling: Tiling<GrownHatType, GrownHatTile>,
+    growth: Growth<GrownHatType, GrownHatTile>,
+    entities: Vec<Entity>
+}
+
+impl Default for HatTiler {
+    fn default() -> Self {
+        HatTiler {
+            levels: vec![make_initial_metatiles()],
+            current_level: 0,
+            hats: Vec::new(),
+            tiling: Tiling::default(),
+            growth: Growth::default(),
+            entities: Vec::new()
+        }
+    }
+}
+
+impl HatTiler {
+    fn ensure_level(&mut self, level: usize) {
+        while self.levels.len() <= level {
+            let patch = construct_patch(self.levels.last().unwrap());
+            self.levels.push(construct_metatiles(&patch));
+        }
+    }
+
+    fn clear(&mut self, commands: &mut Commands) {
+        for entity in self.entities.drain(..) {
+            commands.entity(entity).despawn_recursive();
+        }
+        self.hats.clear();
+        self.tiling = Tiling::default();
+    }
+
+    // Replace whatever is on screen with the H metatile of the given level
+    fn show_level(&mut self, level: usize, commands: &mut Commands) {
+        self.ensure_level(level);
+        self.clear(commands);
+
+        self.current_level = level;
+        let h = &self.levels[level][MetaTileType::H as usize];
+        h.collect_hats(Affine2::scale(HAT_WORLD_SCALE), &mut self.hats);
+
+        for (hat_type, placement) in &self.hats {
+            let (tile, transform) = HatTile::new(*hat_type, placement);
+            let mut entity = commands.spawn();
+            let id = entity.id();
+            tile.insert_shape_component(transform, &mut entity);
+            entity.insert(tile.clone());
+            tile.spawn_dots_entities(id, commands);
+            self.entities.push(id);
+        }
+
+        println!("Level {}: {} hats", level, self.hats.len());
+        for hat_type in HatType::get_all() {
+            let count = self.hats.iter().filter(|(t, _)| *t == hat_type).count();
+            println!("  {}: {}", hat_type, count);
+        }
+    }
+
+    // Grow from a single hat in place of the metatiles, or carry on growing
+    fn grow(&mut self, steps: usize, commands: &mut Commands) {
+        if self.tiling.get_topology().get_num_tiles() == 0 {
+            self.clear(commands);
+        }
+        for entity in growth::grow(&mut self.tiling, &mut self.growth, steps, commands) {
+            self.spawn_tile(entity, commands);
+        }
+    }
+
+    fn spawn_tile(&mut self, entity: Entity, commands: &mut Commands) {
+        if let Some(tile) = self.tiling.get_tile(entity) {
+            spawn_tile(tile, entity, commands);
+            self.entities.push(entity);
+        }
+    }
+
+    // Pairwise non-convex overlap check over the whole patch, grown or substituted
+    fn count_overlaps(&self) -> usize {
+        let polygons: Vec<Vec<Vec2>> = if self.tiling.get_topology().get_num_tiles() > 0 {
+            self.tiling.get_tiles().map(|(_, tile)| tile.get_world_points()).collect()
+        } else {
+            self.hats.iter().map(|(_, placement)| placement.transform_points(&HAT_OUTLINE)).collect()
+        };
+
+        let pairs = geometry::find_overlapping_pairs(&polygons);
+        for (i, j) in &pairs {
+            println!("  Hats {} and {} overlap", i, j);
+        }
+        pairs.len()
+    }
+}
+
+pub fn setup_hat(mut commands: Commands) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+
+    let mut tiler = HatTiler::default();
+    tiler.show_level(0, &mut commands);
+    commands.insert_resource(tiler);
+}
+
+pub fn hat_controls(
+    mut tiler: ResMut<HatTiler>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        let level = tiler.current_level + 1;
+        tiler.show_level(level, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::U) && tiler.current_level > 0 {
+        let level = tiler.current_level - 1;
+        tiler.show_level(level, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::Return) {
+        tiler.grow(GROWTH_STEPS, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::G) {
+        tiler.growth.next_strategy();
+        println!("Growth strategy: {}", tiler.growth.get_strategy_name());
+    } else if keyboard_input.just_pressed(KeyCode::V) {
+        let overlaps = tiler.count_overlaps();
+        println!("{} overlapping hats out of {}", overlaps, tiler.entities.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substituted_hats_only_meet_on_listed_side_pairs() {
+        let counts = count_side_pairs_in_patch(2);
+        assert!(!counts.is_empty());
+        assert!(counts.keys().all(|pair| HAT_SIDE_PAIRS.contains(pair)));
+    }
+
+    #[test]
+    fn every_side_takes_both_hats() {
+        for onto_type in GrownHatType::get_all() {
+            for side in 0..HAT_OUTLINE.len() as u8 {
+                for other_type in GrownHatType::get_all() {
+                    let other_side = GrownHatTile::get_matching_side(onto_type, side, other_type).unwrap();
+                    assert!(GrownHatTile::sides_can_meet(onto_type, side, other_type, other_side).unwrap());
+                }
+            }
+        }
+    }
+}
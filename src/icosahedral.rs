@@ -0,0 +1,392 @@
+use bevy::prelude::*;
+use bevy::input::{keyboard::KeyCode, Input};
+use bevy::render::camera::Camera;
+use bevy::render::mesh::Indices;
+use bevy::render::pipeline::PrimitiveTopology;
+use rand::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use strum_macros::Display;
+
+// The three-dimensional Penrose tiling of Ammann's golden rhombohedra, the model of an icosahedral
+// quasicrystal. It comes from the multigrid construction one dimension up: six families of parallel planes
+// with unit normals along the six fivefold axes of the icosahedron, family j shifted by offsets[j]. Every
+// point where planes of three families meet becomes a rhombohedron with edges along the normals of the
+// three families, prolate or oblate depending on which three they are.
+//
+// Vertices come out as points of Z^6 taken to 3D by the normals, the same points cutting and projecting the
+// 6D cubic lattice would give. Lengths are in units of the rhombohedron edge.
+
+const ICOSAHEDRAL_RADIUS: i32 = 3;
+// Each cell is drawn shrunk towards its centre so the cells inside the patch show through the gaps
+const CELL_SCALE: f32 = 0.8;
+const ORBIT_STEP: f32 = 0.03;
+const ZOOM_STEP: f32 = 1.02;
+const OBJ_PATH: &str = "icosahedral.obj";
+
+pub type LatticePoint = [i32; 6];
+
+#[derive(Display, Clone, Copy, PartialEq, Debug)]
+pub enum RhombohedronType {
+    Prolate,
+    Oblate
+}
+
+impl RhombohedronType {
+    fn get_all() -> [Self; 2] {
+        [RhombohedronType::Prolate, RhombohedronType::Oblate]
+    }
+
+    // Volume of a rhombohedron with unit edges, from the cosine of the angle between the edges at the corner
+    // where all three angles are the same
+    fn get_volume(&self) -> f32 {
+        let c = match self {
+            RhombohedronType::Prolate => 1.0 / 5.0f32.sqrt(),
+            RhombohedronType::Oblate => -1.0 / 5.0f32.sqrt()
+        };
+        (1.0 - 3.0 * c * c + 2.0 * c * c * c).sqrt()
+    }
+
+    fn get_color(&self) -> Color {
+        match self {
+            RhombohedronType::Prolate => Color::rgb(0.9, 0.6, 0.3),
+            RhombohedronType::Oblate => Color::rgb(0.3, 0.6, 0.9)
+        }
+    }
+}
+
+pub struct IcosahedralGrid {
+    normals: [Vec3; 6],
+    offsets: [f32; 6]
+}
+
+// A rhombohedron of the dual tiling. Corner c steps once along the first of its three families from the
+// corner with the lowest coordinates if bit 0 of c is set, along the second for bit 1 and the third for bit 2.
+pub struct GridRhombohedron {
+    pub rhombohedron_type: RhombohedronType,
+    pub vertices: [LatticePoint; 8]
+}
+
+impl IcosahedralGrid {
+    pub fn new(offsets: [f32; 6]) -> Self {
+        let phi = (1.0 + 5.0f32.sqrt()) / 2.0;
+        let axes = [
+            Vec3::new(0.0, 1.0, phi), Vec3::new(0.0, -1.0, phi), Vec3::new(1.0, phi, 0.0),
+            Vec3::new(-1.0, phi, 0.0), Vec3::new(phi, 0.0, 1.0), Vec3::new(phi, 0.0, -1.0)
+        ];
+        let mut normals = [Vec3::ZERO; 6];
+        for (normal, axis) in normals.iter_mut().zip(axes.iter()) {
+            *normal = axis.normalize();
+        }
+        IcosahedralGrid {
+            normals,
+            offsets
+        }
+    }
+
+    // Random offsets are generic with probability 1, so no four planes meet in a point
+    pub fn new_random() -> Self {
+        let mut rng = rand::thread_rng();
+        let mut offsets = [0.0; 6];
+        for offset in offsets.iter_mut() {
+            *offset = rng.gen::<f32>();
+        }
+        IcosahedralGrid::new(offsets)
+    }
+
+    pub fn to_world(&self, point: &LatticePoint) -> Vec3 {
+        let mut p = Vec3::ZERO;
+        for (i, normal) in self.normals.iter().enumerate() {
+            p += *normal * point[i] as f32;
+        }
+        p
+    }
+
+    // Any two of the axes are at the same angle, one way round or the other. Three of them make a prolate
+    // rhombohedron when their signs can be picked to make all three angles acute.
+    fn get_type(&self, i: usize, j: usize, k: usize) -> RhombohedronType {
+        let (a, b, c) = (self.normals[i], self.normals[j], self.normals[k]);
+        if a.dot(b) * b.dot(c) * c.dot(a) > 0.0 {
+            RhombohedronType::Prolate
+        } else {
+            RhombohedronType::Oblate
+        }
+    }
+
+    // All rhombohedra for meeting points within `radius` of the origin
+    pub fn dual_cells(&self, radius: i32) -> Vec<GridRhombohedron> {
+        let mut cells = Vec::new();
+
+        for i in 0..6 {
+            for j in (i + 1)..6 {
+                for k in (j + 1)..6 {
+                    let families = [i, j, k];
+                    let inverse = Mat3::from_cols(self.normals[i], self.normals[j], self.normals[k]).transpose().inverse();
+                    let rhombohedron_type = self.get_type(i, j, k);
+                    for n_i in -radius..=radius {
+                        for n_j in -radius..=radius {
+                            for n_k in -radius..=radius {
+                                // Solve x . normal_f + offset_f = n_f for the three families
+                                let numbers = [n_i, n_j, n_k];
+                                let meeting = inverse * Vec3::new(
+                                    n_i as f32 - self.offsets[i],
+                                    n_j as f32 - self.offsets[j],
+                                    n_k as f32 - self.offsets[k]
+                                );
+                                if meeting.length() > radius as f32 {
+                                    continue;
+                                }
+
+                                let mut base = [0; 6];
+                                for (m, coordinate) in base.iter_mut().enumerate() {
+                                    *coordinate = match families.iter().position(|f| *f == m) {
+                                        Some(index) => numbers[index] - 1,
+                                        None => (meeting.dot(self.normals[m]) + self.offsets[m]).floor() as i32
+                                    };
+                                }
+
+                                let mut vertices = [base; 8];
+                                for (corner, vertex) in vertices.iter_mut().enumerate() {
+                                    for (bit, family) in families.iter().enumerate() {
+                                        if corner & (1 << bit) != 0 {
+                                            vertex[*family] += 1;
+                                        }
+                                    }
+                                }
+
+                                cells.push(GridRhombohedron {
+                                    rhombohedron_type,
+                                    vertices
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        cells
+    }
+}
+
+// The six faces of a rhombohedron as corner indices, going anticlockwise seen from outside
+fn get_outward_faces(corners: &[Vec3; 8]) -> Vec<[usize; 4]> {
+    let centre = corners.iter().fold(Vec3::ZERO, |sum, p| sum + *p) / 8.0;
+    let mut faces = Vec::new();
+    for bit in 0..3 {
+        let (c, d) = (1 << ((bit + 1) % 3), 1 << ((bit + 2) % 3));
+        for side in 0..2 {
+            let base = side << bit;
+            let mut face = [base, base | c, base | c | d, base | d];
+            let normal = (corners[face[1]] - corners[face[0]]).cross(corners[face[3]] - corners[face[0]]);
+            if normal.dot(corners[face[0]] - centre) < 0.0 {
+                face.reverse();
+            }
+            faces.push(face);
+        }
+    }
+    faces
+}
+
+pub struct IcosahedralTiler {
+    grid: IcosahedralGrid,
+    cells: Vec<GridRhombohedron>,
+    entities: Vec<Entity>,
+    // Camera position round the origin
+    yaw: f32,
+    pitch: f32,
+    distance: f32
+}
+
+impl Default for IcosahedralTiler {
+    fn default() -> Self {
+        IcosahedralTiler {
+            grid: IcosahedralGrid::new_random(),
+            cells: Vec::new(),
+            entities: Vec::new(),
+            yaw: 0.5,
+            pitch: 0.4,
+            distance: 6.0 * ICOSAHEDRAL_RADIUS as f32
+        }
+    }
+}
+
+impl IcosahedralTiler {
+    fn get_camera_transform(&self) -> Transform {
+        let direction = Vec3::new(self.yaw.cos() * self.pitch.cos(), self.pitch.sin(), self.yaw.sin() * self.pitch.cos());
+        Transform::from_translation(direction * self.distance).looking_at(Vec3::ZERO, Vec3::Y)
+    }
+
+    // All cells of one type in a single mesh, flat shaded
+    fn build_mesh(&self, rhombohedron_type: RhombohedronType) -> Mesh {
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        let mut indices = Vec::new();
+        for cell in self.cells.iter().filter(|cell| cell.rhombohedron_type == rhombohedron_type) {
+            let mut corners = [Vec3::ZERO; 8];
+            for (corner, vertex) in corners.iter_mut().zip(cell.vertices.iter()) {
+                *corner = self.grid.to_world(vertex);
+            }
+            let centre = corners.iter().fold(Vec3::ZERO, |sum, p| sum + *p) / 8.0;
+            for face in get_outward_faces(&corners) {
+                let points: Vec<Vec3> = face.iter().map(|c| centre + (corners[*c] - centre) * CELL_SCALE).collect();
+                let normal = (points[1] - points[0]).cross(points[3] - points[0]).normalize();
+                let first = positions.len() as u32;
+                for (point, uv) in points.iter().zip(&[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]) {
+                    positions.push([point.x, point.y, point.z]);
+                    normals.push([normal.x, normal.y, normal.z]);
+                    uvs.push(*uv);
+                }
+                indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+            }
+        }
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+
+    fn regenerate(&mut self, commands: &mut Commands, meshes: &mut Assets<Mesh>, materials: &mut Assets<StandardMaterial>) {
+        for entity in self.entities.drain(..) {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        self.grid = IcosahedralGrid::new_random();
+        self.cells = self.grid.dual_cells(ICOSAHEDRAL_RADIUS);
+        for rhombohedron_type in RhombohedronType::get_all().iter() {
+            let entity = commands.spawn_bundle(PbrBundle {
+                mesh: meshes.add(self.build_mesh(*rhombohedron_type)),
+                material: materials.add(rhombohedron_type.get_color().into()),
+                ..Default::default()
+            }).id();
+            self.entities.push(entity);
+        }
+
+        self.print_statistics();
+    }
+
+    // Counts and volume shares per type. The prolate to oblate ratio should approach the golden ratio.
+    fn print_statistics(&self) {
+        println!("Icosahedral tiling, {} rhombohedra", self.cells.len());
+        let total_volume: f32 = self.cells.iter().map(|cell| cell.rhombohedron_type.get_volume()).sum();
+        for rhombohedron_type in RhombohedronType::get_all().iter() {
+            let count = self.cells.iter().filter(|cell| cell.rhombohedron_type == *rhombohedron_type).count();
+            let volume = count as f32 * rhombohedron_type.get_volume();
+            println!("  {}: {} ({:.3} of cells, {:.3} of volume)", rhombohedron_type, count,
+                count as f32 / self.cells.len() as f32, volume / total_volume);
+        }
+    }
+
+    // Wavefront OBJ with unit edges and unshrunk cells, one group per type. Cells share the vertices they
+    // meet at, each written once, with its Z^6 coordinates in a comment before it.
+    fn export_obj(&self, path: &str) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# Icosahedral tiling of golden rhombohedra, {} cells", self.cells.len())?;
+
+        let mut indices: HashMap<LatticePoint, usize> = HashMap::new();
+        for cell in &self.cells {
+            for vertex in &cell.vertices {
+                if !indices.contains_key(vertex) {
+                    let p = self.grid.to_world(vertex);
+                    writeln!(out, "# {:?}", vertex)?;
+                    writeln!(out, "v {} {} {}", p.x, p.y, p.z)?;
+                    indices.insert(*vertex, indices.len() + 1);
+                }
+            }
+        }
+
+        for rhombohedron_type in RhombohedronType::get_all().iter() {
+            writeln!(out, "g {}", rhombohedron_type.to_string().to_lowercase())?;
+            for cell in self.cells.iter().filter(|cell| cell.rhombohedron_type == *rhombohedron_type) {
+                let mut corners = [Vec3::ZERO; 8];
+                for (corner, vertex) in corners.iter_mut().zip(cell.vertices.iter()) {
+                    *corner = self.grid.to_world(vertex);
+                }
+                for face in get_outward_faces(&corners) {
+                    let face: Vec<String> = face.iter().map(|c| indices[&cell.vertices[*c]].to_string()).collect();
+                    writeln!(out, "f {}", face.join(" "))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn setup_icosahedral(
+    mut commands: Commands,
+    mut tiler: ResMut<IcosahedralTiler>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>
+) {
+    commands.spawn_bundle(PerspectiveCameraBundle {
+        transform: tiler.get_camera_transform(),
+        ..Default::default()
+    }).with_children(|parent| {
+        parent.spawn_bundle(LightBundle {
+            light: Light {
+                intensity: 2000.0,
+                range: 200.0,
+                ..Default::default()
+            },
+            transform: Transform::from_xyz(5.0, 10.0, 0.0),
+            ..Default::default()
+        });
+    });
+    tiler.regenerate(&mut commands, &mut meshes, &mut materials);
+}
+
+pub fn icosahedral_controls(
+    mut tiler: ResMut<IcosahedralTiler>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut cameras: Query<&mut Transform, With<Camera>>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        tiler.regenerate(&mut commands, &mut meshes, &mut materials);
+    } else if keyboard_input.just_pressed(KeyCode::E) {
+        match tiler.export_obj(OBJ_PATH) {
+            Ok(()) => println!("Wrote {} rhombohedra to {}", tiler.cells.len(), OBJ_PATH),
+            Err(error) => println!("Can't write {}: {}", OBJ_PATH, error)
+        }
+    }
+
+    // Arrow keys orbit the camera round the patch, + and - move it closer and further
+    let mut moved = false;
+    let limit = std::f32::consts::FRAC_PI_2 - 0.01;
+    if keyboard_input.pressed(KeyCode::Left) {
+        tiler.yaw += ORBIT_STEP;
+        moved = true;
+    }
+    if keyboard_input.pressed(KeyCode::Right) {
+        tiler.yaw -= ORBIT_STEP;
+        moved = true;
+    }
+    if keyboard_input.pressed(KeyCode::Up) {
+        tiler.pitch = (tiler.pitch + ORBIT_STEP).min(limit);
+        moved = true;
+    }
+    if keyboard_input.pressed(KeyCode::Down) {
+        tiler.pitch = (tiler.pitch - ORBIT_STEP).max(-limit);
+        moved = true;
+    }
+    if keyboard_input.pressed(KeyCode::Equals) {
+        tiler.distance /= ZOOM_STEP;
+        moved = true;
+    }
+    if keyboard_input.pressed(KeyCode::Minus) {
+        tiler.distance *= ZOOM_STEP;
+        moved = true;
+    }
+    if moved {
+        for mut transform in cameras.iter_mut() {
+            *transform = tiler.get_camera_transform();
+        }
+    }
+}
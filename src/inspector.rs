@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+
+use crate::tiling::Tiling;
+use crate::{PenroseEnum, Tile};
+
+// Click near an open edge to see every tile type that could go on it as a ghost outline, coloured by the
+// verdict: green allowed, yellow edge already shared, orange mismatched side, pink linked corner mismatch,
+// red overlap, purple illegal vertex. The reasons are printed as well.
+
+const PICK_DISTANCE: f32 = 60.0;
+
+#[derive(Default)]
+pub struct PlacementInspector {
+    ghosts: Vec<Entity>
+}
+
+impl PlacementInspector {
+    pub fn clear(&mut self, commands: &mut Commands) {
+        for entity in self.ghosts.drain(..) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    pub fn inspect<P: PenroseEnum, T: Tile<P>>(&mut self, tiling: &Tiling<P, T>, position: Vec2, commands: &mut Commands) {
+        self.clear(commands);
+
+        let nearest = tiling.get_frontier().get_open_edges()
+            .map(|edge| (edge, ((edge.start + edge.end) / 2.0).distance(position)))
+            .filter(|(_, distance)| *distance < PICK_DISTANCE)
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let edge = match nearest {
+            Some((edge, _)) => edge,
+            None => {
+                println!("No open edge near {:?}", position);
+                return;
+            }
+        };
+
+        let checks = match tiling.inspect_side(edge.entity, edge.side) {
+            Ok(checks) => checks,
+            Err(error) => {
+                println!("Can't inspect side {} of {:?}: {}", edge.side, edge.entity, error);
+                return;
+            }
+        };
+
+        println!("Side {} of {:?}:", edge.side, edge.entity);
+        for (penrose_type, check) in checks {
+            println!("  {}: {}", penrose_type, check);
+            let ghost = match edge.tile.get_connected_tile(edge.side, penrose_type) {
+                Ok(ghost) => ghost,
+                Err(_) => continue
+            };
+            let mut transform = ghost.get_transform();
+            transform.translation.z = 3.0;
+
+            let mut entity = commands.spawn();
+            entity.insert_bundle(
+                GeometryBuilder::build_as(
+                    &shapes::Polygon {
+                        points: ghost.get_points(),
+                        closed: true
+                    },
+                    ShapeColors::new(check.get_color()),
+                    DrawMode::Stroke(StrokeOptions::default().with_line_width(3.0)),
+                    transform
+                )
+            );
+            self.ghosts.push(entity.id());
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use bevy::prelude::*;
+use std::ops::{Add, Sub};
+
+// Every vertex of a Penrose rhomb tiling is an integer combination of the unit vectors at multiples of
+// 72 degrees. Those five vectors sum to zero, so only the first four are stored, which keeps the
+// coordinates unique and lets points be compared and hashed exactly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct PentagonalPoint([i32; 4]);
+
+impl PentagonalPoint {
+    pub const ORIGIN: PentagonalPoint = PentagonalPoint([0, 0, 0, 0]);
+
+    // Unit vector at k * 36 degrees
+    pub fn unit(k: i32) -> Self {
+        let k = k.rem_euclid(10);
+        if k % 2 == 1 {
+            return PentagonalPoint::ORIGIN - PentagonalPoint::unit(k + 5);
+        }
+
+        let j = (k / 2) as usize;
+        if j < 4 {
+            let mut coords = [0; 4];
+            coords[j] = 1;
+            PentagonalPoint(coords)
+        } else {
+            PentagonalPoint([-1, -1, -1, -1])
+        }
+    }
+
+    // 2 cos 36 degrees is the golden ratio, so scaling by it turns each unit vector into the sum of its two
+    // neighbours and stays on the lattice
+    pub fn times_phi(&self) -> Self {
+        let mut coords = [0; 4];
+        for j in 0..4 {
+            let k = 2 * j as i32;
+            let step = PentagonalPoint::unit(k - 1) + PentagonalPoint::unit(k + 1);
+            for (coord, s) in coords.iter_mut().zip(step.0.iter()) {
+                *coord += self.0[j] * s;
+            }
+        }
+        PentagonalPoint(coords)
+    }
+
+    // Dividing by the golden ratio is multiplying by its inverse, which is the golden ratio less one
+    pub fn over_phi(&self) -> Self {
+        self.times_phi() - *self
+    }
+
+    // A point of Z^5 that the unit vectors at 72k degrees take to this one. Any other differs by the same
+    // number in every coordinate, since the five unit vectors sum to zero.
+    pub fn get_coordinates_5d(&self) -> [i32; 5] {
+        [self.0[0], self.0[1], self.0[2], self.0[3], 0]
+    }
+
+    // Sum of the 5D coordinates, the same modulo 5 whichever point of Z^5 is taken
+    pub fn get_index_class(&self) -> i32 {
+        self.get_coordinates_5d().iter().sum::<i32>().rem_euclid(5)
+    }
+
+    // The 5D coordinates projected into perpendicular space, where the unit vector at 72k degrees is sent to
+    // the one at 144k degrees
+    pub fn to_perp(self) -> Vec2 {
+        let mut p = Vec2::ZERO;
+        for (k, coordinate) in self.get_coordinates_5d().iter().enumerate() {
+            let angle = f32::to_radians(144.0 * k as f32);
+            p += Vec2::new(angle.cos(), angle.sin()) * (*coordinate as f32);
+        }
+        p
+    }
+
+    pub fn to_world(self, leg_len: f32) -> Vec2 {
+        let mut p = Vec2::ZERO;
+        for j in 0..4 {
+            let angle = f32::to_radians(72.0 * j as f32);
+            p += Vec2::new(angle.cos(), angle.sin()) * (self.0[j] as f32);
+        }
+        p * leg_len
+    }
+}
+
+impl Add for PentagonalPoint {
+    type Output = PentagonalPoint;
+    fn add(self, rhs: PentagonalPoint) -> PentagonalPoint {
+        PentagonalPoint([self.0[0] + rhs.0[0], self.0[1] + rhs.0[1], self.0[2] + rhs.0[2], self.0[3] + rhs.0[3]])
+    }
+}
+
+impl Sub for PentagonalPoint {
+    type Output = PentagonalPoint;
+    fn sub(self, rhs: PentagonalPoint) -> PentagonalPoint {
+        PentagonalPoint([self.0[0] - rhs.0[0], self.0[1] - rhs.0[1], self.0[2] - rhs.0[2], self.0[3] - rhs.0[3]])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHI: f32 = 1.618_034;
+
+    fn assert_close(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 0.0001, "{:?} is not {:?}", a, b);
+    }
+
+    #[test]
+    fn unit_vectors_match_their_angles() {
+        for k in -10..20 {
+            let angle = f32::to_radians(36.0 * k as f32);
+            assert_close(PentagonalPoint::unit(k).to_world(1.0), Vec2::new(angle.cos(), angle.sin()));
+            assert_eq!(PentagonalPoint::unit(k) + PentagonalPoint::unit(k + 5), PentagonalPoint::ORIGIN);
+        }
+        let sum = (0..5).fold(PentagonalPoint::ORIGIN, |sum, j| sum + PentagonalPoint::unit(2 * j));
+        assert_eq!(sum, PentagonalPoint::ORIGIN);
+    }
+
+    #[test]
+    fn scaling_by_phi_stays_on_the_lattice() {
+        let p = PentagonalPoint::unit(0) + PentagonalPoint::unit(3) - PentagonalPoint::unit(7);
+        assert_close(p.times_phi().to_world(1.0), p.to_world(PHI));
+        assert_close(p.over_phi().to_world(1.0), p.to_world(1.0 / PHI));
+        assert_eq!(p.times_phi().over_phi(), p);
+    }
+
+    #[test]
+    fn index_class_counts_steps_along_even_directions() {
+        assert_eq!(PentagonalPoint::ORIGIN.get_index_class(), 0);
+        for j in 0..5 {
+            assert_eq!(PentagonalPoint::unit(2 * j).get_index_class(), 1);
+            assert_eq!(PentagonalPoint::unit(2 * j + 1).get_index_class(), 4);
+        }
+    }
+}
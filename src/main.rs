@@ -4,10 +4,24 @@ use bevy::math::Vec2;
 use bevy::prelude::Commands;
 use bevy::ecs::system::EntityCommands;
 use bevy::ecs::component::Component;
-use bevy::input::{keyboard::KeyCode, Input};
-use num_traits::FromPrimitive;
-use rand::prelude::*;
-use strum_macros::Display;
+use bevy::input::{keyboard::KeyCode, mouse::MouseButton, Input};
+use strum_macros::{Display, EnumString};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use composition::{Composition, SupertileOverlay};
+use dcel::{FaceId, HalfEdgeId, HalfEdgeMesh};
+use growth::{Growth, TypeWeights};
+use inspector::PlacementInspector;
+use lattice::PentagonalPoint;
+use multigrid::RhombAngles;
+use osds::{OsdsGrowth, OsdsStep};
+use perp_space::PerpSpaceOverlay;
+use propagation::propagate_forced;
+use seeds::SeedLibrary;
+use tiling::{PlacementCheck, Tiling, TilingError, UndoStack};
 
 #[macro_use]
 extern crate lazy_static;
@@ -16,156 +30,29 @@ extern crate lazy_static;
 extern crate enum_primitive_derive;
 extern crate num_traits;
 
-lazy_static! {
-    static ref ROTATION_TRANSFORMS: Vec<Vec<Vec<Transform>>> = {
-        // Create a rhombus centered at the origin
-        let r_fat = Rhombus::new_fat();
-        let r_skinny = Rhombus::new_skinny();
-        let fat_points = r_fat.get_points();
-        let skinny_points = r_skinny.get_points();
-
-        let fat_small_diag_len = r_fat.leg_len * (2.0 - 2.0 * r_fat.small_angle.cos()).sqrt();
-        let fat_half_small_diag = fat_small_diag_len / 2.0;
-
-        let skinny_small_diag_len = r_skinny.leg_len * (2.0 - 2.0 * r_skinny.small_angle.cos()).sqrt();
-        let skinny_half_small_diag = skinny_small_diag_len / 2.0;
-
-        let mut v = Vec::<Vec::<Vec::<Transform>>>::new();
-        v.push(Vec::<Vec::<Transform>>::new());
-        {
-            let fat = &mut v[0];
-            {
-                fat.push(Vec::<Transform>::new());
-                {
-                    let fat_vert_angle = f32::to_radians(180.0 - Rhombus::FAT_LARGE_ANGLE / 2.0 - Rhombus::FAT_LARGE_ANGLE / 2.0);
-                    let fat_fat_sides = &mut fat[0];
-                    {
-                        // Fat on Fat on side 0
-                        fat_fat_sides.push(
-                            make_rotation_transform(fat_vert_angle, fat_points[r_fat.get_top_index()], fat_half_small_diag)
-                        );
-                    }
-                    {
-                        // Fat on Fat on side 1
-                        fat_fat_sides.push(
-                            make_rotation_transform(-fat_vert_angle, fat_points[r_fat.get_top_index()], fat_half_small_diag)
-                        );
-                    }
-                    {
-                        // Fat on Fat on side 2
-                        fat_fat_sides.push(
-                            make_rotation_transform(fat_vert_angle, fat_points[r_fat.get_bottom_index()], -fat_half_small_diag)
-                        );
-                    }
-                    {
-                        // Fat on Fat on side 3
-                        fat_fat_sides.push(
-                            make_rotation_transform(-fat_vert_angle, fat_points[r_fat.get_bottom_index()], -fat_half_small_diag)
-                        );
-                    }
-                }
-                fat.push(Vec::<Transform>::new());
-                {
-                    let skinny_fat_horizontal_angle = f32::to_radians(180.0 + Rhombus::FAT_SMALL_ANGLE / 2.0 - Rhombus::SKINNY_SMALL_ANGLE / 2.0);
-                    let skinny_fat_vert_angle = f32::to_radians(180.0 - Rhombus::SKINNY_LARGE_ANGLE / 2.0 - Rhombus::FAT_LARGE_ANGLE / 2.0);
-                    let skinny_fat_sides = &mut fat[1];
-                    {
-                        // Skinny onto fat on side 0
-                        skinny_fat_sides.push(
-                            make_rotation_transform(skinny_fat_horizontal_angle, fat_points[r_fat.get_left_index()], -skinny_half_small_diag)
-                        );
-                    }
-                    {
-                        // Skinny onto fat on side 1
-                        skinny_fat_sides.push(
-                            make_rotation_transform(-skinny_fat_vert_angle, fat_points[r_fat.get_top_index()], skinny_half_small_diag)
-                        );
-                    }
-                    {
-                        // Skinny onto fat on side 2
-                        skinny_fat_sides.push(
-                            make_rotation_transform(skinny_fat_vert_angle + f32::to_radians(180.0), fat_points[r_fat.get_bottom_index()], skinny_half_small_diag)
-                        );
-                    }
-                    {
-                        // Skinny onto fat on side 3
-                        skinny_fat_sides.push(
-                            make_rotation_transform(-f32::to_radians(Rhombus::SKINNY_SMALL_ANGLE / 2.0), fat_points[r_fat.get_left_index()], -skinny_half_small_diag)
-                        );
-                    }
-                }
-            }
-        }
-
-        v.push(Vec::<Vec::<Transform>>::new());
-        {
-            let skinny = &mut v[1];
-            {
-                skinny.push(Vec::<Transform>::new());
-                {
-                    let fat_skinny_horizontal_angle = f32::to_radians(180.0 + Rhombus::FAT_LARGE_ANGLE / 2.0 - Rhombus::SKINNY_LARGE_ANGLE / 2.0);
-
-                    let skinny_fat_sides = &mut skinny[0];
-                    {
-                        // Fat on Skinny on side 0
-                        skinny_fat_sides.push(
-                            make_rotation_transform(fat_skinny_horizontal_angle, skinny_points[r_skinny.get_left_index()], -fat_half_small_diag)
-                        );
-                    }
-                    {
-                        // Fat on Skinny on side 1
-                        skinny_fat_sides.push(
-                            make_rotation_transform(f32::to_radians(Rhombus::SKINNY_SMALL_ANGLE / 2.0), skinny_points[r_skinny.get_right_index()], fat_half_small_diag)
-                        );
-                    }
-                    {
-                        // Fat on Skinny on side 2
-                        skinny_fat_sides.push(
-                            make_rotation_transform(f32::to_radians(Rhombus::FAT_LARGE_ANGLE / 2.0), skinny_points[r_skinny.get_bottom_index()], -fat_half_small_diag)
-                        );
-                    }
-                    {
-                        // Fat on Skinny on side 3
-                        skinny_fat_sides.push(
-                            make_rotation_transform(f32::to_radians(90.0 + Rhombus::FAT_LARGE_ANGLE / 2.0 - Rhombus::SKINNY_SMALL_ANGLE / 2.0), skinny_points[r_skinny.get_bottom_index()], fat_half_small_diag)
-                        );
-                    }
-                }
-                skinny.push(Vec::<Transform>::new());
-                {
-                    let skinny_skinny_angle = f32::to_radians(180.0 + 180.0 - Rhombus::SKINNY_LARGE_ANGLE / 2.0 - Rhombus::SKINNY_LARGE_ANGLE / 2.0);
-                    let skinny_skinny_sides = &mut skinny[1];
-                    {
-                        // Skinny -> Skinny on side 0
-                        skinny_skinny_sides.push(
-                            make_rotation_transform(skinny_skinny_angle, skinny_points[r_skinny.get_top_index()], -skinny_half_small_diag)
-                        );
-                    }
-                    {
-                        // Skinny -> Skinny on side 1
-                        skinny_skinny_sides.push(
-                            make_rotation_transform(-skinny_skinny_angle, skinny_points[r_skinny.get_top_index()], -skinny_half_small_diag)
-                        );
-                    }
-                    {
-                        // Skinny -> Skinny on side 2
-                        skinny_skinny_sides.push(
-                            make_rotation_transform(skinny_skinny_angle, skinny_points[r_skinny.get_bottom_index()], skinny_half_small_diag)
-                        );
-                    }
-                    {
-                        // Skinny -> Skinny on side 3
-                        skinny_skinny_sides.push(
-                            make_rotation_transform(-skinny_skinny_angle, skinny_points[r_skinny.get_bottom_index()], skinny_half_small_diag)
-                        );
-                    }
-                }
-            }
-        }
-        v
-    };
-}
-
+mod affine;
+mod ammann_beenker;
+mod composition;
+mod dcel;
+mod frontier;
+mod geometry;
+mod girih;
+mod growth;
+mod hat;
+mod icosahedral;
+mod inspector;
+mod lattice;
+mod multigrid;
+mod osds;
+mod outline_tile;
+mod perp_space;
+mod propagation;
+mod seeds;
+mod socolar_taylor;
+mod spectre;
+mod substitution;
+mod tiling;
+mod wang;
 
 trait PenroseEnum : Clone + Copy + std::fmt::Display {
     fn get_all() -> Vec<Self>;
@@ -173,21 +60,77 @@ trait PenroseEnum : Clone + Copy + std::fmt::Display {
 
 type PointList = Vec<Vec2>;
 
-trait Tile<P: PenroseEnum> : Component + Clone {
-    fn new_random() -> Self;
-    fn new(penrose_type: P) -> Self;
-    fn get_num_sides() -> usize;
-    fn get_matching_side(onto_type: P, onto_side: u8, other_type: P) -> u8;
+// Geometry and rendering, shared by every tile set whether it's grown edge by edge or generated some other way
+trait TileShape<P: PenroseEnum> : Component + Clone {
     fn insert_shape_component(&self, transform: Transform, entity_commands: &mut EntityCommands);
     fn spawn_dots_entities(&self, parent: Entity, commands: &mut Commands);
-    fn has_free_sides(&self) -> bool;
-    fn get_free_sides(&self) -> Vec<u8>;
-    fn set_side_used(&mut self, side: u8);
-    fn set_side_free(&mut self, side: u8);
-    fn get_side_used(&self, side: u8) -> bool;
     fn get_points(&self) -> PointList;
     fn get_type(&self) -> P;
-    fn get_connection_transform(&self, side: u8, other_type: P) -> Transform;
+}
+
+trait Tile<P: PenroseEnum> : TileShape<P> {
+    // Exact key of a corner, shared by every tile that meets there
+    type Vertex: Copy + Eq + Hash + Debug;
+
+    fn new_random(weights: &TypeWeights) -> Self;
+    // The most sides any tile of the set has
+    fn get_num_sides() -> usize;
+    // Sides of this particular tile, for sets whose shapes don't all have the same number
+    fn get_side_count(&self) -> usize {
+        Self::get_num_sides()
+    }
+    fn get_matching_side(onto_type: P, onto_side: u8, other_type: P) -> Result<u8, TilingError>;
+    // The types that can be placed next to this tile, for sets made of several that never mix
+    fn get_neighbour_types(&self) -> Vec<P> {
+        P::get_all()
+    }
+    // Whether a side of one tile may lie on a side of another when the two meet without being put down that
+    // way, by default only on the side get_matching_side gives
+    fn sides_can_meet(onto_type: P, onto_side: u8, other_type: P, other_side: u8) -> Result<bool, TilingError> {
+        Ok(other_side == Self::get_matching_side(onto_type, onto_side, other_type)?)
+    }
+    // Whether two sides that meet carry the same marks, for sets that match on more than which sides meet
+    fn sides_agree(_onto_type: P, _onto_side: u8, _other_type: P, _other_side: u8) -> bool {
+        true
+    }
+    // Corners of other tiles the matching rules link to corners of this one without the two sharing a side,
+    // as the corner of this tile and the vertex the linked corner is at. The linked tile is whichever tile
+    // at that vertex doesn't also have a corner at this one.
+    fn get_linked_vertices(&self) -> Vec<(u8, Self::Vertex)> {
+        Vec::new()
+    }
+    fn corners_agree(_onto_type: P, _onto_corner: u8, _other_type: P, _other_corner: u8) -> bool {
+        true
+    }
+    // A bit for each side with another tile on it
+    fn get_side_flags(&self) -> u32;
+    fn get_side_flags_mut(&mut self) -> &mut u32;
+    fn has_free_sides(&self) -> bool {
+        self.get_side_flags() != (1 << self.get_side_count()) - 1
+    }
+    fn set_side_used(&mut self, side: u8) {
+        *self.get_side_flags_mut() |= 1 << side;
+    }
+    fn set_side_free(&mut self, side: u8) {
+        *self.get_side_flags_mut() &= !(1 << side);
+    }
+    fn get_side_used(&self, side: u8) -> bool {
+        (self.get_side_flags() & (1 << side)) != 0
+    }
+    // A tile of other_type placed with its matching side on the given side of this one
+    fn get_connected_tile(&self, side: u8, other_type: P) -> Result<Self, TilingError>;
+    fn get_transform(&self) -> Transform;
+    // Corners in the same order as get_points
+    fn get_vertices(&self) -> Vec<Self::Vertex>;
+    // Sets whose tiles can meet along part of a side, with a corner of one part way along a side of the other
+    fn has_partial_contacts() -> bool {
+        false
+    }
+    // This tile with each side that one of the corners lies part way along split there, or None if none
+    // does. Only asked of sets with partial contacts.
+    fn split_at_corners(&self, _corners: &[Self::Vertex]) -> Result<Option<Self>, TilingError> {
+        Ok(None)
+    }
 }
 
 struct TileWithTransform<'a, T> {
@@ -199,36 +142,37 @@ struct TileWithTransform<'a, T> {
 impl<'a, T> TileWithTransform<'a, T> {
     fn new(tile: &'a T, transform: &'a Transform) -> Self {
         TileWithTransform {
-            tile: tile,
-            transform: transform,
+            tile,
+            transform,
             //phantom: PhantomData
         }
     }
 }
 
-#[derive(Clone, Copy)]
-struct Edge {
-    start: Vec2,
-    end: Vec2
+// Interior angle at each corner of a simple polygon in either winding order, past 180 degrees at reflex
+// corners
+fn get_corner_angles(points: &PointList) -> Vec<f32> {
+    let full_turn = 2.0 * std::f32::consts::PI;
+    let anticlockwise = geometry::signed_area(points) >= 0.0;
+    (0..points.len()).map(|i| {
+        let prev = points[(i + points.len() - 1) % points.len()] - points[i];
+        let next = points[(i + 1) % points.len()] - points[i];
+        // Turning anticlockwise from the next side to the previous one sweeps the inside of an anticlockwise
+        // polygon
+        let angle = next.perp_dot(prev).atan2(next.dot(prev)).rem_euclid(full_turn);
+        if anticlockwise { angle } else { full_turn - angle }
+    }).collect()
 }
 
-impl Edge {
-    fn new(p1: Vec2, p2: Vec2) -> Self {
-        if p1 < p2 {
-            Edge {
-                start: p1,
-                end: p2,
-            }
-        } else {
-            Edge {
-                start: p2,
-                end: p1,
-            }
-        }
-    }
+// The shape, the tile itself and its decorations, on an entity that has already been spawned
+fn spawn_tile<P: PenroseEnum, T: Tile<P>>(tile: &T, entity: Entity, commands: &mut Commands) {
+    let mut entity_commands = commands.entity(entity);
+    tile.insert_shape_component(tile.get_transform(), &mut entity_commands);
+    entity_commands.insert(tile.clone());
+    tile.spawn_dots_entities(entity, commands);
 }
 
-fn get_points_for_tile<P: PenroseEnum, T: Tile<P> >(tile: &TileWithTransform<T>) -> Vec<Vec2> {
+fn get_points_for_tile<P: PenroseEnum, T: TileShape<P> >(tile: &TileWithTransform<T>) -> Vec<Vec2> {
     let origin_points = tile.tile.get_points();
     let mut transformed_points = Vec::new();
     for p in origin_points {
@@ -238,29 +182,6 @@ fn get_points_for_tile<P: PenroseEnum, T: Tile<P> >(tile: &TileWithTransform<T>)
     transformed_points
 }
 
-fn get_edge_vectors_for_tile<P: PenroseEnum, T: Tile<P> >(tile: &TileWithTransform<T>) -> Vec<Vec2> {
-    let points = get_points_for_tile(tile);
-    let mut vectors = Vec::new();
-    for i in 0..points.len() {
-        let next_i = (i + 1) % points.len();
-        vectors.push(points[next_i] - points[i]);
-    }
-    vectors
-}
-
-fn get_edges_for_tile<P: PenroseEnum, T: Tile<P> >(tile: &TileWithTransform<T>) -> Vec<Edge> {
-    let mut edges = Vec::new();
-    let points = tile.tile.get_points();
-    for i in 0..points.len() {
-        let j = (i + 1) % points.len();
-        let world_point_0 = (*tile.transform * points[i].extend(0.0)).truncate();
-        let world_point_1 = (*tile.transform * points[j].extend(0.0)).truncate();
-        edges.push(Edge::new(world_point_0, world_point_1));
-    }
-
-    edges
-}
-
 #[derive(Clone, Copy)]
 struct EdgeData<P: PenroseEnum> {
     entity: Entity,
@@ -268,401 +189,283 @@ struct EdgeData<P: PenroseEnum> {
     penrose_type: P
 }
 
-#[derive(Default)]
-struct EdgeLookup<P: PenroseEnum> {
-    edges: Vec<Edge>,
-    tiles: Vec<Vec<EdgeData<P>> >
+#[derive(Clone, Copy)]
+struct CornerData<P: PenroseEnum> {
+    entity: Entity,
+    corner: u8,
+    penrose_type: P
 }
 
-struct EdgeResult<P: PenroseEnum> {
-    edge: Edge,
-    data: Vec<EdgeData<P>>
+#[derive(Clone, Copy)]
+struct FaceData<P: PenroseEnum> {
+    entity: Entity,
+    penrose_type: P
 }
 
-impl<P: PenroseEnum> EdgeLookup<P> {
-    fn edge_search_fuzzy(&self, edge: &Edge) -> Result<usize, usize> {
-        let epsilon = 0.004;
-        /*self.edges.binary_search_by(|probe| {
-            let close_start = probe.start - edge.start;
-            let close_end = probe.end - edge.end;
-            if close_start.x.abs() <= epsilon && close_start.y.abs() <= epsilon &&
-                close_end.x.abs() <= epsilon && close_end.y.abs() <= epsilon {
-                Ordering::Equal
-            } else {
-                if probe.start < edge.start && probe.end < edge.end {
-                    Ordering::Less
-                } else if probe.start < edge.start && probe.end > edge.end {
-                    Ordering::Less
-                } else {
-                    Ordering::Greater
-                }
-            }
-        })*/
-        for i in 0..self.edges.len() {
-            let probe = &self.edges[i];
-            let close_start = probe.start - edge.start;
-            let close_end = probe.end - edge.end;
-            if close_start.x.abs() <= epsilon && close_start.y.abs() <= epsilon &&
-                close_end.x.abs() <= epsilon && close_end.y.abs() <= epsilon {
-                return Ok(i);
-            }
+// Exact topology of the placed tiles. Each tile is a face of the half-edge mesh, with its sides in the same
+// order as its points, so the tile on the other side of an edge is found by following the twin pointer.
+struct TileTopology<P: PenroseEnum, V> {
+    mesh: HalfEdgeMesh<V, FaceData<P>>,
+    faces: HashMap<Entity, FaceId>,
+    corner_angles: HashMap<Entity, Vec<f32>>
+}
+
+impl<P: PenroseEnum, V: Copy + Eq + Hash + Debug> Default for TileTopology<P, V> {
+    fn default() -> Self {
+        TileTopology {
+            mesh: HalfEdgeMesh::default(),
+            faces: HashMap::new(),
+            corner_angles: HashMap::new()
         }
+    }
+}
 
-        Err(self.edges.len())
+impl<P: PenroseEnum, V: Copy + Eq + Hash + Debug> TileTopology<P, V> {
+    fn get_edge_data(&self, h: HalfEdgeId) -> Option<EdgeData<P>> {
+        let face = self.mesh.get_face(h)?;
+        let data = self.mesh.get_face_data(face)?;
+        Some(EdgeData {
+            entity: data.entity,
+            side: self.mesh.get_face_side(h)? as u8,
+            penrose_type: data.penrose_type
+        })
     }
 
-    fn get_tiles_for_edge(&self, edge: &Edge) -> Option<EdgeResult<P>> {
-        self.get_tiles_for_edge_excluding(edge, Entity::new(0))
+    // The tile whose side runs from `from` to `to`, tiles sharing that edge have it the other way round
+    fn get_tile_on_edge(&self, from: &V, to: &V) -> Option<EdgeData<P>> {
+        let h = self.mesh.find_half_edge(from, to)?;
+        self.get_edge_data(h)
     }
 
-    fn get_tiles_for_edge_excluding(&self, edge: &Edge, exclude: Entity) -> Option<EdgeResult<P>> {
-        let result = self.edge_search_fuzzy(edge);
-        match result {
-            Ok(pos) => {
-                let tiles_copy = self.tiles[pos].clone();
-                Some(EdgeResult {
-                    edge: *edge,
-                    data: tiles_copy.into_iter().filter(|x| x.entity != exclude).collect()
-                })
-            },
-            Err(_) => None 
+    // For each side of the tile that has a neighbour, the side and the neighbour's side
+    fn get_neighbours(&self, entity: Entity) -> Vec<(u8, EdgeData<P>)> {
+        let mut neighbours = Vec::new();
+        if let Some(face) = self.faces.get(&entity) {
+            for (side, h) in self.mesh.get_face_half_edges(*face).into_iter().enumerate() {
+                if let Some(data) = self.get_edge_data(HalfEdgeMesh::<V, FaceData<P>>::twin(h)) {
+                    neighbours.push((side as u8, data));
+                }
+            }
         }
+        neighbours
     }
 
-    fn get_tiles_for_all_edges<T: Tile<P>>(&self, tile: &TileWithTransform<T>) -> Vec<EdgeResult<P>> {
-        self.get_tiles_for_all_edges_excluding(tile, Entity::new(0))
-    }
+    fn add_tile<T: Tile<P, Vertex = V>>(&mut self, tile: &T, entity: Entity) -> Result<(), TilingError> {
+        let face = self.mesh.add_face(&tile.get_vertices(), FaceData {
+            entity,
+            penrose_type: tile.get_type()
+        }).map_err(TilingError::Topology)?;
+        self.faces.insert(entity, face);
 
-    fn get_tiles_for_all_edges_excluding<T: Tile<P>>(&self, tile: &TileWithTransform<T>, exclude: Entity) -> Vec<EdgeResult<P>> {
-        let edges = get_edges_for_tile(tile);
-        let mut edge_data = Vec::new();
-        for i in 0..edges.len() {
-            match self.get_tiles_for_edge_excluding(&edges[i], exclude) {
-                Some(e) => { 
-                    println!("HIT for edge ({:?} {:?}) len {}", edges[i].start, edges[i].end, e.data.len());
-                    if e.data.len() > 0 {
-                        edge_data.push(e);
-                    }
-                },
-                _ => {}
-            }
-        }
+        self.corner_angles.insert(entity, get_corner_angles(&tile.get_points()));
+        Ok(())
+    }
 
-        edge_data
+    fn remove_entity(&mut self, entity: Entity) -> Result<(), TilingError> {
+        let face = *self.faces.get(&entity).ok_or(TilingError::UnknownTile(entity))?;
+        self.mesh.remove_face(face).map_err(TilingError::Topology)?;
+        self.faces.remove(&entity);
+        self.corner_angles.remove(&entity);
+        Ok(())
     }
 
-    fn add_edge(&mut self, edge: &Edge, data: EdgeData<P>) {
-        let result = self.edge_search_fuzzy(&edge);
-        match result {
-            Ok(pos) => {
-                println!("Adding to EXISTING edge at {} ({:?}, {:?}) {:?} {} {} new len {}",
-                    pos, edge.start, edge.end, data.entity, data.side, data.penrose_type, self.tiles[pos].len() + 1);
-                self.tiles[pos].push(data)
-            },
-            Err(pos) =>  {
-                println!("Adding to NEW edge at {} ({:?}, {:?}) {:?} {} {}", pos, edge.start, edge.end, data.entity, data.side, data.penrose_type);
-                self.edges.insert(pos, *edge);
+    fn get_num_tiles(&self) -> usize {
+        self.faces.len()
+    }
 
-                let mut v = Vec::new();
-                v.push(data);
-                self.tiles.insert(pos, v);
+    // The tiles with a corner at the vertex, leaving out any that also have a corner at away_from
+    fn get_corners_at(&self, vertex: &V, away_from: &V) -> Vec<CornerData<P>> {
+        let mut corners = Vec::new();
+        for face in self.mesh.get_vertex_fan(vertex) {
+            let vertices = self.mesh.get_face_vertices(face);
+            if vertices.contains(away_from) {
+                continue;
             }
+            let data = self.mesh.get_face_data(face).unwrap();
+            corners.push(CornerData {
+                entity: data.entity,
+                corner: vertices.iter().position(|v| v == vertex).unwrap() as u8,
+                penrose_type: data.penrose_type
+            });
         }
+        corners
     }
 
-    fn add_edges<T: Tile<P>>(&mut self, tile: &TileWithTransform<T>, entity: &Entity) {
-        let edges = get_edges_for_tile(tile);
-        for i in 0..edges.len() {
-            println!("--> Adding edge {:?} ({:?}, {:?}) {}", entity, edges[i].start, edges[i].end, i);
-            self.add_edge(&edges[i], EdgeData {
-                entity: *entity,
-                side: i as u8,
-                penrose_type: tile.tile.get_type()
-            });
+    // Sum of the corner angles of the tiles meeting at a vertex
+    fn get_filled_angle(&self, vertex: &V) -> f32 {
+        let mut angle = 0.0;
+        for face in self.mesh.get_vertex_fan(vertex) {
+            let entity = self.mesh.get_face_data(face).unwrap().entity;
+            let corner = self.mesh.get_face_vertices(face).iter().position(|v| v == vertex).unwrap();
+            angle += self.corner_angles[&entity][corner];
         }
+        angle
     }
 
-    fn remove_entity(&mut self, entity: Entity) {
-        assert!(self.tiles.len() == self.edges.len());
-        let mut i = 0;
-        while i < self.tiles.len() {
-            let edges: &mut Vec<EdgeData<P>> = &mut self.tiles[i];
-            edges.retain(|data| {
-                data.entity != entity
-            });
-
-            if edges.len() == 0 {
-                self.tiles.remove(i);
-                self.edges.remove(i);
-            } else {
-                i += 1;
+    fn print_statistics(&self, last_entity: Entity) {
+        let loops = self.mesh.get_boundary_loops();
+        println!("{} tiles, {} vertices, {} boundary loops", self.mesh.get_num_faces(), self.mesh.get_num_vertices(), loops.len());
+        for boundary in &loops {
+            println!("  boundary loop of {} edges", boundary.len());
+        }
+        if let Some(face) = self.faces.get(&last_entity) {
+            for vertex in self.mesh.get_face_vertices(*face) {
+                println!("  {:?} of {:?} has {} tiles around it", vertex, last_entity, self.mesh.get_vertex_fan(&vertex).len());
             }
         }
     }
 }
 
 #[derive(Default)]
-struct PenroseTiler {
-    tiles_added: Vec<Entity>
-}
+struct PenroseTiler;
 
 struct EdgeTile;
 
 impl PenroseTiler {
-    fn is_separating_axis(normal: &Vec2, points_a: &Vec<Vec2>, points_b: &Vec<Vec2>) -> bool {
-        let epsilon = 0.004;
-        let mut min_a = f32::INFINITY;
-        let mut max_a = f32::NEG_INFINITY;
-        let mut min_b = f32::INFINITY;
-        let mut max_b = f32::NEG_INFINITY;
-
-        for v in points_a {
-            let projection = v.dot(*normal);
-            min_a = min_a.min(projection);
-            max_a = max_a.max(projection);
-        }
+    // Shared edges and corners count as touching, not overlapping. Works for tiles that aren't convex.
+    fn tiles_collide<P: PenroseEnum, T: TileShape<P>>(tile_a: &TileWithTransform<T>, tile_b: &TileWithTransform<T>) -> bool {
+        geometry::polygons_overlap(&get_points_for_tile(tile_a), &get_points_for_tile(tile_b))
+    }
 
-        for v in points_b {
-            let projection = v.dot(*normal);
-            min_b = min_b.min(projection);
-            max_b = max_b.max(projection);
+    // Components for a tile the model has placed, taking the side state from the model's copy
+    fn insert_tile<P: PenroseEnum, T: Tile<P>>(&self, entity: Entity, tile: &T, commands: &mut Commands) {
+        spawn_tile(tile, entity, commands);
+        if tile.has_free_sides() {
+            commands.entity(entity).insert(EdgeTile);
         }
-        
-        let diff_1 = max_a - min_b;
-        let diff_2 = max_b - min_a;
-
-
-        // !(max_a >= min_b && max_b >= min_a)
-        let overlap = diff_1 > epsilon && diff_2 > epsilon;
-
-        /*println!("overlap: {}, diff_1: {}, diff_2: {}, min_a: {}, max_a: {}, min_b: {}, max_b: {}",
-            overlap, diff_1, diff_2, min_a, max_a, min_b, max_b);*/
-
-        !overlap
     }
 
-    fn tiles_collide<P: PenroseEnum, T: Tile<P>>(tile_a: &TileWithTransform<T>, tile_b: &TileWithTransform<T>) -> bool {
-        let points_a = get_points_for_tile(tile_a);
-        let points_b = get_points_for_tile(tile_b);
-
-        //println!("points_a: {:?}", points_a);
-        //println!("points_b: {:?}", points_b);
-        
-        let mut vectors = get_edge_vectors_for_tile(tile_a);
-        vectors.extend(get_edge_vectors_for_tile(tile_b));
+    // Whether a tile of new_tile_penrose_type can go on a side of a placed tile as far as the tiles sharing
+    // its edges and corners are concerned. Overlaps further away need the geometry, see Tiling::inspect_side.
+    fn check_placement_on_side<P: PenroseEnum, T: Tile<P>>(
+        on_tile: &T,
+        on_tile_side: u8,
+        new_tile_penrose_type: P,
+        topology: &TileTopology<P, T::Vertex>
+    ) -> Result<PlacementCheck, TilingError> {
+        let new_tile = on_tile.get_connected_tile(on_tile_side, new_tile_penrose_type)?;
+        let vertices = new_tile.get_vertices();
+        // Found from the corners rather than the type, tiles whose sides have been split at corners of their
+        // neighbours number their sides differently from the type
+        let on_vertices = on_tile.get_vertices();
+        let side_start = &on_vertices[(on_tile_side as usize + 1) % on_vertices.len()];
+        let side_end = &on_vertices[on_tile_side as usize];
+
+        for new_side in 0..vertices.len() {
+            let from = &vertices[new_side];
+            let to = &vertices[(new_side + 1) % vertices.len()];
+            let glued = from == side_start && to == side_end;
+
+            // A tile with the same side going the same way would be on top of the new one
+            if let Some(existing) = topology.get_tile_on_edge(from, to) {
+                return Ok(PlacementCheck::EdgeAlreadyShared { side: new_side as u8, entity: existing.entity });
+            }
 
-        for v in &vectors {
-            let normal = v.perp();
-            if PenroseTiler::is_separating_axis(&normal, &points_a, &points_b) {
-                return false;
+            if let Some(existing) = topology.get_tile_on_edge(to, from) {
+                let matching = glued || T::sides_can_meet(new_tile_penrose_type, new_side as u8, existing.penrose_type, existing.side)?;
+                if !matching || !T::sides_agree(new_tile_penrose_type, new_side as u8, existing.penrose_type, existing.side) {
+                    return Ok(PlacementCheck::MismatchedSide { side: new_side as u8, entity: existing.entity, existing_side: existing.side });
+                }
             }
         }
 
-        return true;
-    }
-    fn spawn_tile_at<P: PenroseEnum, T: Tile<P>>(&mut self, tile: &T, transform: Transform, commands: &mut Commands) -> Entity {
-        let mut entity = commands.spawn();
-        let id = entity.id();
-        tile.insert_shape_component(transform, &mut entity);
-        entity.insert(tile.clone());
-        entity.insert(EdgeTile);
-
-        tile.spawn_dots_entities(id, commands);
-        self.tiles_added.push(id);
-
-        id
-    }
-
-    fn spawn_tile_at_origin<P: PenroseEnum, T: Tile<P>>(&mut self, tile: &T, commands: &mut Commands) -> Entity {
-        self.spawn_tile_at(tile, Transform::identity(), commands)
-    }
-
-    fn spawn_tile_on<P: PenroseEnum, T: Tile<P>>(
-        &mut self, 
-        on_tile_side: u8, 
-        tile: &T, 
-        on_tile: &TileWithTransform<T>, 
-        commands: &mut Commands
-    ) -> (Entity, Transform) {
-        assert!(!on_tile.tile.get_side_used(on_tile_side));
-
-        let tile_side = T::get_matching_side(on_tile.tile.get_type(), on_tile_side, tile.get_type());
-        let mut tile = tile.clone();
-
-        tile.set_side_used(tile_side);
-        let transform = (*on_tile.transform) * on_tile.tile.get_connection_transform(on_tile_side, tile.get_type());
-
-        let entity = self.spawn_tile_at(&tile, transform, commands);
-
-        println!("Setting side used {} new entity {:?} on tile transform {:?}", tile_side, entity, *on_tile.transform);
+        for (corner, linked) in new_tile.get_linked_vertices() {
+            for other in topology.get_corners_at(&linked, &vertices[corner as usize]) {
+                if !T::corners_agree(new_tile_penrose_type, corner, other.penrose_type, other.corner) {
+                    return Ok(PlacementCheck::MismatchedCorner { corner, entity: other.entity, other_corner: other.corner });
+                }
+            }
+        }
 
+        // The corners can't add up to more than a full turn at any vertex
+        let epsilon = 0.001;
+        for (corner, angle) in get_corner_angles(&new_tile.get_points()).into_iter().enumerate() {
+            if topology.get_filled_angle(&vertices[corner]) + angle > 2.0 * std::f32::consts::PI + epsilon {
+                return Ok(PlacementCheck::IllegalVertex { corner: corner as u8 });
+            }
+        }
 
-        (entity, transform)
+        Ok(PlacementCheck::Allowed)
     }
 
-    fn spawn_random_tile_at_origin<P: PenroseEnum, T: Tile<P>>(&mut self, commands: &mut Commands) -> (T, Entity) {
-        let tile = T::new_random();
-        let entity = self.spawn_tile_at_origin(&tile, commands);
-        (tile, entity)
-    }
+    // Types that can go on a side of a placed tile without breaking the matching rules on any edge they
+    // would share with tiles already placed
+    fn get_allowed_types_on_side<P: PenroseEnum, T: Tile<P>>(on_tile: &T, on_tile_side: u8, topology: &TileTopology<P, T::Vertex>) -> Result<Vec<P>, TilingError> {
+        if on_tile_side as usize >= on_tile.get_side_count() {
+            return Err(TilingError::InvalidSide(on_tile_side));
+        }
 
-    fn get_allowed_tiles_to_place<P: PenroseEnum, T: Tile<P>>(on_tile: &TileWithTransform<T>, edge_lookup: &EdgeLookup<P>) -> Vec<(u8, P)> {
-        let mut allowed_tiles = Vec::new();
-        let free_sides = on_tile.tile.get_free_sides();
-        let all_types: Vec<P> = P::get_all();
-        for side in free_sides {
-            for t in &all_types {
-                //println!("Pushing {}, {}", side, *t);
-                allowed_tiles.push((side, *t));
+        let mut allowed_types: Vec<P> = Vec::new();
+        for new_tile_penrose_type in on_tile.get_neighbour_types() {
+            if PenroseTiler::check_placement_on_side(on_tile, on_tile_side, new_tile_penrose_type, topology)?.is_allowed() {
+                allowed_types.push(new_tile_penrose_type);
             }
         }
 
-        allowed_tiles.retain(|(on_tile_side, new_tile_penrose_type)| {
-            let points = T::new(*new_tile_penrose_type).get_points();
-            let transform = (*on_tile.transform) * on_tile.tile.get_connection_transform(*on_tile_side, *new_tile_penrose_type);
-            let matching_side = T::get_matching_side(on_tile.tile.get_type(), *on_tile_side, *new_tile_penrose_type);
-
-            for new_side in 0..points.len() {
-                let new_side = new_side as u8;
-                if new_side == matching_side {
-                    continue;
-                }
-                let point1_index = new_side as usize;
-                let point2_index = ((new_side + 1) as usize) % points.len();
-                let point1 = (transform * points[point1_index].extend(0.0)).truncate();
-                let point2 = (transform * points[point2_index].extend(0.0)).truncate();
-                let edge = Edge::new(point1, point2);
-
-                match edge_lookup.get_tiles_for_edge(&edge) {
-                    Some(result) => {
-                        if result.data.len() == 0 {
-                            //println!("      ALLOWED No matching tiles for edge, for on_tile_side {} new_side {} new_type {}",
-                            //    *on_tile_side, new_side, *new_tile_penrose_type);
-                        }
-                        else if result.data.len() > 1 {
-                            //println!("      NOT ALLOWED More than 1 matching tiles for edge, for on_tile_side {}, new_side {} type {}",
-                            //    *on_tile_side, new_side, *new_tile_penrose_type);
-                            return false;
-                        } else {
-                            if result.data[0].side == T::get_matching_side(*new_tile_penrose_type, new_side, result.data[0].penrose_type) {
-                                //println!("      ALLOWED matching side for edge, for on_tile_side {}, new_side {} type {} existing_side {}", 
-                                //    *on_tile_side, new_side, *new_tile_penrose_type, result.data[0].side);
-                            } else {
-                                //println!("      NOT ALLOWED matching side for edge, for on_tile_side {}, new_side {} type {} existing_side {}",
-                                //    *on_tile_side, new_side, *new_tile_penrose_type, result.data[0].side);
-                                return false;
-                            }
-                        }
-                    },
-                    None => {
-                        //println!("      ALLOWED NONE matching tiles for edge, for on_tile_side {} new_side {} new_type {}",
-                        //    *on_tile_side, new_side, *new_tile_penrose_type)
-                    }
-                }
-            }
-            true
-        });
+        Ok(allowed_types)
+    }
 
-        allowed_tiles
+    // Sides of the tile that would meet a tile already placed
+    fn count_matched_sides<P: PenroseEnum, T: Tile<P>>(tile: &T, topology: &TileTopology<P, T::Vertex>) -> usize {
+        let vertices = tile.get_vertices();
+        (0..vertices.len()).filter(|i| {
+            let next = (i + 1) % vertices.len();
+            topology.get_tile_on_edge(&vertices[next], &vertices[*i]).is_some()
+        }).count()
     }
 
-    fn spawn_random_tile_on<P: PenroseEnum, T: Tile<P>>(
-        &mut self, 
+    fn choose_random_type_on<P: PenroseEnum, T: Tile<P>>(
         on_tile: &TileWithTransform<T>,
-        edge_lookup: &EdgeLookup<P>,
+        side: u8,
+        legal_types: &[P],
         edge_vec: &Vec<(Entity, Mut<T>, &Transform)>,
-        commands: &mut Commands
-    ) -> Option<(T, Transform, Entity)> {
-        let mut possible_tiles = PenroseTiler::get_allowed_tiles_to_place(on_tile, edge_lookup);
-        if possible_tiles.len() == 0 {
-            return None;
-        }
-
-        possible_tiles.retain(|(side, penrose_type)| {
-            let possible = T::new(*penrose_type);
-            let transform = (*on_tile.transform) * on_tile.tile.get_connection_transform(*side, *penrose_type);
+        topology: &TileTopology<P, T::Vertex>,
+        weights: &TypeWeights
+    ) -> Option<P> {
+        let mut candidates = Vec::new();
+        for penrose_type in legal_types {
+            let possible = match on_tile.tile.get_connected_tile(side, *penrose_type) {
+                Ok(possible) => possible,
+                Err(_) => continue
+            };
+            let transform = possible.get_transform();
             let new_tile = TileWithTransform::new(&possible, &transform);
-            for (_, existing_t, existing_trans) in edge_vec {
+            let collides = edge_vec.iter().any(|(_, existing_t, existing_trans)| {
                 let existing_tile = TileWithTransform::new(& **existing_t, existing_trans);
-                if PenroseTiler::tiles_collide(&new_tile, &existing_tile) {
-                    //println!("Tiles collide, returning false.");
-                    return false;
-                }
+                PenroseTiler::tiles_collide(&new_tile, &existing_tile)
+            });
+            if !collides {
+                candidates.push((*penrose_type, PenroseTiler::count_matched_sides(&possible, topology)));
             }
-
-            true
-        });
-
-        if possible_tiles.len() == 0 {
-            return None;
         }
 
-        let index = rand::thread_rng().gen_range(0..possible_tiles.len());
-
-        let (side, penrose_type) = possible_tiles[index];
-        let tile = T::new(penrose_type);
-
-        let (entity, transform) = self.spawn_tile_on(side, &tile, on_tile, commands);
-        println!("Spawned {:?} {:?} on side {}", entity, transform, side);
-
-        Some((tile, transform, entity))
-    }
-}
-
-#[derive(Display, Clone, Copy, Primitive)]
-enum PenroseRhombusType {
-    Fat = 0,
-    Skinny = 1,
-
-    Count = 2
-}
-
-impl Default for PenroseRhombusType {
-    fn default() -> Self {
-        PenroseRhombusType::Fat
+        weights.choose(&candidates)
     }
 }
 
-impl PenroseEnum for PenroseRhombusType {
-    fn get_all() -> Vec<Self> {
-        let mut vec = Vec::new();
-        for i in 0..(PenroseRhombusType::Count as usize) {
-            vec.push(PenroseRhombusType::from_usize(i).unwrap());
-        }
-        vec
-    }
+// Exact placement of a rhombus. The orientation counts steps of 36 degrees from a long diagonal along the
+// x axis, skinny rhombs sit 18 degrees further round so their sides follow the same ten directions as the
+// fat ones. The position is the lattice point of the left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+struct RhombusPose {
+    orientation: u8,
+    left: PentagonalPoint
 }
 
+// A grown rhomb is keyed by its angle pair like the multigrid rhombs, so growth, the frontier and the type
+// weights see the same types for both. The matching rules only exist for the two Penrose pairs, which are
+// all growth ever offers.
 #[derive(Clone)]
 struct Rhombus {
     small_angle: f32,
     leg_len: f32,
     color: Color,
-    used_side_flags: u8,
-    penrose_type: PenroseRhombusType
-}
-
-fn make_rotation_transform(angle: f32, translation: Vec2, distance_to_center_from_translation: f32) -> Transform {
-    let centerpoint = Vec3::new(0.0, distance_to_center_from_translation, 0.0);
-    let rotation = Quat::from_rotation_z(angle);
-    let centerpoint_rotated = rotation * centerpoint;
-    let vec3_translation = Vec3::from((translation, 0.0));
-    let centerpoint_translated_rotated = centerpoint_rotated + vec3_translation;
-
-    Transform::from_matrix(
-        Mat4::from_rotation_translation(
-            rotation,
-            centerpoint_translated_rotated
-        )
-    )
+    used_side_flags: u32,
+    penrose_type: RhombAngles,
+    pose: RhombusPose
 }
 
 impl Rhombus {
-    const FAT_SMALL_ANGLE: f32 = 72.0;
-    const SKINNY_SMALL_ANGLE: f32 = 36.0;
-    const FAT_LARGE_ANGLE: f32 = 180.0 - Rhombus::FAT_SMALL_ANGLE;
-    const SKINNY_LARGE_ANGLE: f32 = 180.0 - Rhombus::SKINNY_SMALL_ANGLE;
-
     const UPPER_LEFT_SIDE: usize = 0;
     const UPPER_RIGHT_SIDE: usize = 1;
     const LOWER_RIGHT_SIDE: usize = 2;
@@ -673,17 +476,20 @@ impl Rhombus {
         [0, 1, 2, 3]
     ];
 
-    fn get_left_index(&self) -> usize {
-        Rhombus::PENROSE_POINT_INDICES[self.penrose_type as usize][0]
+    fn get_penrose_types() -> Vec<RhombAngles> {
+        vec![RhombAngles::PENROSE_FAT, RhombAngles::PENROSE_SKINNY]
     }
-    fn get_top_index(&self) -> usize {
-        Rhombus::PENROSE_POINT_INDICES[self.penrose_type as usize][1]
+
+    // Row of the tables below for one of the Penrose angle pairs
+    fn get_table_index(penrose_type: RhombAngles) -> usize {
+        if penrose_type == RhombAngles::PENROSE_SKINNY { 1 } else { 0 }
     }
-    fn get_right_index(&self) -> usize {
-        Rhombus::PENROSE_POINT_INDICES[self.penrose_type as usize][2]
+
+    fn get_left_index(&self) -> usize {
+        Rhombus::PENROSE_POINT_INDICES[Rhombus::get_table_index(self.penrose_type)][0]
     }
-    fn get_bottom_index(&self) -> usize {
-        Rhombus::PENROSE_POINT_INDICES[self.penrose_type as usize][3]
+    fn get_right_index(&self) -> usize {
+        Rhombus::PENROSE_POINT_INDICES[Rhombus::get_table_index(self.penrose_type)][2]
     }
 
     const PENROSE_MATCHING_RULES: [[[u8; 4]; 2]; 2] = [
@@ -725,31 +531,79 @@ impl Rhombus {
         ]
     ];
 
-    fn new(penrose_type: PenroseRhombusType) -> Self {
+    // Direction of each side, going round the points, in steps of 36 degrees on top of the orientation
+    const PENROSE_SIDE_DIRECTIONS: [[i32; 4]; 2] = [
+        // Fat directions
+        [1, -1, 6, 4],
+        // Skinny directions
+        [1, 0, 6, 5]
+    ];
+
+    // The shape follows from the side directions, 72 degrees for the fat rhomb and 36 for the skinny one
+    fn get_small_angle(penrose_type: RhombAngles) -> f32 {
+        rhombus_small_angle(&Rhombus::PENROSE_SIDE_DIRECTIONS[Rhombus::get_table_index(penrose_type)], 10)
+    }
+
+    fn get_side_direction(penrose_type: RhombAngles, orientation: u8, side: u8) -> i32 {
+        orientation as i32 + Rhombus::PENROSE_SIDE_DIRECTIONS[Rhombus::get_table_index(penrose_type)][side as usize]
+    }
+
+    // Lattice points of the corners, in the same order as get_points
+    fn get_lattice_points(&self) -> [PentagonalPoint; 4] {
+        let mut points = [self.pose.left; 4];
+        for side in 0..3 {
+            let direction = Rhombus::get_side_direction(self.penrose_type, self.pose.orientation, side as u8);
+            points[side + 1] = points[side] + PentagonalPoint::unit(direction);
+        }
+        points
+    }
+
+    // The pose a rhombus of other_type gets when its matching side is placed on the given side of this one.
+    // Both rhombs list their points the same way round, so the shared side runs in opposite directions.
+    fn get_connection_pose(&self, side: u8, other_type: RhombAngles) -> Result<RhombusPose, TilingError> {
+        let other_side = Rhombus::get_matching_side(self.penrose_type, side, other_type)?;
+        let direction = Rhombus::get_side_direction(self.penrose_type, self.pose.orientation, side) + 5;
+        let orientation = (direction - Rhombus::PENROSE_SIDE_DIRECTIONS[Rhombus::get_table_index(other_type)][other_side as usize]).rem_euclid(10) as u8;
+
+        // Walk back from the shared corner to the other rhombus's left corner
+        let mut left = self.get_lattice_points()[(side as usize + 1) % 4];
+        for s in 0..other_side {
+            left = left - PentagonalPoint::unit(Rhombus::get_side_direction(other_type, orientation, s));
+        }
+
+        Ok(RhombusPose {
+            orientation,
+            left
+        })
+    }
+
+    fn new(penrose_type: RhombAngles) -> Result<Self, TilingError> {
         match penrose_type {
-            PenroseRhombusType::Fat => Rhombus::new_fat(),
-            PenroseRhombusType::Skinny => Rhombus::new_skinny(),
-            _ => panic!("Invalid type")
+            RhombAngles::PENROSE_FAT => Ok(Rhombus::new_fat()),
+            RhombAngles::PENROSE_SKINNY => Ok(Rhombus::new_skinny()),
+            _ => Err(TilingError::InvalidType(penrose_type.to_string()))
         }
     }
 
     fn new_fat() -> Self {
         Rhombus {
-            small_angle: f32::to_radians(Rhombus::FAT_SMALL_ANGLE),
+            small_angle: Rhombus::get_small_angle(RhombAngles::PENROSE_FAT),
             leg_len: 100.0,
             color: Color::BLUE,
             used_side_flags: 0,
-            penrose_type: PenroseRhombusType::Fat
+            penrose_type: RhombAngles::PENROSE_FAT,
+            pose: RhombusPose::default()
         }
     }
 
     fn new_skinny() -> Self {
         Rhombus {
-            small_angle: f32::to_radians(Rhombus::SKINNY_SMALL_ANGLE),
+            small_angle: Rhombus::get_small_angle(RhombAngles::PENROSE_SKINNY),
             leg_len: 100.0,
             color: Color::RED,
             used_side_flags: 0,
-            penrose_type: PenroseRhombusType::Skinny
+            penrose_type: RhombAngles::PENROSE_SKINNY,
+            pose: RhombusPose::default()
         }
     }
 }
@@ -767,9 +621,8 @@ fn get_edge_point(scale: f32, radius: f32, angle: f32, point: Vec2, neg_y: bool)
     Vec2::new(x_coord, y_coord)
 }
 
-fn insert_edge_point(rhombus: &Rhombus, side: usize, entity_commands: &mut EntityCommands) {
+fn insert_edge_point(rhombus: &Rhombus, side: usize, entity_commands: &mut EntityCommands) -> Result<(), TilingError> {
     let radius = 5.0;
-    let scale = Rhombus::PENROSE_POINT_SCALES[rhombus.penrose_type as usize][side];
     let angle = rhombus.small_angle / 2.0;
 
     let (point_index, neg_y) = match side {
@@ -777,16 +630,17 @@ fn insert_edge_point(rhombus: &Rhombus, side: usize, entity_commands: &mut Entit
         Rhombus::UPPER_RIGHT_SIDE => (rhombus.get_right_index(), false),
         Rhombus::LOWER_RIGHT_SIDE => (rhombus.get_right_index(), true),
         Rhombus::LOWER_LEFT_SIDE => (rhombus.get_left_index(), true),
-        _ => panic!("invalid index!")
+        _ => return Err(TilingError::InvalidSide(side as u8))
     };
+    let scale = Rhombus::PENROSE_POINT_SCALES[Rhombus::get_table_index(rhombus.penrose_type)][side];
     let point = rhombus.get_points()[point_index];
-    let color = Rhombus::PENROSE_EDGE_DOT_COLORS[rhombus.penrose_type as usize][side];
+    let color = Rhombus::PENROSE_EDGE_DOT_COLORS[Rhombus::get_table_index(rhombus.penrose_type)][side];
     let angle = if point.y < 0.0 { -angle } else { angle };
 
     entity_commands.insert_bundle(
         GeometryBuilder::build_as(
             &shapes::Circle {
-                radius: radius,
+                radius,
                 center: get_edge_point(scale, radius, angle, point, neg_y)
             },
             ShapeColors::outlined(color, Color::BLACK),
@@ -797,69 +651,113 @@ fn insert_edge_point(rhombus: &Rhombus, side: usize, entity_commands: &mut Entit
             Transform::from_xyz(0.0, 0.0, 1.0)
         )
     );
+    Ok(())
 }
 
-impl Tile<PenroseRhombusType> for Rhombus {
-    fn new_random() -> Self {
-        let tile_type = PenroseRhombusType::from_i32(rand::thread_rng().gen_range(0..(PenroseRhombusType::Count as i32))).unwrap();
-        Rhombus::new(tile_type)
+impl Tile<RhombAngles> for Rhombus {
+    type Vertex = PentagonalPoint;
+
+    fn new_random(weights: &TypeWeights) -> Self {
+        let candidates: Vec<(RhombAngles, usize)> = Rhombus::get_penrose_types().into_iter().map(|t| (t, 0)).collect();
+        match weights.choose(&candidates) {
+            Some(RhombAngles::PENROSE_SKINNY) => Rhombus::new_skinny(),
+            _ => Rhombus::new_fat()
+        }
     }
     
-    fn new(penrose_type: PenroseRhombusType) -> Self {
-        Rhombus::new(penrose_type)
-    }
-
     fn get_num_sides() -> usize {
         4
     }
 
-    fn has_free_sides(&self) -> bool {
-        return self.used_side_flags & 0xf != 0xf
+    fn get_side_flags(&self) -> u32 {
+        self.used_side_flags
     }
 
-    fn get_free_sides(&self) -> Vec<u8> {
-        let mut free_sides = Vec::<u8>::new();
-        for side in 0..Rhombus::get_num_sides() {
-            if !self.get_side_used(side as u8) {
-                free_sides.push(side as u8);
+    fn get_side_flags_mut(&mut self) -> &mut u32 {
+        &mut self.used_side_flags
+    }
+
+    fn get_matching_side(onto_type: RhombAngles, onto_side: u8, other_type: RhombAngles) -> Result<u8, TilingError> {
+        if onto_side as usize >= Rhombus::get_num_sides() {
+            return Err(TilingError::InvalidSide(onto_side));
+        }
+        for penrose_type in &[onto_type, other_type] {
+            if !Rhombus::get_penrose_types().contains(penrose_type) {
+                return Err(TilingError::InvalidType(penrose_type.to_string()));
             }
         }
+        Ok(Rhombus::PENROSE_MATCHING_RULES[Rhombus::get_table_index(onto_type)][Rhombus::get_table_index(other_type)][onto_side as usize])
+    }
 
-        free_sides
+    fn get_neighbour_types(&self) -> Vec<RhombAngles> {
+        Rhombus::get_penrose_types()
     }
 
-    fn get_side_used(&self, side: u8) -> bool {
-        return (self.used_side_flags & (1 << side)) != 0;
+    fn get_connected_tile(&self, side: u8, other_type: RhombAngles) -> Result<Self, TilingError> {
+        let mut tile = Rhombus::new(other_type)?;
+        tile.pose = self.get_connection_pose(side, other_type)?;
+        Ok(tile)
     }
 
-    fn set_side_used(&mut self, side: u8) {
-        self.used_side_flags |= 1 << side;
+    // Rendering transform derived from the pose, nothing is accumulated in floating point
+    fn get_transform(&self) -> Transform {
+        let points = self.get_lattice_points();
+        let left = points[self.get_left_index()].to_world(self.leg_len);
+        let right = points[self.get_right_index()].to_world(self.leg_len);
+        let base_angle = match self.penrose_type {
+            RhombAngles::PENROSE_SKINNY => 18.0,
+            _ => 0.0
+        };
+        let angle = f32::to_radians(36.0 * self.pose.orientation as f32 + base_angle);
+        Transform {
+            translation: ((left + right) / 2.0).extend(0.0),
+            rotation: Quat::from_rotation_z(angle),
+            scale: Vec3::ONE
+        }
     }
 
-    fn set_side_free(&mut self, side: u8) {
-        self.used_side_flags &= !(1 << side);
+    fn get_vertices(&self) -> Vec<PentagonalPoint> {
+        self.get_lattice_points().to_vec()
     }
 
+    /*fn get_edge_transformed(&self, side: u8, transform: Transform) -> Edge {
+        let points = self.get_points();
+        
+    }*/
+}
+
+// Acute corner of a rhombus whose sides run along lattice directions, num_directions of them to a full
+// turn, found from its first and last sides as they run round the points
+fn rhombus_small_angle(side_directions: &[i32; 4], num_directions: i32) -> f32 {
+    let steps = (side_directions[0] - side_directions[3] - num_directions / 2).rem_euclid(num_directions);
+    2.0 * std::f32::consts::PI * steps as f32 / num_directions as f32
+}
+
+// A rhombus centered at the origin with its long diagonal along the x axis, points ordered left, top, right, bottom
+fn rhombus_points(small_angle: f32, leg_len: f32) -> PointList {
+    assert!(small_angle <= f32::to_radians(90.0));
+
+    let long_diag_len = leg_len * (2.0 + 2.0 * small_angle.cos()).sqrt();
+    let half_long_diag = long_diag_len / 2.0;
+    let small_diag_len = leg_len * (2.0 - 2.0 * small_angle.cos()).sqrt();
+    let half_small_diag = small_diag_len / 2.0;
+
+    let left = Vec2::new(-half_long_diag, 0.0);
+    let top = Vec2::new(0.0, half_small_diag);
+    let right = Vec2::new(half_long_diag, 0.0);
+    let bottom = Vec2::new(0.0, -half_small_diag);
+
+    vec![
+        left,
+        top,
+        right,
+        bottom
+    ]
+}
+
+impl TileShape<RhombAngles> for Rhombus {
     fn get_points(&self) -> PointList {
-        assert!(self.small_angle <= f32::to_radians(90.0));
-
-        // Create a rhombus centered at the origin
-        let long_diag_len = self.leg_len * (2.0 + 2.0 * self.small_angle.cos()).sqrt();
-        let half_long_diag = long_diag_len / 2.0;
-        let small_diag_len = self.leg_len * (2.0 - 2.0 * self.small_angle.cos()).sqrt();
-        let half_small_diag = small_diag_len / 2.0;
-
-        let left = Vec2::new(-half_long_diag, 0.0);
-        let top = Vec2::new(0.0, half_small_diag);
-        let right = Vec2::new(half_long_diag, 0.0);
-        let bottom = Vec2::new(0.0, -half_small_diag);
-
-        vec![
-            left,
-            top,
-            right,
-            bottom
-        ]
+        rhombus_points(self.small_angle, self.leg_len)
     }
 
     fn insert_shape_component(&self, transform: Transform, entity_commands: &mut EntityCommands) {
@@ -881,55 +779,241 @@ impl Tile<PenroseRhombusType> for Rhombus {
 
     fn spawn_dots_entities(&self, parent: Entity, commands: &mut Commands) {
 
-        for i in 0..4 {
+        for i in 0..Rhombus::get_num_sides() {
             let mut entity = commands.spawn();
             let id = entity.id();
-            insert_edge_point(self, i, &mut entity);
-            commands.entity(parent).push_children(&[id]);
+            match insert_edge_point(self, i, &mut entity) {
+                Ok(()) => {
+                    commands.entity(parent).push_children(&[id]);
+                },
+                Err(error) => {
+                    println!("No edge point for side {} of {:?}: {}", i, parent, error);
+                    commands.entity(id).despawn();
+                }
+            }
         }
     }
 
-    fn get_type(&self) -> PenroseRhombusType {
+    fn get_type(&self) -> RhombAngles {
         self.penrose_type
     }
+}
 
-    fn get_matching_side(onto_type: PenroseRhombusType, onto_side: u8, other_type: PenroseRhombusType) -> u8 {
-        assert!(onto_side < 4);
-        Rhombus::PENROSE_MATCHING_RULES[onto_type as usize][other_type as usize][onto_side as usize]
-    }
+// Picked with the first command line argument, e.g. `cargo run -- hat`
+#[derive(Display, EnumString, Clone, Copy, PartialEq)]
+enum TileSet {
+    #[strum(serialize = "penrose")]
+    Penrose,
+    #[strum(serialize = "hat")]
+    Hat,
+    // Add --curved to start with the curved edges that rule out reflected spectres
+    #[strum(serialize = "spectre")]
+    Spectre,
+    #[strum(serialize = "ammann-beenker")]
+    AmmannBeenker,
+    #[strum(serialize = "girih")]
+    Girih,
+    // Followed by the number of line families, e.g. `multigrid 7`
+    #[strum(serialize = "multigrid")]
+    Multigrid,
+    // Followed by the rule, e.g. `substitution chair`
+    #[strum(serialize = "substitution")]
+    Substitution,
+    // Followed by the set, e.g. `wang kari`, and optionally --weights as for penrose
+    #[strum(serialize = "wang")]
+    Wang,
+    // Optionally --weights as for penrose, e.g. `--weights=L0=0` to leave out a reflection
+    #[strum(serialize = "socolar-taylor")]
+    SocolarTaylor,
+    // The 3D tiling of golden rhombohedra, E writes it to icosahedral.obj
+    #[strum(serialize = "icosahedral")]
+    Icosahedral
+}
+
+fn main() {
+    let tile_set = match std::env::args().nth(1) {
+        Some(arg) => match TileSet::from_str(&arg) {
+            Ok(tile_set) => tile_set,
+            Err(_) => {
+                eprintln!("Unknown tile set '{}', expected one of: {}, {}, {}, {}, {}, {}, {}, {}, {}, {}", arg,
+                    TileSet::Penrose, TileSet::Hat, TileSet::Spectre, TileSet::AmmannBeenker, TileSet::Girih,
+                    TileSet::Multigrid, TileSet::Substitution, TileSet::Wang, TileSet::SocolarTaylor, TileSet::Icosahedral);
+                std::process::exit(1);
+            }
+        },
+        None => TileSet::Penrose
+    };
 
-    fn get_connection_transform(&self, side: u8, other_type: PenroseRhombusType) -> Transform {
-        ROTATION_TRANSFORMS[self.penrose_type as usize][other_type as usize][side as usize]
+    let mut app = App::build();
+    app.insert_resource(Msaa { samples: 8 })
+        .add_plugins(DefaultPlugins)
+        .add_plugin(ShapePlugin);
+
+    match tile_set {
+        TileSet::Penrose => {
+            let weights = match parse_type_weights() {
+                Ok(weights) => weights,
+                Err(error) => {
+                    eprintln!("Bad tile weights: {}", error);
+                    std::process::exit(1);
+                }
+            };
+            let seed = std::env::args().find_map(|arg| arg.strip_prefix("--seed=").map(|name| name.to_string()));
+            let seeds = match SeedLibrary::new(seed.as_deref().unwrap_or("single")) {
+                Ok(seeds) => seeds,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            };
+            app.insert_resource(Growth::<RhombAngles, Rhombus>::with_weights(weights))
+                .insert_resource(seeds)
+                .add_startup_system(setup.system())
+                .add_system(place_shapes.system())
+                .add_system(inspect_placements.system())
+                .add_system(osds_growth.system())
+                .add_system(choose_seed.system());
+        },
+        TileSet::Hat => {
+            app.add_startup_system(hat::setup_hat.system())
+                .add_system(hat::hat_controls.system());
+        },
+        TileSet::Spectre => {
+            let curved = std::env::args().any(|arg| arg == "--curved");
+            app.insert_resource(spectre::SpectreTiler::new(curved))
+                .add_startup_system(spectre::setup_spectre.system())
+                .add_system(spectre::spectre_controls.system());
+        },
+        TileSet::AmmannBeenker => {
+            app.add_startup_system(ammann_beenker::setup_ammann_beenker.system())
+                .add_system(ammann_beenker::ammann_beenker_controls.system());
+        },
+        TileSet::Girih => {
+            app.insert_resource(girih::GirihTiler::default())
+                .add_startup_system(girih::setup_girih.system())
+                .add_system(girih::girih_controls.system());
+        },
+        TileSet::Multigrid => {
+            let num_families = std::env::args().nth(2).and_then(|arg| arg.parse().ok()).unwrap_or(7);
+            app.insert_resource(multigrid::MultigridTiler::new(num_families))
+                .add_startup_system(multigrid::setup_multigrid.system())
+                .add_system(multigrid::multigrid_controls.system());
+        },
+        TileSet::Substitution => {
+            let rule = std::env::args().nth(2).unwrap_or_else(|| "tuebingen".to_string());
+            let tiler = match substitution::SubstitutionTiler::new(&rule) {
+                Ok(tiler) => tiler,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            };
+            app.insert_resource(tiler)
+                .add_startup_system(substitution::setup_substitution.system())
+                .add_system(substitution::substitution_controls.system());
+        },
+        TileSet::Wang => {
+            let set = std::env::args().nth(2).filter(|arg| !arg.starts_with("--")).unwrap_or_else(|| "jeandel-rao".to_string());
+            let tiler = match parse_type_weights().and_then(|weights| wang::WangTiler::new(&set, weights)) {
+                Ok(tiler) => tiler,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            };
+            app.insert_resource(tiler)
+                .add_startup_system(wang::setup_wang.system())
+                .add_system(wang::wang_controls.system());
+        },
+        TileSet::SocolarTaylor => {
+            let weights = match parse_type_weights() {
+                Ok(weights) => weights,
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            };
+            app.insert_resource(socolar_taylor::SocolarTaylorTiler::new(weights))
+                .add_startup_system(socolar_taylor::setup_socolar_taylor.system())
+                .add_system(socolar_taylor::socolar_taylor_controls.system());
+        },
+        TileSet::Icosahedral => {
+            app.insert_resource(icosahedral::IcosahedralTiler::default())
+                .add_startup_system(icosahedral::setup_icosahedral.system())
+                .add_system(icosahedral::icosahedral_controls.system());
+        }
     }
 
-    /*fn get_edge_transformed(&self, side: u8, transform: Transform) -> Edge {
-        let points = self.get_points();
-        
-    }*/
+    app.run();
 }
 
-fn main() {
-    App::build()
-        .insert_resource(Msaa { samples: 8 })
-        .add_plugins(DefaultPlugins)
-        .add_plugin(ShapePlugin)
-        .add_startup_system(setup.system())
-        .add_system(mark_sides_used.system().label("mark_sides_used"))
-        .add_system(place_shapes.system().after("mark_sides_used"))
-        .run();
+// `--weights=72/108=1.618,36/144=1` weights tile types during growth, Penrose rhombs by their angle pair,
+// `--bond=2` multiplies the weight by 2 for every extra side the new tile would match
+fn parse_type_weights() -> Result<TypeWeights, String> {
+    let mut per_type = "";
+    let mut bond_weight = 1.0;
+    let args: Vec<String> = std::env::args().collect();
+    for arg in &args {
+        if let Some(value) = arg.strip_prefix("--weights=") {
+            per_type = value;
+        } else if let Some(value) = arg.strip_prefix("--bond=") {
+            bond_weight = value.parse().map_err(|_| format!("'{}' is not a bond weight", value))?;
+        }
+    }
+    TypeWeights::parse(per_type, bond_weight)
 }
 
-fn setup(mut commands: Commands) {
+// The model takes the whole seed before any components are inserted, so every tile starts with its final
+// side state
+fn place_seed(
+    seeds: &SeedLibrary,
+    weights: &TypeWeights,
+    tiler: &PenroseTiler,
+    tiling: &mut Tiling<RhombAngles, Rhombus>,
+    commands: &mut Commands
+) {
+    let tiles = match seeds.build(weights) {
+        Ok(tiles) => tiles,
+        Err(error) => {
+            println!("Couldn't build seed {}: {}", seeds.get_current_name(), error);
+            return;
+        }
+    };
+
+    let mut entities = Vec::new();
+    for tile in tiles {
+        let entity = commands.spawn().id();
+        match tiling.place(tile, entity) {
+            Ok(_) => entities.push(entity),
+            Err(error) => {
+                println!("Couldn't place a tile of seed {}: {}", seeds.get_current_name(), error);
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+    for entity in &entities {
+        if let Some(tile) = tiling.get_tile(*entity) {
+            tiler.insert_tile(*entity, tile, commands);
+        }
+    }
+    println!("Seed {}: {} tiles", seeds.get_current_name(), entities.len());
+}
+
+fn setup(seeds: Res<SeedLibrary>, growth: Res<Growth<RhombAngles, Rhombus>>, mut commands: Commands) {
 
     commands.spawn_bundle(OrthographicCameraBundle::new_2d());
 
-    let mut tiler = PenroseTiler::default();
-    let (tile, entity) = tiler.spawn_random_tile_at_origin::<PenroseRhombusType, Rhombus>(&mut commands);
-    commands.insert_resource(tiler);
+    let tiler = PenroseTiler;
+    let mut tiling = Tiling::<RhombAngles, Rhombus>::default();
+    place_seed(&seeds, &growth.weights, &tiler, &mut tiling, &mut commands);
 
-    let mut edges = EdgeLookup::<PenroseRhombusType>::default();
-    edges.add_edges(&TileWithTransform::new(&tile, &Transform::identity()), &entity);
-    commands.insert_resource(edges);
+    commands.insert_resource(tiler);
+    commands.insert_resource(tiling);
+    commands.insert_resource(UndoStack::<Rhombus>::default());
+    commands.insert_resource(PlacementInspector::default());
+    commands.insert_resource(SupertileOverlay::default());
+    commands.insert_resource(PerpSpaceOverlay::default());
+    commands.insert_resource(OsdsGrowth::new(RhombAngles::PENROSE_FAT, std::f32::consts::PI - Rhombus::get_small_angle(RhombAngles::PENROSE_FAT)));
 
 
     /*let r1 = Rhombus::new_skinny();
@@ -988,64 +1072,63 @@ fn setup(mut commands: Commands) {
 }
 
 
-fn mark_sides_used(
-    edges: Res<EdgeLookup<PenroseRhombusType>>,
-    mut query: QuerySet<(
-        Query<(Entity, &mut Rhombus, &Transform), Added<Rhombus>>,
-        Query<(Entity, &mut Rhombus, &Transform)>
-    )>,
-    mut commands: Commands
+// Copy the model's side state back onto the components of tiles an operation touched
+fn sync_tile_components(
+    tiling: &Tiling<RhombAngles, Rhombus>,
+    entities: &[Entity],
+    query: &mut Query<(Entity, &mut Rhombus, &Transform)>,
+    commands: &mut Commands
 ) {
-
-    let mut has_new_rhombus = false;
-    let mut edges_to_set: Vec<EdgeData<PenroseRhombusType>> = Vec::new();
-    for (entity, mut rhombus, transform) in query.q0_mut().iter_mut() {
-        if !has_new_rhombus {
-            println!("START MARK SIDES PASS*****************************************************");
-            has_new_rhombus = true;
-        }
-
-        let tile = TileWithTransform::new(&mut *rhombus, &transform);
-        let edge_data = edges.get_tiles_for_all_edges(&tile);
-        
-        for edge in edge_data {
-            let num_data = edge.data.len();
-            for data in edge.data {
-                println!("  considering: {:?} {}", data.entity, data.side);
-                if data.entity == entity {
-                    if num_data < 2 {
-                        println!( "  (Skipping side {} on new entity {:?}, nothing connected to it)", data.side, data.entity);
-                    } else {
-                        edges_to_set.push(data);
-                        println!( "  --> SETTING side {} used on new entity {:?}, because there's something else connected)", data.side, data.entity);
-                    }
-                } else {
-                    println!( "  --> SETTING side {} used on entity {:?}", data.side, data.entity);
-                    edges_to_set.push(data);
+    for entity in entities {
+        if let Some(tile) = tiling.get_tile(*entity) {
+            match query.get_component_mut::<Rhombus>(*entity) {
+                Ok(mut rhombus) => *rhombus = tile.clone(),
+                // Placed this frame, its components haven't been inserted yet
+                Err(_) => {
+                    commands.entity(*entity).insert(tile.clone());
                 }
             }
+            if tile.has_free_sides() {
+                commands.entity(*entity).insert(EdgeTile);
+            } else {
+                println!("  Removing edge tile {:?}!", entity);
+                commands.entity(*entity).remove::<EdgeTile>();
+            }
         }
     }
-    
-    for data in edges_to_set {
-        let mut rhombus = query.q1_mut().get_component_mut::<Rhombus>(data.entity).unwrap();
-        rhombus.set_side_used(data.side);
+}
 
-        if !rhombus.has_free_sides() {
-            println!("  Removing edge tile {:?}!", data.entity);
-            commands.entity(data.entity).remove::<EdgeTile>();
+// Fill every forced edge, keeping each tile as its own undo step
+fn propagate(
+    tiler: &PenroseTiler,
+    tiling: &mut Tiling<RhombAngles, Rhombus>,
+    history: &mut UndoStack<Rhombus>,
+    growth: &mut Growth<RhombAngles, Rhombus>,
+    query: &mut Query<(Entity, &mut Rhombus, &Transform)>,
+    commands: &mut Commands
+) {
+    let propagation = propagate_forced(tiling, commands);
+    for record in propagation.records {
+        if let Some(tile) = tiling.get_tile(record.entity) {
+            tiler.insert_tile(record.entity, tile, commands);
+            growth.stats.add_forced(tile.get_type());
         }
+        sync_tile_components(tiling, &record.touched, query, commands);
+        history.records.push(record);
     }
-
-    if has_new_rhombus {
-        println!("END MARK SIDES PASS*****************************************************");
-        println!("");
+    if !propagation.contradictions.is_empty() {
+        println!("  {} open edges can't take any tile: {:?}", propagation.contradictions.len(), propagation.contradictions);
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn place_shapes(
-    mut tiler: ResMut<PenroseTiler>,
-    mut edges: ResMut<EdgeLookup<PenroseRhombusType>>,
+    tiler: Res<PenroseTiler>,
+    mut tiling: ResMut<Tiling<RhombAngles, Rhombus>>,
+    mut history: ResMut<UndoStack<Rhombus>>,
+    mut growth: ResMut<Growth<RhombAngles, Rhombus>>,
+    mut supertiles: ResMut<SupertileOverlay>,
+    mut perp_space: ResMut<PerpSpaceOverlay>,
     keyboard_input: Res<Input<KeyCode>>,
     mut query: QuerySet<(
         Query<(Entity, &EdgeTile, &mut Rhombus, &Transform)>,
@@ -1057,63 +1140,226 @@ fn place_shapes(
         println!("START*****************************************************");
         let q0 = query.q0_mut();
 
-        let mut edge_vec: Vec<(Entity, Mut<Rhombus>, &Transform)> = q0.iter_mut().map(
+        let edge_vec: Vec<(Entity, Mut<Rhombus>, &Transform)> = q0.iter_mut().map(
             |(entity, _, rhombus, transform)| (entity, rhombus, transform)
         ).collect();
 
-        edge_vec.shuffle(&mut rand::thread_rng());
+        let open_edges: Vec<(Entity, u8, Vec<RhombAngles>)> = growth.order_edges(tiling.get_frontier()).into_iter()
+            .map(|edge| (edge.entity, edge.side, edge.legal_types.clone()))
+            .collect();
 
-        assert!(edge_vec.len() > 0);
-        for (existing_entity, rhombus, transform) in edge_vec.iter() {
-            println!("  Attempting to spawn tile on exisiting {:?} !", existing_entity);
-            if !rhombus.has_free_sides() {
-                // This can happen if this tile is marked to be removed but we're in the same tick that it happened
-                continue;
-            }
+        let mut choice = None;
+        for (existing_entity, side, legal_types) in open_edges.iter() {
+            println!("  Attempting to spawn tile on side {} of exisiting {:?} !", side, existing_entity);
+            let (_, rhombus, transform) = match edge_vec.iter().find(|(entity, _, _)| entity == existing_entity) {
+                Some(found) => found,
+                None => continue
+            };
 
             let existing_tile = TileWithTransform::new(& **rhombus, transform);
 
-            match tiler.spawn_random_tile_on(&existing_tile, &edges, &edge_vec, &mut commands) {
-                Some((new_tile, new_transform, new_entity)) => {
-                    let new_tile = TileWithTransform::new(&new_tile, &new_transform);
-                    edges.add_edges(&new_tile, &new_entity);
-                    println!("  Success!");
-                    break;
+            if let Some(penrose_type) = PenroseTiler::choose_random_type_on(&existing_tile, *side, legal_types, &edge_vec,
+                tiling.get_topology(), &growth.weights) {
+                choice = Some((*existing_entity, *side, penrose_type));
+                break;
+            }
+            growth.stats.failed_edges += 1;
+            println!("  Fail! Trying again");
+        }
+        drop(edge_vec);
+
+        if let Some((existing_entity, side, penrose_type)) = choice {
+            let entity = commands.spawn().id();
+            match tiling.place_on(existing_entity, side, penrose_type, entity) {
+                Ok(record) => {
+                    if let Some(tile) = tiling.get_tile(entity) {
+                        tiler.insert_tile(entity, tile, &mut commands);
+                    }
+                    sync_tile_components(&tiling, &record.touched, query.q1_mut(), &mut commands);
+                    history.records.push(record);
+                    growth.stats.add_placed(penrose_type);
+                    println!("  Success! Spawned {:?}", entity);
+                    if growth.propagate {
+                        propagate(&tiler, &mut tiling, &mut history, &mut growth, query.q1_mut(), &mut commands);
+                    }
                 },
-                None => {
-                    println!("  Fail! Trying again");
+                Err(error) => {
+                    commands.entity(entity).despawn();
+                    println!("  Placement rejected: {}", error);
                 }
             }
+            tiling.get_frontier_mut().update_overlay(&mut commands);
+            supertiles.update(&tiling, &mut commands);
+            perp_space.update(&tiling, &mut commands);
+        } else {
+            growth.stats.dead_ends += 1;
         }
 
         println!("END*****************************************************");
-        println!("");
-    } else if keyboard_input.just_pressed(KeyCode::U) && tiler.tiles_added.len() > 1 {
+        println!();
+    } else if keyboard_input.just_pressed(KeyCode::O) {
+        // Poses are exact, so tiles can be grouped by orientation without any tolerance
+        let mut counts = std::collections::BTreeMap::new();
+        for (_, rhombus, _) in query.q1_mut().iter_mut() {
+            *counts.entry((rhombus.penrose_type.to_string(), rhombus.pose.orientation)).or_insert(0) += 1;
+        }
+        for ((penrose_type, orientation), count) in counts {
+            println!("{} orientation {}: {}", penrose_type, orientation, count);
+        }
+    } else if keyboard_input.just_pressed(KeyCode::F) {
+        tiling.get_frontier().print();
+        tiling.get_frontier_mut().toggle_overlay(&mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::G) {
+        growth.next_strategy();
+        println!("Growth strategy: {}", growth.get_strategy_name());
+    } else if keyboard_input.just_pressed(KeyCode::P) {
+        growth.propagate = !growth.propagate;
+        println!("Forced tile propagation {}", if growth.propagate { "on" } else { "off" });
+        if growth.propagate {
+            propagate(&tiler, &mut tiling, &mut history, &mut growth, query.q1_mut(), &mut commands);
+            tiling.get_frontier_mut().update_overlay(&mut commands);
+            supertiles.update(&tiling, &mut commands);
+            perp_space.update(&tiling, &mut commands);
+        }
+    } else if keyboard_input.just_pressed(KeyCode::C) {
+        let composition = Composition::new(&tiling);
+        composition.print_report();
+        composition.print_labels();
+    } else if keyboard_input.just_pressed(KeyCode::L) {
+        supertiles.next_level(&tiling, &mut commands);
+        println!("Supertile outlines at level {}", supertiles.get_level());
+    } else if keyboard_input.just_pressed(KeyCode::K) {
+        supertiles.previous_level(&tiling, &mut commands);
+        println!("Supertile outlines at level {}", supertiles.get_level());
+    } else if keyboard_input.just_pressed(KeyCode::W) {
+        perp_space.toggle(&tiling, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::S) {
+        growth.print_statistics(tiling.get_frontier(), tiling.get_topology().get_num_tiles());
+    } else if keyboard_input.just_pressed(KeyCode::B) {
+        if let Some(record) = history.records.last() {
+            tiling.get_topology().print_statistics(record.entity);
+        }
+    } else if keyboard_input.just_pressed(KeyCode::U) {
+        let record = match history.records.pop() {
+            Some(record) => record,
+            None => return
+        };
         println!("START*****************************************************");
-        let entity = tiler.tiles_added.pop().unwrap();
-        println!("  Removing {:?}", entity);
-        let q1 = query.q1_mut();
-        let removed_rhombus: &Rhombus = q1.get_component::<Rhombus>(entity).unwrap();
-        let removed_transform: &Transform = q1.get_component::<Transform>(entity).unwrap();
-        let edge_data = edges.get_tiles_for_all_edges_excluding(&TileWithTransform::new(removed_rhombus, removed_transform), entity);
-        for v in edge_data {
-            if v.data.len() == 1 {
-                let data = &v.data[0];
-                let mut rhombus = query.q1_mut().get_component_mut::<Rhombus>(data.entity).unwrap();
-                rhombus.set_side_free(data.side);
-                commands.entity(data.entity).insert(EdgeTile);
-                println!("  SET FREE side {} of entity {:?}, edge ({:?} {:?})", data.side, data.entity, v.edge.start, v.edge.end);
-            } else if v.data.len() > 0 {
-                let data = &v.data[0];
-                println!("  NOT SETTING side {} of entity {:?} free as there are references OTHER than {:?} len {}",
-                data.side, data.entity, entity, v.data.len());
-            } else {
-                println!("  EMPTY EDGE DATA???");
+        println!("  Removing {:?}", record.entity);
+        match tiling.undo(&record) {
+            Ok(redo) => {
+                commands.entity(record.entity).despawn_recursive();
+                sync_tile_components(&tiling, &redo.touched, query.q1_mut(), &mut commands);
+            },
+            Err(error) => {
+                println!("  Undo rejected: {}", error);
+                history.records.push(record);
             }
         }
-        commands.entity(entity).despawn_recursive();
-        edges.remove_entity(entity);
+        tiling.get_frontier_mut().update_overlay(&mut commands);
+        supertiles.update(&tiling, &mut commands);
+        perp_space.update(&tiling, &mut commands);
         println!("END*****************************************************");
-        println!("");
+        println!();
+    }
+}
+
+// Hold Q to grow by the OSDS rules a tile per frame, R reports whether the patch is still defect-free
+#[allow(clippy::too_many_arguments)]
+fn osds_growth(
+    tiler: Res<PenroseTiler>,
+    mut osds: ResMut<OsdsGrowth<RhombAngles>>,
+    mut tiling: ResMut<Tiling<RhombAngles, Rhombus>>,
+    mut history: ResMut<UndoStack<Rhombus>>,
+    mut supertiles: ResMut<SupertileOverlay>,
+    mut perp_space: ResMut<PerpSpaceOverlay>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut query: Query<(Entity, &mut Rhombus, &Transform)>,
+    mut commands: Commands
+) {
+    if keyboard_input.pressed(KeyCode::Q) {
+        match osds.step(&mut tiling, &mut commands) {
+            Ok((step, Some(record))) => {
+                if let Some(tile) = tiling.get_tile(record.entity) {
+                    tiler.insert_tile(record.entity, tile, &mut commands);
+                }
+                sync_tile_components(&tiling, &record.touched, &mut query, &mut commands);
+                history.records.push(record);
+                if step == OsdsStep::Fallback {
+                    println!("No forced vertex, added a fallback tile");
+                }
+            },
+            Ok(_) => {
+                if keyboard_input.just_pressed(KeyCode::Q) {
+                    println!("No forced vertex and no corner to fall back on");
+                }
+            },
+            Err(error) => println!("OSDS step failed: {}", error)
+        }
+        tiling.get_frontier_mut().update_overlay(&mut commands);
+        supertiles.update(&tiling, &mut commands);
+        perp_space.update(&tiling, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::R) {
+        if let Err(error) = osds.print_report(&mut tiling) {
+            println!("Couldn't check for defects: {}", error);
+        }
+    }
+}
+
+// N clears everything and starts again from the next seed in the library
+#[allow(clippy::too_many_arguments)]
+fn choose_seed(
+    tiler: Res<PenroseTiler>,
+    mut seeds: ResMut<SeedLibrary>,
+    mut tiling: ResMut<Tiling<RhombAngles, Rhombus>>,
+    mut history: ResMut<UndoStack<Rhombus>>,
+    mut growth: ResMut<Growth<RhombAngles, Rhombus>>,
+    mut osds: ResMut<OsdsGrowth<RhombAngles>>,
+    mut inspector: ResMut<PlacementInspector>,
+    mut supertiles: ResMut<SupertileOverlay>,
+    mut perp_space: ResMut<PerpSpaceOverlay>,
+    keyboard_input: Res<Input<KeyCode>>,
+    query: Query<Entity, With<Rhombus>>,
+    mut commands: Commands
+) {
+    if !keyboard_input.just_pressed(KeyCode::N) {
+        return;
+    }
+
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
     }
-}
\ No newline at end of file
+    tiling.get_frontier_mut().clear_overlay(&mut commands);
+    inspector.clear(&mut commands);
+    *tiling = Tiling::default();
+    history.records.clear();
+    growth.reset_statistics();
+    osds.reset();
+
+    seeds.next();
+    place_seed(&seeds, &growth.weights, &tiler, &mut tiling, &mut commands);
+    supertiles.update(&tiling, &mut commands);
+    perp_space.update(&tiling, &mut commands);
+}
+
+fn inspect_placements(
+    mut inspector: ResMut<PlacementInspector>,
+    tiling: Res<Tiling<RhombAngles, Rhombus>>,
+    mouse_input: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    mut commands: Commands
+) {
+    if mouse_input.just_pressed(MouseButton::Left) {
+        let window = match windows.get_primary() {
+            Some(window) => window,
+            None => return
+        };
+        if let Some(cursor) = window.cursor_position() {
+            // The camera sits at the origin, so world coordinates are the cursor's relative to the window centre
+            let position = cursor - Vec2::new(window.width(), window.height()) / 2.0;
+            inspector.inspect(&*tiling, position, &mut commands);
+        }
+    } else if mouse_input.just_pressed(MouseButton::Right) {
+        inspector.clear(&mut commands);
+    }
+}
@@ -0,0 +1,315 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy::ecs::system::EntityCommands;
+use bevy::input::{keyboard::KeyCode, Input};
+use rand::prelude::*;
+use std::fmt;
+
+use crate::{rhombus_points, PenroseEnum, PointList, TileShape};
+
+// De Bruijn's multigrid construction: n families of parallel lines with unit normals at j * 180 / n degrees,
+// family j shifted by offsets[j]. Every crossing of a line from family j with one from family k becomes a
+// rhombus with edges along the normals of both families. n = 5 gives the Penrose rhombs and n = 4 the
+// Ammann-Beenker tiling, larger n gives rhombus tilings with 2n-fold symmetry.
+//
+// Tile vertices come out as integer combinations of the normals, so they are points of Z^n and can be
+// compared exactly.
+//
+// The tilings are generated whole and drawn, nothing here goes through Tiling. Rhombs carry their angle pair
+// as their type, as the grown Penrose rhombs do.
+
+pub const MAX_FAMILIES: u8 = 24;
+
+const MULTIGRID_LEG_LEN: f32 = 30.0;
+const MULTIGRID_RADIUS: i32 = 5;
+
+pub type LatticePoint = Vec<i32>;
+
+pub struct Multigrid {
+    normals: Vec<Vec2>,
+    offsets: Vec<f32>
+}
+
+// A rhombus of the dual tiling, vertices in Z^n going round from the corner with the lowest coordinates
+// along families.0 first
+pub struct GridRhomb {
+    pub families: (usize, usize),
+    pub vertices: [LatticePoint; 4]
+}
+
+impl Multigrid {
+    pub fn new(offsets: Vec<f32>) -> Self {
+        let n = offsets.len();
+        assert!(n >= 3);
+        let normals = (0..n).map(|j| {
+            let angle = std::f32::consts::PI * j as f32 / n as f32;
+            Vec2::new(angle.cos(), angle.sin())
+        }).collect();
+        Multigrid {
+            normals,
+            offsets
+        }
+    }
+
+    // Random offsets are generic with probability 1, so no three lines meet in a point
+    pub fn new_random(n: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        Multigrid::new((0..n).map(|_| rng.gen::<f32>()).collect())
+    }
+
+    pub fn get_num_families(&self) -> usize {
+        self.normals.len()
+    }
+
+    pub fn get_offsets(&self) -> &[f32] {
+        &self.offsets
+    }
+
+    pub fn to_world(&self, point: &LatticePoint, leg_len: f32) -> Vec2 {
+        let mut p = Vec2::ZERO;
+        for (i, normal) in self.normals.iter().enumerate() {
+            p += *normal * point[i] as f32;
+        }
+        p * leg_len
+    }
+
+    // All rhombs for crossings within `radius` of the origin
+    pub fn dual_tiles(&self, radius: i32) -> Vec<GridRhomb> {
+        let n = self.get_num_families();
+        let mut rhombs = Vec::new();
+
+        for j in 0..n {
+            for k in (j + 1)..n {
+                let (a, b) = (self.normals[j], self.normals[k]);
+                let det = a.x * b.y - a.y * b.x;
+                for n_j in -radius..=radius {
+                    for n_k in -radius..=radius {
+                        // Solve x . normal_j + offset_j = n_j and x . normal_k + offset_k = n_k
+                        let r_j = n_j as f32 - self.offsets[j];
+                        let r_k = n_k as f32 - self.offsets[k];
+                        let crossing = Vec2::new(r_j * b.y - r_k * a.y, a.x * r_k - b.x * r_j) / det;
+                        if crossing.length() > radius as f32 {
+                            continue;
+                        }
+
+                        let base: LatticePoint = (0..n).map(|i| {
+                            if i == j {
+                                n_j - 1
+                            } else if i == k {
+                                n_k - 1
+                            } else {
+                                (crossing.dot(self.normals[i]) + self.offsets[i]).floor() as i32
+                            }
+                        }).collect();
+
+                        let mut step_j = base.clone();
+                        step_j[j] += 1;
+                        let mut step_both = step_j.clone();
+                        step_both[k] += 1;
+                        let mut step_k = base.clone();
+                        step_k[k] += 1;
+
+                        rhombs.push(GridRhomb {
+                            families: (j, k),
+                            vertices: [base, step_j, step_both, step_k]
+                        });
+                    }
+                }
+            }
+        }
+
+        rhombs
+    }
+}
+
+// A rhombus's angles as multiples of 180 / n degrees, small angle first. Penrose's skinny rhomb is
+// (1, 4) for n = 5 and the fat one (2, 3).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RhombAngles {
+    pub n: u8,
+    pub small: u8
+}
+
+impl RhombAngles {
+    pub const PENROSE_SKINNY: RhombAngles = RhombAngles { n: 5, small: 1 };
+    pub const PENROSE_FAT: RhombAngles = RhombAngles { n: 5, small: 2 };
+
+    pub fn from_families(n: usize, j: usize, k: usize) -> Self {
+        let m = k - j;
+        RhombAngles {
+            n: n as u8,
+            small: m.min(n - m) as u8
+        }
+    }
+
+    pub fn get_large(&self) -> u8 {
+        self.n - self.small
+    }
+
+    pub fn get_small_angle(&self) -> f32 {
+        std::f32::consts::PI * self.small as f32 / self.n as f32
+    }
+
+    // Area of a rhomb with unit edges
+    pub fn get_area(&self) -> f32 {
+        self.get_small_angle().sin()
+    }
+
+    pub fn get_color(&self) -> Color {
+        let hue = 360.0 * (self.small as f32 - 1.0) / (self.n / 2) as f32;
+        Color::hsl(hue, 0.6, 0.6)
+    }
+}
+
+impl fmt::Display for RhombAngles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Whole degrees where they are, so the Penrose pairs read 36°/144° and 72°/108°
+        let unit = 180.0 / self.n as f32;
+        let precision = if 180 % self.n as u32 == 0 { 0 } else { 1 };
+        write!(f, "{:.*}°/{:.*}°", precision, unit * self.small as f32, precision, unit * self.get_large() as f32)
+    }
+}
+
+impl PenroseEnum for RhombAngles {
+    // Every angle pair a multigrid with up to MAX_FAMILIES families can produce
+    fn get_all() -> Vec<Self> {
+        let mut vec = Vec::new();
+        for n in 3..=MAX_FAMILIES {
+            for small in 1..=(n / 2) {
+                vec.push(RhombAngles { n, small });
+            }
+        }
+        vec
+    }
+}
+
+#[derive(Clone)]
+pub struct MultigridRhombus {
+    angles: RhombAngles,
+    leg_len: f32
+}
+
+impl MultigridRhombus {
+    // Tile and transform for a dual rhomb, with the long diagonal between its acute corners
+    pub fn new_from_grid(grid: &Multigrid, rhomb: &GridRhomb, leg_len: f32) -> (Self, Transform) {
+        let n = grid.get_num_families();
+        let (j, k) = rhomb.families;
+        let angles = RhombAngles::from_families(n, j, k);
+        let corners: Vec<Vec2> = rhomb.vertices.iter().map(|v| grid.to_world(v, leg_len)).collect();
+        let (left, right) = if 2 * (k - j) <= n {
+            (corners[0], corners[2])
+        } else {
+            (corners[1], corners[3])
+        };
+
+        let diagonal = right - left;
+        let centre = (left + right) / 2.0;
+        let tile = MultigridRhombus {
+            angles,
+            leg_len
+        };
+        let transform = Transform {
+            translation: centre.extend(0.0),
+            rotation: Quat::from_rotation_z(diagonal.y.atan2(diagonal.x)),
+            scale: Vec3::ONE
+        };
+        (tile, transform)
+    }
+}
+
+impl TileShape<RhombAngles> for MultigridRhombus {
+    fn get_points(&self) -> PointList {
+        rhombus_points(self.angles.get_small_angle(), self.leg_len)
+    }
+
+    fn get_type(&self) -> RhombAngles {
+        self.angles
+    }
+
+    fn insert_shape_component(&self, transform: Transform, entity_commands: &mut EntityCommands) {
+        entity_commands.insert_bundle(
+            GeometryBuilder::build_as(
+                &shapes::Polygon {
+                    points: self.get_points(),
+                    closed: true
+                },
+                ShapeColors::outlined(self.angles.get_color(), Color::BLACK),
+                DrawMode::Outlined {
+                    fill_options: FillOptions::default(),
+                    outline_options: StrokeOptions::default().with_line_width(1.0),
+                },
+                transform
+            )
+        );
+    }
+
+    fn spawn_dots_entities(&self, _parent: Entity, _commands: &mut Commands) {
+    }
+}
+
+pub struct MultigridTiler {
+    num_families: usize,
+    entities: Vec<Entity>
+}
+
+impl MultigridTiler {
+    pub fn new(num_families: usize) -> Self {
+        MultigridTiler {
+            num_families: num_families.max(3).min(MAX_FAMILIES as usize),
+            entities: Vec::new()
+        }
+    }
+
+    fn regenerate(&mut self, commands: &mut Commands) {
+        for entity in self.entities.drain(..) {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        let grid = Multigrid::new_random(self.num_families);
+        let rhombs = grid.dual_tiles(MULTIGRID_RADIUS);
+        let mut angles = Vec::new();
+        for rhomb in &rhombs {
+            let (tile, transform) = MultigridRhombus::new_from_grid(&grid, rhomb, MULTIGRID_LEG_LEN);
+            let mut entity = commands.spawn();
+            tile.insert_shape_component(transform, &mut entity);
+            entity.insert(tile.clone());
+            self.entities.push(entity.id());
+            angles.push(tile.get_type());
+        }
+
+        print_statistics(self.num_families, &angles);
+    }
+}
+
+// Counts and area shares per rhomb shape. For n = 5 the fat to skinny ratio should approach the golden ratio.
+fn print_statistics(n: usize, angles: &[RhombAngles]) {
+    println!("{}-grid, {} rhombs", n, angles.len());
+    let total_area: f32 = angles.iter().map(|a| a.get_area()).sum();
+    for rhomb_type in RhombAngles::get_all().into_iter().filter(|a| a.n as usize == n) {
+        let count = angles.iter().filter(|a| **a == rhomb_type).count();
+        let area = count as f32 * rhomb_type.get_area();
+        println!("  {}: {} ({:.3} of tiles, {:.3} of area)", rhomb_type, count,
+            count as f32 / angles.len() as f32, area / total_area);
+    }
+}
+
+pub fn setup_multigrid(mut commands: Commands, mut tiler: ResMut<MultigridTiler>) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    tiler.regenerate(&mut commands);
+}
+
+pub fn multigrid_controls(
+    mut tiler: ResMut<MultigridTiler>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        tiler.regenerate(&mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::Up) && tiler.num_families < MAX_FAMILIES as usize {
+        tiler.num_families += 1;
+        tiler.regenerate(&mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::Down) && tiler.num_families > 3 {
+        tiler.num_families -= 1;
+        tiler.regenerate(&mut commands);
+    }
+}
@@ -0,0 +1,187 @@
+use bevy::prelude::*;
+
+use crate::tiling::{Tiling, TilingError, UndoRecord, SCRATCH_ENTITY};
+use crate::{PenroseEnum, Tile};
+
+// Growth after Onoda, Steinhardt, DiVincenzo and Socolar, "Growing perfect quasicrystals" (doc/growthQC.pdf).
+// Tiles are only added at forced vertices: surface vertices where one of the open edges can take exactly
+// one tile type that still lets the vertex be completed under the matching rules. When no vertex is forced
+// a fat tile is added at a corner of the surface, a 108 degree notch if there is one, otherwise the corner
+// whose opening comes closest. Started from a decapod the patch should grow without defects, a defect shows
+// up as a surface vertex that can no longer be completed at all.
+
+const EPSILON: f32 = 0.001;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OsdsStep {
+    Forced,
+    Fallback,
+    // Neither a forced vertex nor a corner the fallback tile fits
+    Stuck
+}
+
+pub struct OsdsGrowth<P: PenroseEnum> {
+    fallback_type: P,
+    fallback_angle: f32,
+    pub forced: usize,
+    pub fallbacks: usize,
+    pub stuck: usize
+}
+
+impl<P: PenroseEnum + PartialEq> OsdsGrowth<P> {
+    // The tile type added when nothing is forced, and the opening of the corners it prefers to go in
+    pub fn new(fallback_type: P, fallback_angle: f32) -> Self {
+        OsdsGrowth {
+            fallback_type,
+            fallback_angle,
+            forced: 0,
+            fallbacks: 0,
+            stuck: 0
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.forced = 0;
+        self.fallbacks = 0;
+        self.stuck = 0;
+    }
+
+    pub fn step<T: Tile<P>>(&mut self, tiling: &mut Tiling<P, T>, commands: &mut Commands) -> Result<(OsdsStep, Option<UndoRecord<T>>), TilingError> {
+        let vertices = get_surface_vertices(tiling);
+
+        for vertex in &vertices {
+            for (entity, side) in get_open_edges_at(tiling, vertex) {
+                let options = get_completable_types(tiling, vertex, entity, side)?;
+                if options.len() == 1 {
+                    let record = place(tiling, entity, side, options[0], commands)?;
+                    self.forced += 1;
+                    return Ok((OsdsStep::Forced, Some(record)));
+                }
+            }
+        }
+
+        // Corners whose open angle is closest to the fallback angle are tried first
+        let mut corners: Vec<(f32, T::Vertex)> = vertices.iter().map(|vertex| {
+            let open_angle = 2.0 * std::f32::consts::PI - tiling.get_topology().get_filled_angle(vertex);
+            ((open_angle - self.fallback_angle).abs(), *vertex)
+        }).collect();
+        corners.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        for (_, vertex) in &corners {
+            for (entity, side) in get_open_edges_at(tiling, vertex) {
+                let options = get_completable_types(tiling, vertex, entity, side)?;
+                if options.contains(&self.fallback_type) {
+                    let record = place(tiling, entity, side, self.fallback_type, commands)?;
+                    self.fallbacks += 1;
+                    return Ok((OsdsStep::Fallback, Some(record)));
+                }
+            }
+        }
+
+        self.stuck += 1;
+        Ok((OsdsStep::Stuck, None))
+    }
+
+    // Every surface vertex that can't be completed any more
+    pub fn find_defects<T: Tile<P>>(&self, tiling: &mut Tiling<P, T>) -> Result<Vec<T::Vertex>, TilingError> {
+        let mut defects = Vec::new();
+        for vertex in get_surface_vertices(tiling) {
+            if count_completions(tiling, &vertex, 1, 0)? == 0 {
+                defects.push(vertex);
+            }
+        }
+        Ok(defects)
+    }
+
+    pub fn print_report<T: Tile<P>>(&self, tiling: &mut Tiling<P, T>) -> Result<(), TilingError> {
+        let defects = self.find_defects(tiling)?;
+        println!("OSDS growth: {} forced, {} fallback {} tiles, {} stuck steps, {} tiles",
+            self.forced, self.fallbacks, self.fallback_type, self.stuck, tiling.get_topology().get_num_tiles());
+        if defects.is_empty() {
+            println!("  defect-free, every surface vertex can still be completed");
+        } else {
+            println!("  {} defects at {:?}", defects.len(), defects);
+        }
+        Ok(())
+    }
+}
+
+fn place<P: PenroseEnum, T: Tile<P>>(tiling: &mut Tiling<P, T>, on_entity: Entity, side: u8, penrose_type: P, commands: &mut Commands) -> Result<UndoRecord<T>, TilingError> {
+    let entity = commands.spawn().id();
+    tiling.place_on(on_entity, side, penrose_type, entity).inspect_err(|_error| {
+        commands.entity(entity).despawn();
+    })
+}
+
+// Vertices on open edges, in the order the edges were opened so growth is repeatable
+fn get_surface_vertices<P: PenroseEnum, T: Tile<P>>(tiling: &Tiling<P, T>) -> Vec<T::Vertex> {
+    let mut edges: Vec<(u64, T::Vertex, T::Vertex)> = tiling.get_frontier().get_open_edges().map(|edge| {
+        let vertices = edge.tile.get_vertices();
+        (edge.age, vertices[edge.side as usize], vertices[(edge.side as usize + 1) % vertices.len()])
+    }).collect();
+    edges.sort_by_key(|(age, _, _)| *age);
+
+    let mut vertices = Vec::new();
+    for (_, start, end) in edges {
+        for vertex in [start, end].iter() {
+            if !vertices.contains(vertex) {
+                vertices.push(*vertex);
+            }
+        }
+    }
+    vertices
+}
+
+pub fn get_open_edges_at<P: PenroseEnum, T: Tile<P>>(tiling: &Tiling<P, T>, vertex: &T::Vertex) -> Vec<(Entity, u8)> {
+    let mut edges: Vec<(u64, Entity, u8)> = tiling.get_frontier().get_open_edges().filter(|edge| {
+        let vertices = edge.tile.get_vertices();
+        vertices[edge.side as usize] == *vertex || vertices[(edge.side as usize + 1) % vertices.len()] == *vertex
+    }).map(|edge| (edge.age, edge.entity, edge.side)).collect();
+    edges.sort_by_key(|(age, _, _)| *age);
+    edges.into_iter().map(|(_, entity, side)| (entity, side)).collect()
+}
+
+fn get_allowed_types<P: PenroseEnum, T: Tile<P>>(tiling: &Tiling<P, T>, entity: Entity, side: u8) -> Result<Vec<P>, TilingError> {
+    Ok(tiling.inspect_side(entity, side)?.into_iter()
+        .filter(|(_, check)| check.is_allowed())
+        .map(|(penrose_type, _)| penrose_type)
+        .collect())
+}
+
+// Types that can go on the edge with the vertex still completable afterwards
+fn get_completable_types<P: PenroseEnum, T: Tile<P>>(tiling: &mut Tiling<P, T>, vertex: &T::Vertex, entity: Entity, side: u8) -> Result<Vec<P>, TilingError> {
+    let mut completable = Vec::new();
+    for penrose_type in get_allowed_types(tiling, entity, side)? {
+        let record = tiling.place_on(entity, side, penrose_type, Entity::new(SCRATCH_ENTITY))?;
+        let completions = count_completions(tiling, vertex, 1, 1);
+        tiling.undo(&record)?;
+        if completions? > 0 {
+            completable.push(penrose_type);
+        }
+    }
+    Ok(completable)
+}
+
+// Ways of filling the rest of the turn round the vertex, counting up to the limit. Tiles are always added on
+// the oldest open edge at the vertex, so each way is only found once.
+fn count_completions<P: PenroseEnum, T: Tile<P>>(tiling: &mut Tiling<P, T>, vertex: &T::Vertex, limit: usize, depth: u32) -> Result<usize, TilingError> {
+    if tiling.get_topology().get_filled_angle(vertex) > 2.0 * std::f32::consts::PI - EPSILON {
+        return Ok(1);
+    }
+    let (entity, side) = match get_open_edges_at(tiling, vertex).first() {
+        Some(edge) => *edge,
+        None => return Ok(0)
+    };
+
+    let mut count = 0;
+    for penrose_type in get_allowed_types(tiling, entity, side)? {
+        let record = tiling.place_on(entity, side, penrose_type, Entity::new(SCRATCH_ENTITY - depth))?;
+        let completions = count_completions(tiling, vertex, limit - count, depth + 1);
+        tiling.undo(&record)?;
+        count += completions?;
+        if count >= limit {
+            break;
+        }
+    }
+    Ok(count)
+}
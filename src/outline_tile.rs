@@ -0,0 +1,105 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy::ecs::system::EntityCommands;
+
+use crate::affine::Affine2;
+use crate::{PenroseEnum, PointList, TileShape};
+
+// Tiles with one fixed outline, like the hat and the spectre, that substitutions place by affine maps
+// which can reflect them. A Bevy Transform can't reflect, so the placement is split into the rigid part,
+// which becomes the Transform, and the chirality and size, which the tile keeps and applies to the outline.
+
+pub(crate) trait OutlineType : PenroseEnum {
+    fn get_outline() -> &'static [Vec2];
+    fn get_color(&self) -> Color;
+}
+
+#[derive(Clone)]
+pub struct OutlineTile<P> {
+    tile_type: P,
+    // From the outline to the world, reflection included
+    placement: Affine2,
+    // Drawn instead of the outline, e.g. with curved edges, and reflected along with it
+    drawn_outline: Option<&'static [Vec2]>,
+    // For sets that grow through the tiling, a bit for each side with another tile on it
+    pub(crate) used_side_flags: u32
+}
+
+impl<P: OutlineType> OutlineTile<P> {
+    // Splits a placement into the Transform Bevy can represent and the tile's own chirality
+    pub fn new(tile_type: P, placement: &Affine2) -> (Self, Transform) {
+        let tile = OutlineTile {
+            tile_type,
+            placement: *placement,
+            drawn_outline: None,
+            used_side_flags: 0
+        };
+        (tile.clone(), tile.get_rigid_transform())
+    }
+
+    pub fn with_drawn_outline(mut self, outline: &'static [Vec2]) -> Self {
+        self.drawn_outline = Some(outline);
+        self
+    }
+
+    pub fn get_placement(&self) -> &Affine2 {
+        &self.placement
+    }
+
+    pub fn get_rigid_transform(&self) -> Transform {
+        self.placement.split_reflection().0.to_transform(0.0)
+    }
+
+    // The outline where the placement puts it, anticlockwise like get_points
+    pub fn get_world_points(&self) -> PointList {
+        orient(self.placement.transform_points(P::get_outline()), self.placement.is_reflection())
+    }
+
+    // Mirrored and scaled, with the rest of the placement left to the Transform
+    fn place_outline(&self, outline: &[Vec2]) -> PointList {
+        let (rigid, reflected) = self.placement.split_reflection();
+        let mirror = if reflected { -1.0 } else { 1.0 };
+        let size = rigid.uniform_scale();
+        orient(outline.iter().map(|p| Vec2::new(p.x, mirror * p.y) * size).collect(), reflected)
+    }
+}
+
+// A reflected outline runs clockwise, so its corners are listed in reverse to keep every tile anticlockwise
+fn orient(mut points: PointList, reflected: bool) -> PointList {
+    if reflected {
+        points.reverse();
+    }
+    points
+}
+
+impl<P: OutlineType + Send + Sync + 'static> TileShape<P> for OutlineTile<P> {
+    // Always the outline itself, whatever is drawn
+    fn get_points(&self) -> PointList {
+        self.place_outline(P::get_outline())
+    }
+
+    fn get_type(&self) -> P {
+        self.tile_type
+    }
+
+    fn insert_shape_component(&self, transform: Transform, entity_commands: &mut EntityCommands) {
+        entity_commands.insert_bundle(
+            GeometryBuilder::build_as(
+                &shapes::Polygon {
+                    points: self.place_outline(self.drawn_outline.unwrap_or_else(P::get_outline)),
+                    closed: true
+                },
+                ShapeColors::outlined(self.tile_type.get_color(), Color::BLACK),
+                DrawMode::Outlined {
+                    fill_options: FillOptions::default(),
+                    outline_options: StrokeOptions::default().with_line_width(1.5),
+                },
+                transform
+            )
+        );
+    }
+
+    fn spawn_dots_entities(&self, _parent: Entity, _commands: &mut Commands) {
+        // The shape is its own matching rule, there are no decorations
+    }
+}
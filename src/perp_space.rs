@@ -0,0 +1,260 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::geometry;
+use crate::lattice::PentagonalPoint;
+use crate::multigrid::RhombAngles;
+use crate::tiling::Tiling;
+use crate::{Rhombus, Tile};
+
+// The cut and project picture of a rhomb patch. Every vertex is a point of Z^5, and projecting it the other
+// way, into perpendicular space, puts it in one of four pentagons according to the sum of its coordinates.
+// That is the acceptance window. A patch of a Penrose tiling has all of its vertices inside, for some
+// shift of the window, and a vertex that can't be fitted in with the rest is a defect even where every
+// edge around it matches.
+// The sum only matters modulo 5 and only up to a constant, the tiling fixes neither, so the class with no
+// vertices is taken to be the empty fifth window and the rest are numbered from it.
+
+const NUM_CLASSES: usize = 5;
+
+// Windows are enlarged by this fraction so vertices on their boundary still count as inside
+const WINDOW_TOLERANCE: f32 = 0.001;
+
+// How far the corners of the two larger windows are from their centre, the golden ratio
+const WINDOW_RADIUS: f32 = 1.618034;
+
+// Side panel, in world units, which are pixels since the camera never moves
+const PANEL_X: f32 = 470.0;
+const PANEL_Y: f32 = 170.0;
+const PANEL_CELL: f32 = 170.0;
+const PANEL_SCALE: f32 = 45.0;
+const DOT_RADIUS: f32 = 2.0;
+const OUTLINE_WIDTH: f32 = 2.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PerpVertex {
+    pub point: PentagonalPoint,
+    // Window 1 to 4, or 0 for the class that should have no vertices
+    pub window: usize,
+    // Relative to the fitted window centre
+    pub perp: Vec2,
+    pub defect: bool
+}
+
+pub struct WindowAnalysis {
+    pub vertices: Vec<PerpVertex>,
+    pub centre: Vec2,
+    pub empty_class: i32
+}
+
+impl WindowAnalysis {
+    // Vertices closest to the origin are fitted first, so a defect in a grown patch is blamed on the
+    // vertices added around it rather than on the seed
+    pub fn new(points: impl IntoIterator<Item = PentagonalPoint>) -> Self {
+        let mut points: Vec<PentagonalPoint> = points.into_iter().collect::<HashSet<_>>().into_iter().collect();
+        points.sort_by(|a, b| {
+            a.to_world(1.0).length().partial_cmp(&b.to_world(1.0).length())
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.cmp(b))
+        });
+
+        let mut counts = [0; NUM_CLASSES];
+        for point in points.iter() {
+            counts[point.get_index_class() as usize] += 1;
+        }
+
+        // A small patch can leave several classes empty, any of them could be the one without a window
+        let fewest = counts.iter().min().copied().unwrap_or(0);
+        let mut best: Option<WindowAnalysis> = None;
+        for empty_class in (0..NUM_CLASSES as i32).filter(|class| counts[*class as usize] == fewest) {
+            let analysis = WindowAnalysis::fit(&points, empty_class);
+            if best.as_ref().is_none_or(|best| analysis.get_num_defects() < best.get_num_defects()) {
+                best = Some(analysis);
+            }
+        }
+        best.unwrap_or(WindowAnalysis {
+            vertices: Vec::new(),
+            centre: Vec2::ZERO,
+            empty_class: 0
+        })
+    }
+
+    pub fn from_tiling(tiling: &Tiling<RhombAngles, Rhombus>) -> Self {
+        WindowAnalysis::new(tiling.get_tiles().flat_map(|(_, tile)| tile.get_vertices()))
+    }
+
+    // Narrows down the centres that keep every vertex so far inside its window, a vertex that would leave
+    // none is a defect and is left out
+    fn fit(points: &[PentagonalPoint], empty_class: i32) -> Self {
+        let windows: Vec<Vec<Vec2>> = (0..NUM_CLASSES).map(|i| get_window(i, 1.0 + WINDOW_TOLERANCE)).collect();
+        let mut centres: Option<Vec<Vec2>> = None;
+        let mut vertices = Vec::new();
+        for point in points {
+            let window = (point.get_index_class() - empty_class).rem_euclid(NUM_CLASSES as i32) as usize;
+            let perp = point.to_perp();
+            let mut defect = window == 0;
+            if !defect {
+                // Centres c with perp - c inside the window
+                let allowed: Vec<Vec2> = windows[window].iter().map(|w| perp - *w).collect();
+                let clipped = match &centres {
+                    Some(centres) => geometry::clip_convex(centres, &allowed),
+                    None => allowed
+                };
+                if clipped.len() >= 3 && geometry::signed_area(&clipped) > 0.0 {
+                    centres = Some(clipped);
+                } else {
+                    defect = true;
+                }
+            }
+            vertices.push(PerpVertex {
+                point: *point,
+                window,
+                perp,
+                defect
+            });
+        }
+
+        let centre = centres.map_or(Vec2::ZERO, |centres| centres.iter().fold(Vec2::ZERO, |sum, p| sum + *p) / centres.len() as f32);
+        for vertex in vertices.iter_mut() {
+            vertex.perp -= centre;
+        }
+        WindowAnalysis {
+            vertices,
+            centre,
+            empty_class
+        }
+    }
+
+    pub fn get_num_defects(&self) -> usize {
+        self.vertices.iter().filter(|vertex| vertex.defect).count()
+    }
+
+    pub fn print_report(&self) {
+        let mut counts = BTreeMap::new();
+        for vertex in self.vertices.iter() {
+            *counts.entry(vertex.window).or_insert(0) += 1;
+        }
+        println!("{} vertices, index class {} empty, window centre ({:.3}, {:.3})",
+            self.vertices.len(), self.empty_class, self.centre.x, self.centre.y);
+        for (window, count) in counts {
+            println!("  window {}: {}", window, count);
+        }
+
+        let defects: Vec<&PerpVertex> = self.vertices.iter().filter(|vertex| vertex.defect).collect();
+        if defects.is_empty() {
+            println!("  Every vertex is inside the window");
+        } else {
+            println!("  {} vertices outside the window:", defects.len());
+            for vertex in defects {
+                println!("    {:?} in window {} at ({:.3}, {:.3})",
+                    vertex.point.get_coordinates_5d(), vertex.window, vertex.perp.x, vertex.perp.y);
+            }
+        }
+    }
+}
+
+// Window i is the hull of the sums of i of the five projected unit vectors. Those sum to zero, so every
+// window is centred on the origin.
+pub fn get_window(i: usize, scale: f32) -> Vec<Vec2> {
+    let units: Vec<Vec2> = (0..NUM_CLASSES).map(|k| PentagonalPoint::unit(2 * k as i32).to_perp()).collect();
+
+    let sums: Vec<Vec2> = (0..1usize << NUM_CLASSES).filter(|subset| subset.count_ones() as usize == i).map(|subset| {
+        (0..NUM_CLASSES).filter(|k| subset & (1 << k) != 0).fold(Vec2::ZERO, |sum, k| sum + units[k]) * scale
+    }).collect();
+    geometry::convex_hull(&sums)
+}
+
+// Hue from the direction away from the window centre, lighter further out
+fn get_perp_color(perp: Vec2) -> Color {
+    let hue = perp.y.atan2(perp.x).to_degrees().rem_euclid(360.0);
+    let lightness = 0.3 + 0.4 * (perp.length() / WINDOW_RADIUS).min(1.0);
+    Color::hsla(hue, 0.8, lightness, 0.7)
+}
+
+// W toggles a side panel with the vertices plotted in their windows and the tiles coloured by where
+// their corners project to
+#[derive(Default)]
+pub struct PerpSpaceOverlay {
+    visible: bool,
+    entities: Vec<Entity>
+}
+
+impl PerpSpaceOverlay {
+    pub fn toggle(&mut self, tiling: &Tiling<RhombAngles, Rhombus>, commands: &mut Commands) {
+        self.visible = !self.visible;
+        if self.visible {
+            WindowAnalysis::from_tiling(tiling).print_report();
+        }
+        self.update(tiling, commands);
+    }
+
+    pub fn update(&mut self, tiling: &Tiling<RhombAngles, Rhombus>, commands: &mut Commands) {
+        self.clear(commands);
+        if !self.visible {
+            return;
+        }
+
+        let analysis = WindowAnalysis::from_tiling(tiling);
+        let by_point: std::collections::HashMap<PentagonalPoint, &PerpVertex> =
+            analysis.vertices.iter().map(|vertex| (vertex.point, vertex)).collect();
+        for (_, tile) in tiling.get_tiles() {
+            let corners: Vec<&PerpVertex> = tile.get_vertices().iter().filter_map(|point| by_point.get(point).copied()).collect();
+            let color = if corners.iter().any(|vertex| vertex.defect) {
+                Color::rgba(0.0, 0.0, 0.0, 0.8)
+            } else {
+                get_perp_color(corners.iter().fold(Vec2::ZERO, |sum, vertex| sum + vertex.perp) / corners.len().max(1) as f32)
+            };
+            let points = tile.get_vertices().iter().map(|point| point.to_world(tile.leg_len)).collect();
+            self.spawn(commands, &shapes::Polygon { points, closed: true }, color,
+                DrawMode::Fill(FillOptions::default()), 1.5);
+        }
+
+        let panel_centre = Vec2::new(PANEL_X, PANEL_Y);
+        let half_size = PANEL_CELL;
+        let background = vec![
+            panel_centre + Vec2::new(-half_size, -half_size),
+            panel_centre + Vec2::new(half_size, -half_size),
+            panel_centre + Vec2::new(half_size, half_size),
+            panel_centre + Vec2::new(-half_size, half_size)
+        ];
+        self.spawn(commands, &shapes::Polygon { points: background, closed: true }, Color::rgba(1.0, 1.0, 1.0, 0.9),
+            DrawMode::Fill(FillOptions::default()), 4.0);
+
+        // Windows 1 and 2 along the top, 3 and 4 below
+        for window in 1..NUM_CLASSES {
+            let cell_centre = get_cell_centre(window);
+            let outline = get_window(window, PANEL_SCALE).into_iter().map(|p| cell_centre + p).collect();
+            self.spawn(commands, &shapes::Polygon { points: outline, closed: true }, Color::DARK_GRAY,
+                DrawMode::Stroke(StrokeOptions::default().with_line_width(OUTLINE_WIDTH)), 4.1);
+        }
+        for vertex in analysis.vertices.iter().filter(|vertex| vertex.window != 0) {
+            let color = if vertex.defect { Color::RED } else { get_perp_color(vertex.perp) };
+            let circle = shapes::Circle {
+                radius: DOT_RADIUS,
+                center: get_cell_centre(vertex.window) + vertex.perp * PANEL_SCALE
+            };
+            self.spawn(commands, &circle, color, DrawMode::Fill(FillOptions::default()), 4.2);
+        }
+    }
+
+    pub fn clear(&mut self, commands: &mut Commands) {
+        for entity in self.entities.drain(..) {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    fn spawn(&mut self, commands: &mut Commands, shape: &impl Geometry, color: Color, mode: DrawMode, z: f32) {
+        let mut entity = commands.spawn();
+        entity.insert_bundle(
+            GeometryBuilder::build_as(shape, ShapeColors::new(color), mode, Transform::from_xyz(0.0, 0.0, z))
+        );
+        self.entities.push(entity.id());
+    }
+}
+
+fn get_cell_centre(window: usize) -> Vec2 {
+    let column = ((window - 1) % 2) as f32;
+    let row = ((window - 1) / 2) as f32;
+    Vec2::new(PANEL_X + (column - 0.5) * PANEL_CELL, PANEL_Y + (0.5 - row) * PANEL_CELL)
+}
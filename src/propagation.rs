@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+
+use crate::tiling::{Tiling, UndoRecord};
+use crate::{PenroseEnum, Tile};
+
+// Local deduction: an open edge where only one tile type is allowed, counting overlaps, has to take that
+// tile, so it is placed without a random choice. Placing it can force further edges, so the pass repeats
+// until every open edge has two or more options, or none. An edge with none is a contradiction, the patch
+// can't be completed around it and growth has to back off.
+
+pub struct Propagation<T> {
+    // In the order the tiles were placed
+    pub records: Vec<UndoRecord<T>>,
+    // Open edges nothing can go on once the pass stopped
+    pub contradictions: Vec<(Entity, u8)>
+}
+
+pub fn propagate_forced<P: PenroseEnum, T: Tile<P>>(tiling: &mut Tiling<P, T>, commands: &mut Commands) -> Propagation<T> {
+    let mut propagation = Propagation {
+        records: Vec::new(),
+        contradictions: Vec::new()
+    };
+
+    loop {
+        let mut edges: Vec<(Entity, u8, u64)> = tiling.get_frontier().get_open_edges()
+            .map(|edge| (edge.entity, edge.side, edge.age))
+            .collect();
+        edges.sort_by_key(|(_, _, age)| *age);
+
+        let mut contradictions = Vec::new();
+        let mut forced = None;
+        for (entity, side, _) in edges {
+            let allowed: Vec<P> = match tiling.inspect_side(entity, side) {
+                Ok(checks) => checks.into_iter().filter(|(_, check)| check.is_allowed()).map(|(penrose_type, _)| penrose_type).collect(),
+                Err(error) => {
+                    println!("Can't inspect side {} of {:?}: {}", side, entity, error);
+                    continue;
+                }
+            };
+            match allowed.len() {
+                0 => contradictions.push((entity, side)),
+                1 => {
+                    forced = Some((entity, side, allowed[0]));
+                    break;
+                },
+                _ => ()
+            }
+        }
+
+        let (on_entity, side, penrose_type) = match forced {
+            Some(forced) => forced,
+            None => {
+                propagation.contradictions = contradictions;
+                return propagation;
+            }
+        };
+
+        let entity = commands.spawn().id();
+        match tiling.place_on(on_entity, side, penrose_type, entity) {
+            Ok(record) => {
+                println!("  Forced {} on side {} of {:?}", penrose_type, side, on_entity);
+                propagation.records.push(record);
+            },
+            Err(error) => {
+                commands.entity(entity).despawn();
+                println!("  Forced placement rejected: {}", error);
+                propagation.contradictions = contradictions;
+                return propagation;
+            }
+        }
+    }
+}
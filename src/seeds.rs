@@ -0,0 +1,377 @@
+use bevy::prelude::*;
+use std::collections::{HashSet, VecDeque};
+
+use crate::growth::TypeWeights;
+use crate::lattice::PentagonalPoint;
+use crate::multigrid::{Multigrid, RhombAngles};
+use crate::osds::get_open_edges_at;
+use crate::tiling::{Tiling, TilingError};
+use crate::{get_corner_angles, Rhombus, RhombusPose, Tile, TileShape};
+
+// Named patches to start growth from:
+//  - single: one random tile
+//  - the eight vertex neighbourhoods of the rhomb tiling. The kite and dart tiling has no rhomb counterpart
+//    of the ace, the rhomb tiling has S3 and S4 instead.
+//  - cartwheel: a patch of the tiling from a pentagrid next to the singular one, which agrees with Conway's
+//    cartwheel everywhere outside the central decagon
+//  - decapod-0 to decapod-61: the 62 ways of tiling the decagon of side one with rhombs. Most can't be
+//    decorated without breaking a matching rule somewhere inside, the seed keeps as few breaks as it can.
+
+// The rhomb whose corner the search starts from and the corner angles going anticlockwise round the
+// vertex, in steps of 36 degrees
+const VERTEX_STARS: [(&str, RhombAngles, usize, &[u8]); 8] = [
+    ("sun", RhombAngles::PENROSE_FAT, 0, &[2, 2, 2, 2, 2]),
+    ("star", RhombAngles::PENROSE_FAT, 2, &[2, 2, 2, 2, 2]),
+    ("s3", RhombAngles::PENROSE_FAT, 0, &[2, 2, 2, 4]),
+    ("s4", RhombAngles::PENROSE_FAT, 2, &[1, 1, 2, 2, 2, 2]),
+    ("jack", RhombAngles::PENROSE_FAT, 1, &[1, 2, 1, 3, 3]),
+    ("queen", RhombAngles::PENROSE_FAT, 2, &[1, 1, 2, 1, 1, 2, 2]),
+    ("king", RhombAngles::PENROSE_FAT, 0, &[2, 4, 4]),
+    ("deuce", RhombAngles::PENROSE_FAT, 1, &[3, 3, 4])
+];
+
+const NUM_DECAPODS: usize = 62;
+
+// Patches up to this size get the decorations that break the fewest matching rules, larger ones take them
+// from their neighbours
+const MAX_EXHAUSTIVE_TILES: usize = 12;
+
+// In leg lengths
+const CARTWHEEL_RADIUS: f32 = 4.5;
+
+pub struct SeedLibrary {
+    names: Vec<String>,
+    current: usize
+}
+
+impl SeedLibrary {
+    pub fn new(name: &str) -> Result<Self, String> {
+        let mut names = vec!["single".to_string()];
+        names.extend(VERTEX_STARS.iter().map(|(name, _, _, _)| name.to_string()));
+        names.push("cartwheel".to_string());
+        names.extend((0..NUM_DECAPODS).map(|i| format!("decapod-{}", i)));
+
+        let current = names.iter().position(|n| n == name)
+            .ok_or(format!("unknown seed '{}', expected one of: {}", name, names.join(", ")))?;
+        Ok(SeedLibrary {
+            names,
+            current
+        })
+    }
+
+    pub fn get_current_name(&self) -> &str {
+        &self.names[self.current]
+    }
+
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.names.len();
+    }
+
+    // The tiles of the current seed, each sharing a side with one before it
+    pub fn build(&self, weights: &TypeWeights) -> Result<Vec<Rhombus>, TilingError> {
+        let name = self.get_current_name();
+        if name == "single" {
+            return Ok(vec![Rhombus::new_random(weights)]);
+        }
+        if name == "cartwheel" {
+            let (tiles, mismatches) = decorate(&get_cartwheel_shapes()?);
+            if mismatches > 0 {
+                println!("Cartwheel breaks the matching rules on {} sides", mismatches);
+            }
+            return Ok(tiles);
+        }
+        if let Some(index) = name.strip_prefix("decapod-").and_then(|i| i.parse::<usize>().ok()) {
+            let decapods = get_decapod_shapes();
+            let shapes = decapods.get(index).ok_or(TilingError::InvalidType(name.to_string()))?;
+            let (tiles, mismatches) = decorate(shapes);
+            println!("Decapod {} breaks the matching rules on {} sides", index, mismatches);
+            return Ok(tiles);
+        }
+
+        let (_, penrose_type, corner, angles) = VERTEX_STARS.iter().find(|(star, _, _, _)| *star == name)
+            .ok_or(TilingError::InvalidType(name.to_string()))?;
+        build_vertex_star(*penrose_type, *corner, angles)?.ok_or(TilingError::InvalidType(name.to_string()))
+    }
+}
+
+// Search the ways of completing the vertex at a corner of a lone rhomb for one with the given angles
+fn build_vertex_star(penrose_type: RhombAngles, corner: usize, angles: &[u8]) -> Result<Option<Vec<Rhombus>>, TilingError> {
+    let mut tiling = Tiling::<RhombAngles, Rhombus>::default();
+    let first = Rhombus::new(penrose_type)?;
+    let vertex = first.get_vertices()[corner];
+    tiling.place(first, Entity::new(0))?;
+    find_vertex_star(&mut tiling, &vertex, &mut vec![Entity::new(0)], angles)
+}
+
+fn find_vertex_star(
+    tiling: &mut Tiling<RhombAngles, Rhombus>,
+    vertex: &PentagonalPoint,
+    placed: &mut Vec<Entity>,
+    angles: &[u8]
+) -> Result<Option<Vec<Rhombus>>, TilingError> {
+    if tiling.get_topology().get_filled_angle(vertex) > 2.0 * std::f32::consts::PI - 0.001 {
+        let tiles: Vec<Rhombus> = placed.iter().filter_map(|entity| tiling.get_tile(*entity).cloned()).collect();
+        return Ok(if is_rotation_of(&get_angles_round(&tiles, vertex), angles) { Some(tiles) } else { None });
+    }
+    let (entity, side) = match get_open_edges_at(tiling, vertex).first() {
+        Some(edge) => *edge,
+        None => return Ok(None)
+    };
+
+    for (penrose_type, check) in tiling.inspect_side(entity, side)? {
+        if !check.is_allowed() {
+            continue;
+        }
+        let new_entity = Entity::new(placed.len() as u32);
+        let record = tiling.place_on(entity, side, penrose_type, new_entity)?;
+        placed.push(new_entity);
+        let found = find_vertex_star(tiling, vertex, placed, angles);
+        placed.pop();
+        tiling.undo(&record)?;
+        if let Some(tiles) = found? {
+            return Ok(Some(tiles));
+        }
+    }
+    Ok(None)
+}
+
+// Corner angles of the tiles at the vertex in steps of 36 degrees, going anticlockwise
+fn get_angles_round(tiles: &[Rhombus], vertex: &PentagonalPoint) -> Vec<u8> {
+    let centre = vertex.to_world(1.0);
+    let mut corners: Vec<(f32, u8)> = tiles.iter().filter_map(|tile| {
+        let points = tile.get_lattice_points();
+        let corner = points.iter().position(|p| p == vertex)?;
+        let middle = points.iter().fold(Vec2::ZERO, |sum, p| sum + p.to_world(1.0)) / 4.0 - centre;
+        let angle = get_corner_angles(&tile.get_points())[corner];
+        Some((middle.y.atan2(middle.x), (angle.to_degrees() / 36.0).round() as u8))
+    }).collect();
+    corners.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    corners.into_iter().map(|(_, angle)| angle).collect()
+}
+
+fn is_rotation_of(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && (0..a.len()).any(|start| (0..a.len()).all(|i| a[(start + i) % a.len()] == b[i]))
+}
+
+// Rhombs of a tiling by lattice corners, before the matching rule decorations are chosen
+type RhombShape = (RhombAngles, [PentagonalPoint; 4]);
+
+// Angles between unit vectors k steps of 36 degrees apart
+fn get_type_between(k: i32) -> RhombAngles {
+    match k.rem_euclid(5) {
+        1 | 4 => RhombAngles::PENROSE_SKINNY,
+        _ => RhombAngles::PENROSE_FAT
+    }
+}
+
+// Each tiling of the decagon is a way of turning the path along its lower five sides into the path along its
+// upper five, one rhomb at a time, by swapping two neighbouring steps that are still in order
+fn get_decapod_shapes() -> Vec<Vec<RhombShape>> {
+    let mut decapods = Vec::new();
+    let mut seen = HashSet::new();
+    // Roughly centres the decagon on the origin
+    let start = PentagonalPoint::ORIGIN - PentagonalPoint::unit(0) - PentagonalPoint::unit(1) - PentagonalPoint::unit(2);
+    add_decapods(&mut vec![0, 1, 2, 3, 4], &mut Vec::new(), start, &mut seen, &mut decapods);
+    decapods.sort_by_key(|decapod| decapod.iter().map(|(_, corners)| *corners).collect::<Vec<_>>());
+    decapods
+}
+
+fn add_decapods(
+    path: &mut Vec<i32>,
+    shapes: &mut Vec<RhombShape>,
+    start: PentagonalPoint,
+    seen: &mut HashSet<Vec<[PentagonalPoint; 4]>>,
+    decapods: &mut Vec<Vec<RhombShape>>
+) {
+    let mut corner = start;
+    let mut swapped = false;
+    for i in 0..path.len() - 1 {
+        let (a, b) = (path[i], path[i + 1]);
+        if a < b {
+            swapped = true;
+            let corners = [
+                corner,
+                corner + PentagonalPoint::unit(a),
+                corner + PentagonalPoint::unit(a) + PentagonalPoint::unit(b),
+                corner + PentagonalPoint::unit(b)
+            ];
+            let mut sorted = corners;
+            sorted.sort();
+            path.swap(i, i + 1);
+            shapes.push((get_type_between(b - a), sorted));
+            add_decapods(path, shapes, start, seen, decapods);
+            shapes.pop();
+            path.swap(i, i + 1);
+        }
+        corner = corner + PentagonalPoint::unit(a);
+    }
+
+    if !swapped {
+        let mut decapod = shapes.clone();
+        decapod.sort_by_key(|(_, corners)| *corners);
+        if seen.insert(decapod.iter().map(|(_, corners)| *corners).collect()) {
+            decapods.push(decapod);
+        }
+    }
+}
+
+fn get_cartwheel_shapes() -> Result<Vec<RhombShape>, TilingError> {
+    // Offsets of the five families at multiples of 72 degrees, summing to zero so the tiling is Penrose's,
+    // and small enough that nothing outside the central decagon differs from the singular pentagrid
+    let gamma = [0.0011, 0.0023, -0.0017, -0.0009, -0.0008];
+    // The multigrid's families are 36 degrees apart, the odd ones point the opposite way
+    let grid = Multigrid::new(vec![gamma[0], -gamma[3], gamma[1], -gamma[4], gamma[2]]);
+
+    let mut shapes = Vec::new();
+    for rhomb in grid.dual_tiles(CARTWHEEL_RADIUS.ceil() as i32 + 2) {
+        let mut corners = [PentagonalPoint::ORIGIN; 4];
+        for (corner, point) in corners.iter_mut().zip(rhomb.vertices.iter()) {
+            for (j, count) in point.iter().enumerate() {
+                for _ in 0..count.abs() {
+                    *corner = if *count > 0 { *corner + PentagonalPoint::unit(j as i32) } else { *corner - PentagonalPoint::unit(j as i32) };
+                }
+            }
+        }
+        if corners.iter().all(|corner| corner.to_world(1.0).length() <= CARTWHEEL_RADIUS) {
+            corners.sort();
+            shapes.push((get_type_between(rhomb.families.1 as i32 - rhomb.families.0 as i32), corners));
+        }
+    }
+    // Nearest the centre first, so decoration spreads outwards
+    shapes.sort_by(|(_, a), (_, b)| {
+        let distance_a = a.iter().fold(Vec2::ZERO, |sum, p| sum + p.to_world(1.0)).length();
+        let distance_b = b.iter().fold(Vec2::ZERO, |sum, p| sum + p.to_world(1.0)).length();
+        distance_a.partial_cmp(&distance_b).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    if shapes.is_empty() {
+        return Err(TilingError::InvariantViolated("empty cartwheel".to_string()));
+    }
+    Ok(shapes)
+}
+
+// Both decorations of a rhomb with the given corners, half a turn apart
+fn get_decorations(shape: &RhombShape) -> Vec<Rhombus> {
+    let (penrose_type, corners) = shape;
+    let mut decorations = Vec::new();
+    for orientation in 0..10 {
+        for left in corners.iter() {
+            let mut tile = match Rhombus::new(*penrose_type) {
+                Ok(tile) => tile,
+                Err(_) => return decorations
+            };
+            tile.pose = RhombusPose {
+                orientation,
+                left: *left
+            };
+            let mut points = tile.get_lattice_points();
+            points.sort();
+            if points == *corners {
+                decorations.push(tile);
+            }
+        }
+    }
+    decorations
+}
+
+// The side of a that b shares, if any
+fn get_shared_side(a: &Rhombus, b: &Rhombus) -> Option<u8> {
+    let points_a = a.get_lattice_points();
+    let points_b = b.get_lattice_points();
+    (0..4).find(|side| points_b.contains(&points_a[*side]) && points_b.contains(&points_a[(*side + 1) % 4])).map(|side| side as u8)
+}
+
+fn count_mismatches(tiles: &[Rhombus]) -> usize {
+    let mut mismatches = 0;
+    for (i, a) in tiles.iter().enumerate() {
+        for b in &tiles[i + 1..] {
+            if let Some(side) = get_shared_side(a, b) {
+                if a.get_connection_pose(side, b.get_type()).ok() != Some(b.pose) {
+                    mismatches += 1;
+                }
+            }
+        }
+    }
+    mismatches
+}
+
+// Choose decorations spreading out from the first rhomb, each taking the one its first decorated neighbour
+// asks for. Tried with both decorations of the first rhomb, keeping whichever breaks fewer matching rules.
+// Rhombs that never share a side with a decorated one are dropped. Returns the rhombs in an order where each
+// shares a side with one before it.
+fn decorate(shapes: &[RhombShape]) -> (Vec<Rhombus>, usize) {
+    let candidates: Vec<Vec<Rhombus>> = shapes.iter().map(get_decorations).collect();
+    let mut best: Option<(Vec<Rhombus>, usize)> = None;
+
+    for first in &candidates[0] {
+        let mut chosen: Vec<Option<Rhombus>> = vec![None; shapes.len()];
+        chosen[0] = Some(first.clone());
+        let mut order = vec![first.clone()];
+        let mut queue = VecDeque::new();
+        queue.push_back(0);
+
+        while let Some(a) = queue.pop_front() {
+            let tile_a = match &chosen[a] {
+                Some(tile) => tile.clone(),
+                None => continue
+            };
+            for b in 0..shapes.len() {
+                if chosen[b].is_some() || candidates[b].is_empty() {
+                    continue;
+                }
+                let side = match get_shared_side(&tile_a, &candidates[b][0]) {
+                    Some(side) => side,
+                    None => continue
+                };
+                let pose = tile_a.get_connection_pose(side, shapes[b].0).ok();
+                let tile_b = candidates[b].iter().find(|c| Some(c.pose) == pose).unwrap_or(&candidates[b][0]).clone();
+                order.push(tile_b.clone());
+                chosen[b] = Some(tile_b);
+                queue.push_back(b);
+            }
+        }
+
+        let mismatches = count_mismatches(&order);
+        if best.as_ref().is_none_or(|(_, fewest)| mismatches < *fewest) {
+            best = Some((order, mismatches));
+        }
+    }
+
+    let (order, mismatches) = match best {
+        Some(best) => best,
+        None => return (Vec::new(), 0)
+    };
+    if mismatches == 0 || order.len() > MAX_EXHAUSTIVE_TILES {
+        return (order, mismatches);
+    }
+
+    // Small patches like the decapods can try every combination
+    let others: Vec<Rhombus> = order.iter().map(|tile| {
+        let mut corners = tile.get_lattice_points();
+        corners.sort();
+        get_decorations(&(tile.get_type(), corners)).into_iter().find(|other| other.pose != tile.pose).unwrap_or_else(|| tile.clone())
+    }).collect();
+    let mut best = (order.clone(), mismatches);
+    for flips in 1..(1u32 << order.len()) {
+        let tiles: Vec<Rhombus> = order.iter().zip(others.iter()).enumerate()
+            .map(|(i, (tile, other))| if flips & (1 << i) != 0 { other.clone() } else { tile.clone() })
+            .collect();
+        let mismatches = count_mismatches(&tiles);
+        if mismatches < best.1 {
+            best = (tiles, mismatches);
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_every_decapod() {
+        let decapods = get_decapod_shapes();
+        assert_eq!(decapods.len(), NUM_DECAPODS);
+        // Five directions pair up ten ways, one rhomb for each pair
+        assert!(decapods.iter().all(|decapod| decapod.len() == 10));
+    }
+}
@@ -0,0 +1,365 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy::ecs::system::EntityCommands;
+use bevy::input::{keyboard::KeyCode, Input};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::growth::{RowSearch, SearchStep, TypeWeights};
+use crate::tiling::{Tiling, TilingError};
+use crate::{spawn_tile, PenroseEnum, PointList, Tile, TileShape};
+
+// The Socolar-Taylor monotile, after Socolar and Taylor, "An aperiodic hexagonal tile" (2011): a regular
+// hexagon whose decorations only tile the plane non-periodically, used both ways up. Every tile sits on a
+// cell of the hexagonal lattice, so corners are integer points in the basis of two lattice edges.
+//
+// There are two rules. The black stripes have to carry on unbroken across every side two tiles share
+// (R1, through Tile::sides_agree). The coloured diameters ending at the two corners of a lattice edge that
+// isn't a side of either tile, one at each end, have to be different colours (R2, through
+// Tile::corners_agree). R2 links tiles that don't touch, which is what R1 on its own can't force: R1 alone
+// tiles periodically.
+
+const HEX_RADIUS: f32 = 24.0;
+const ROW_WIDTH: usize = 8;
+const GROWTH_STEPS: usize = 100;
+const STRIPE_WIDTH: f32 = 2.5;
+
+// Corner k of a hexagon is a lattice edge from its centre in direction 60k degrees
+const CORNER_OFFSETS: [(i32, i32); 6] = [(1, 0), (0, 1), (-1, 1), (-1, 0), (0, -1), (1, -1)];
+// Cell across each side, in steps of the cells across sides 0 and 1
+const SIDE_NEIGHBOURS: [(i32, i32); 6] = [(1, 0), (0, 1), (-1, 1), (-1, 0), (0, -1), (1, -1)];
+// Cell whose opposite corner is one lattice edge on from each corner
+const LINKED_CELLS: [(i32, i32); 6] = [(2, -1), (1, 1), (-1, 2), (-2, 1), (-1, -1), (1, -2)];
+
+// Where the stripes of the unreflected tile in its first rotation cross each side, as flags for a quarter,
+// half and three quarters of the way along it: one stripe across the middle and a short one cutting off
+// each of corners 0 and 3
+const STRIPE_CROSSINGS: [u8; 6] = [1, 1, 4, 1, 4, 4];
+// Stripes as the two side crossings they join, by side and fraction along it
+const STRIPES: [((u8, f32), (u8, f32)); 3] = [((1, 0.25), (4, 0.75)), ((5, 0.75), (0, 0.25)), ((2, 0.75), (3, 0.25))];
+// Colour of the diameter at each corner of the unreflected tile in its first rotation, true for red
+const CORNER_COLORS: [bool; 6] = [true, true, false, false, true, false];
+
+// A crossing a quarter of the way along a side is three quarters of the way along it from the other end
+fn reverse_crossings(crossings: u8) -> u8 {
+    ((crossings & 1) << 2) | (crossings & 2) | ((crossings & 4) >> 2)
+}
+
+// The tile turned anticlockwise by a number of 60 degree steps, reflected in the line through corners 0 and
+// 3 first if mirrored. Reflecting also swaps the colours.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SocolarTaylorType {
+    rotation: u8,
+    mirrored: bool
+}
+
+impl SocolarTaylorType {
+    // Corner of the unturned tile that ends up at the corner
+    fn get_base_corner(&self, corner: u8) -> usize {
+        if self.mirrored {
+            (6 + self.rotation as usize - corner as usize) % 6
+        } else {
+            (6 + corner as usize - self.rotation as usize) % 6
+        }
+    }
+
+    fn is_red(&self, corner: u8) -> bool {
+        CORNER_COLORS[self.get_base_corner(corner)] != self.mirrored
+    }
+
+    // Side of the unturned tile that ends up on the side, and whether it now runs the other way
+    fn get_base_side(&self, side: u8) -> (u8, bool) {
+        if self.mirrored {
+            ((12 + self.rotation as usize - side as usize - 1) as u8 % 6, true)
+        } else {
+            ((6 + side as usize - self.rotation as usize) as u8 % 6, false)
+        }
+    }
+
+    fn get_crossings(&self, side: u8) -> u8 {
+        let (base_side, reversed) = self.get_base_side(side);
+        let crossings = STRIPE_CROSSINGS[base_side as usize];
+        if reversed { reverse_crossings(crossings) } else { crossings }
+    }
+
+    // Side of the tile and fraction along it that a crossing of the unturned tile ends up at
+    fn place_crossing(&self, (side, t): (u8, f32)) -> (u8, f32) {
+        if self.mirrored {
+            ((12 + self.rotation - side - 1) % 6, 1.0 - t)
+        } else {
+            ((side + self.rotation) % 6, t)
+        }
+    }
+}
+
+impl PenroseEnum for SocolarTaylorType {
+    fn get_all() -> Vec<Self> {
+        let mut vec = Vec::new();
+        for &mirrored in &[false, true] {
+            for rotation in 0..6 {
+                vec.push(SocolarTaylorType { rotation, mirrored });
+            }
+        }
+        vec
+    }
+}
+
+// R0 to R5 for the tile turned, L0 to L5 for its reflection
+impl fmt::Display for SocolarTaylorType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", if self.mirrored { "L" } else { "R" }, self.rotation)
+    }
+}
+
+#[derive(Clone)]
+pub struct SocolarTaylorTile {
+    st_type: SocolarTaylorType,
+    cell: (i32, i32),
+    used_side_flags: u32
+}
+
+impl SocolarTaylorTile {
+    fn new(st_type: SocolarTaylorType, cell: (i32, i32)) -> Self {
+        SocolarTaylorTile {
+            st_type,
+            cell,
+            used_side_flags: 0
+        }
+    }
+
+    // The centre as a lattice point
+    fn get_centre(&self) -> (i32, i32) {
+        let (q, r) = self.cell;
+        (q - r, q + 2 * r)
+    }
+
+    fn get_offset_cell(&self, offset: (i32, i32)) -> (i32, i32) {
+        (self.cell.0 + offset.0, self.cell.1 + offset.1)
+    }
+}
+
+fn to_world((a, b): (i32, i32)) -> Vec2 {
+    Vec2::new(a as f32 + b as f32 / 2.0, b as f32 * 3.0f32.sqrt() / 2.0) * HEX_RADIUS
+}
+
+impl TileShape<SocolarTaylorType> for SocolarTaylorTile {
+    fn get_points(&self) -> PointList {
+        CORNER_OFFSETS.iter().map(|offset| to_world(*offset)).collect()
+    }
+
+    fn get_type(&self) -> SocolarTaylorType {
+        self.st_type
+    }
+
+    fn insert_shape_component(&self, transform: Transform, entity_commands: &mut EntityCommands) {
+        let fill = if self.st_type.mirrored { Color::rgb(0.95, 0.9, 0.75) } else { Color::rgb(0.85, 0.9, 0.95) };
+        entity_commands.insert_bundle(
+            GeometryBuilder::build_as(
+                &shapes::Polygon {
+                    points: self.get_points(),
+                    closed: true
+                },
+                ShapeColors::outlined(fill, Color::BLACK),
+                DrawMode::Outlined {
+                    fill_options: FillOptions::default(),
+                    outline_options: StrokeOptions::default().with_line_width(1.0),
+                },
+                transform
+            )
+        );
+    }
+
+    // The coloured half diameters under the black stripes, in place of the matching rule dots
+    fn spawn_dots_entities(&self, parent: Entity, commands: &mut Commands) {
+        let points = self.get_points();
+        let mut lines = Vec::new();
+        for corner in 0..6 {
+            let color = if self.st_type.is_red(corner) { Color::RED } else { Color::BLUE };
+            lines.push((Vec2::ZERO, points[corner as usize], color, 1.5, 1.0));
+        }
+        for (start, end) in &STRIPES {
+            let ends: Vec<Vec2> = [*start, *end].iter().map(|crossing| {
+                let (side, t) = self.st_type.place_crossing(*crossing);
+                points[side as usize].lerp(points[(side as usize + 1) % 6], t)
+            }).collect();
+            lines.push((ends[0], ends[1], Color::BLACK, STRIPE_WIDTH, 2.0));
+        }
+
+        let mut children = Vec::new();
+        for (start, end, color, width, z) in lines {
+            let mut entity = commands.spawn();
+            entity.insert_bundle(
+                GeometryBuilder::build_as(
+                    &shapes::Polygon {
+                        points: vec![start, end],
+                        closed: false
+                    },
+                    ShapeColors::new(color),
+                    DrawMode::Stroke(StrokeOptions::default().with_line_width(width)),
+                    Transform::from_xyz(0.0, 0.0, z)
+                )
+            );
+            children.push(entity.id());
+        }
+        commands.entity(parent).push_children(&children);
+    }
+}
+
+impl Tile<SocolarTaylorType> for SocolarTaylorTile {
+    type Vertex = (i32, i32);
+
+    fn new_random(weights: &TypeWeights) -> Self {
+        let candidates: Vec<(SocolarTaylorType, usize)> = SocolarTaylorType::get_all().into_iter().map(|t| (t, 0)).collect();
+        let st_type = weights.choose(&candidates).unwrap_or(SocolarTaylorType { rotation: 0, mirrored: false });
+        SocolarTaylorTile::new(st_type, (0, 0))
+    }
+
+    fn get_num_sides() -> usize {
+        6
+    }
+
+    fn get_side_flags(&self) -> u32 {
+        self.used_side_flags
+    }
+
+    fn get_side_flags_mut(&mut self) -> &mut u32 {
+        &mut self.used_side_flags
+    }
+
+    // The hexagons all sit the same way on the lattice, whichever way their decorations are turned
+    fn get_matching_side(onto_type: SocolarTaylorType, onto_side: u8, other_type: SocolarTaylorType) -> Result<u8, TilingError> {
+        for st_type in &[onto_type, other_type] {
+            if st_type.rotation >= 6 {
+                return Err(TilingError::InvalidType(st_type.to_string()));
+            }
+        }
+        if onto_side >= 6 {
+            return Err(TilingError::InvalidSide(onto_side));
+        }
+        Ok((onto_side + 3) % 6)
+    }
+
+    // The sides run opposite ways, so a stripe a quarter of the way along one is three quarters of the way
+    // along the other
+    fn sides_agree(onto_type: SocolarTaylorType, onto_side: u8, other_type: SocolarTaylorType, other_side: u8) -> bool {
+        onto_type.get_crossings(onto_side) == reverse_crossings(other_type.get_crossings(other_side))
+    }
+
+    fn get_linked_vertices(&self) -> Vec<(u8, (i32, i32))> {
+        let (x, y) = self.get_centre();
+        CORNER_OFFSETS.iter().enumerate().map(|(corner, (dx, dy))| (corner as u8, (x + 2 * dx, y + 2 * dy))).collect()
+    }
+
+    fn corners_agree(onto_type: SocolarTaylorType, onto_corner: u8, other_type: SocolarTaylorType, other_corner: u8) -> bool {
+        onto_type.is_red(onto_corner) != other_type.is_red(other_corner)
+    }
+
+    fn get_connected_tile(&self, side: u8, other_type: SocolarTaylorType) -> Result<Self, TilingError> {
+        SocolarTaylorTile::get_matching_side(self.st_type, side, other_type)?;
+        Ok(SocolarTaylorTile::new(other_type, self.get_offset_cell(SIDE_NEIGHBOURS[side as usize])))
+    }
+
+    fn get_transform(&self) -> Transform {
+        Transform::from_translation(to_world(self.get_centre()).extend(0.0))
+    }
+
+    fn get_vertices(&self) -> Vec<(i32, i32)> {
+        let (x, y) = self.get_centre();
+        CORNER_OFFSETS.iter().map(|(dx, dy)| (x + dx, y + dy)).collect()
+    }
+}
+
+pub struct SocolarTaylorTiler {
+    tiling: Tiling<SocolarTaylorType, SocolarTaylorTile>,
+    weights: TypeWeights,
+    // Rows run across sides 0 and 3 in turn, each starting across side 1 of the end of the one below
+    search: RowSearch<SocolarTaylorType, SocolarTaylorTile>
+}
+
+impl SocolarTaylorTiler {
+    pub fn new(weights: TypeWeights) -> Self {
+        SocolarTaylorTiler {
+            tiling: Tiling::default(),
+            weights,
+            search: RowSearch::new(ROW_WIDTH, (0, 3), 1)
+        }
+    }
+
+    fn grow(&mut self, steps: usize, commands: &mut Commands) {
+        let first_cell = (-(ROW_WIDTH as i32) / 2, -(ROW_WIDTH as i32) / 2);
+        for _ in 0..steps {
+            let step = self.search.step(&mut self.tiling, &self.weights, &SocolarTaylorType::get_all(),
+                |st_type| SocolarTaylorTile::new(st_type, first_cell), || commands.spawn().id());
+            match step {
+                Ok(SearchStep::Placed(entity)) => {
+                    if let Some(tile) = self.tiling.get_tile(entity) {
+                        spawn_tile(tile, entity, commands);
+                    }
+                },
+                Ok(SearchStep::Removed(entity)) => commands.entity(entity).despawn_recursive(),
+                Ok(SearchStep::Stuck) => {
+                    println!("The tiles can't fill a row of {}", ROW_WIDTH);
+                    break;
+                },
+                Err(error) => {
+                    println!("Placement rejected: {}", error);
+                    break;
+                }
+            }
+        }
+        let placed = self.search.get_num_placed();
+        println!("{} Socolar-Taylor tiles, {} full rows, {} backtracks", placed, placed / ROW_WIDTH, self.search.backtracks);
+    }
+
+    fn restart(&mut self, commands: &mut Commands) {
+        for entity in self.search.get_entities() {
+            commands.entity(entity).despawn_recursive();
+        }
+        self.tiling = Tiling::default();
+        self.search = RowSearch::new(ROW_WIDTH, (0, 3), 1);
+    }
+
+    // Both rules between every pair of tiles they apply to, whichever order the tiles were placed in, and
+    // how many of each hand there are
+    fn verify(&self) {
+        let cells: HashMap<(i32, i32), &SocolarTaylorTile> = self.tiling.get_tiles().map(|(_, tile)| (tile.cell, tile)).collect();
+        let mut stripe_breaks = 0;
+        let mut color_clashes = 0;
+        for tile in cells.values() {
+            for k in 0..3 {
+                if let Some(other) = cells.get(&tile.get_offset_cell(SIDE_NEIGHBOURS[k])) {
+                    if !SocolarTaylorTile::sides_agree(tile.st_type, k as u8, other.st_type, k as u8 + 3) {
+                        stripe_breaks += 1;
+                    }
+                }
+                if let Some(other) = cells.get(&tile.get_offset_cell(LINKED_CELLS[k])) {
+                    if !SocolarTaylorTile::corners_agree(tile.st_type, k as u8, other.st_type, k as u8 + 3) {
+                        color_clashes += 1;
+                    }
+                }
+            }
+        }
+        let mirrored = cells.values().filter(|tile| tile.st_type.mirrored).count();
+        println!("{} tiles ({} reflected), {} broken stripes, {} linked corners of the same colour",
+            cells.len(), mirrored, stripe_breaks, color_clashes);
+    }
+}
+
+pub fn setup_socolar_taylor(mut commands: Commands, mut tiler: ResMut<SocolarTaylorTiler>) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    tiler.grow(GROWTH_STEPS, &mut commands);
+}
+
+pub fn socolar_taylor_controls(
+    mut tiler: ResMut<SocolarTaylorTiler>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        tiler.grow(GROWTH_STEPS, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::R) {
+        tiler.restart(&mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::V) {
+        tiler.verify();
+    }
+}
@@ -0,0 +1,368 @@
+use bevy::prelude::*;
+use bevy::input::{keyboard::KeyCode, Input};
+use num_traits::FromPrimitive;
+use std::sync::Arc;
+use strum_macros::Display;
+
+use crate::affine::Affine2;
+use crate::geometry;
+use crate::outline_tile::{OutlineTile, OutlineType};
+use crate::{PenroseEnum, TileShape};
+
+// The chiral "Spectre" monotile of Smith, Myers, Kaplan and Goodman-Strauss: Tile(1,1), a 14 sided
+// polygon with unit edges, generated with the Spectre cluster substitution from "A chiral aperiodic
+// monotile" (2023), following Craig Kaplan's reference implementation.
+
+// Size of the final patch on screen, applied on top of the supertile coordinates
+const SPECTRE_WORLD_SCALE: f32 = 20.0;
+
+// How far the S-curve on each edge bulges out, as a fraction of the edge length
+const CURVE_BULGE: f32 = 0.25;
+const CURVE_SEGMENTS: usize = 8;
+
+lazy_static! {
+    static ref SPECTRE_OUTLINE: Vec<Vec2> = vec![
+        Vec2::new(0.0, 0.0),
+        Vec2::new(1.0, 0.0),
+        Vec2::new(1.5, -0.866_025_4),
+        Vec2::new(2.366_025_4, -0.366_025_4),
+        Vec2::new(2.366_025_4, 0.633_974_6),
+        Vec2::new(3.366_025_4, 0.633_974_6),
+        Vec2::new(3.866_025_4, 1.5),
+        Vec2::new(3.0, 2.0),
+        Vec2::new(2.133_974_6, 1.5),
+        Vec2::new(1.633_974_6, 2.366_025_4),
+        Vec2::new(0.633_974_6, 2.366_025_4),
+        Vec2::new(-0.366_025_4, 2.366_025_4),
+        Vec2::new(-0.866_025_4, 1.5),
+        Vec2::new(0.0, 1.0)
+    ];
+
+    // Drawn in place of the outline when edges are curved, see curved_outline
+    static ref CURVED_SPECTRE_OUTLINE: Vec<Vec2> = curved_outline(&SPECTRE_OUTLINE);
+
+    // Four of the outline's vertices, used to line supertiles up with each other
+    static ref SPECTRE_QUAD: [Vec2; 4] = [SPECTRE_OUTLINE[3], SPECTRE_OUTLINE[5], SPECTRE_OUTLINE[7], SPECTRE_OUTLINE[11]];
+}
+
+// Each edge is replaced by the same S-curve, symmetric under a half turn about the edge's midpoint.
+// Any two edges still fit together, but the mirror image of an S is a Z, so a reflected spectre can't
+// be placed against an unreflected one.
+fn curved_outline(points: &[Vec2]) -> Vec<Vec2> {
+    let mut curved = Vec::new();
+    for i in 0..points.len() {
+        let start = points[i];
+        let end = points[(i + 1) % points.len()];
+        let along = end - start;
+        let normal = along.perp() * CURVE_BULGE;
+        let control_1 = start + along / 3.0 + normal;
+        let control_2 = start + along * 2.0 / 3.0 - normal;
+
+        for step in 0..CURVE_SEGMENTS {
+            let t = step as f32 / CURVE_SEGMENTS as f32;
+            let u = 1.0 - t;
+            curved.push(
+                start * (u * u * u) + control_1 * (3.0 * u * u * t) + control_2 * (3.0 * u * t * t) + end * (t * t * t)
+            );
+        }
+    }
+    curved
+}
+
+// The two spectres making up the Gamma "mystic" cluster get their own labels, everything else is
+// labelled by the supertile it was placed as
+#[derive(Display, Clone, Copy, Primitive, PartialEq)]
+pub enum SpectreType {
+    Gamma1 = 0,
+    Gamma2 = 1,
+    Delta = 2,
+    Theta = 3,
+    Lambda = 4,
+    Xi = 5,
+    Pi = 6,
+    Sigma = 7,
+    Phi = 8,
+    Psi = 9,
+
+    Count = 10
+}
+
+impl PenroseEnum for SpectreType {
+    fn get_all() -> Vec<Self> {
+        let mut vec = Vec::new();
+        for i in 0..(SpectreType::Count as usize) {
+            vec.push(SpectreType::from_usize(i).unwrap());
+        }
+        vec
+    }
+}
+
+impl OutlineType for SpectreType {
+    fn get_outline() -> &'static [Vec2] {
+        &SPECTRE_OUTLINE
+    }
+
+    fn get_color(&self) -> Color {
+        match self {
+            SpectreType::Gamma1 => Color::rgb_u8(255, 255, 255),
+            SpectreType::Gamma2 => Color::rgb_u8(235, 235, 235),
+            SpectreType::Delta => Color::rgb_u8(220, 220, 220),
+            SpectreType::Theta => Color::rgb_u8(255, 191, 191),
+            SpectreType::Lambda => Color::rgb_u8(255, 160, 122),
+            SpectreType::Xi => Color::rgb_u8(255, 242, 0),
+            SpectreType::Pi => Color::rgb_u8(135, 206, 250),
+            SpectreType::Sigma => Color::rgb_u8(245, 245, 220),
+            SpectreType::Phi => Color::rgb_u8(0, 255, 0),
+            SpectreType::Psi => Color::rgb_u8(0, 255, 255),
+            SpectreType::Count => panic!("Invalid type")
+        }
+    }
+}
+
+pub type SpectreTile = OutlineTile<SpectreType>;
+
+// The nine supertile labels from the paper
+#[derive(Clone, Copy, PartialEq)]
+enum Supertile {
+    Gamma = 0,
+    Delta = 1,
+    Theta = 2,
+    Lambda = 3,
+    Xi = 4,
+    Pi = 5,
+    Sigma = 6,
+    Phi = 7,
+    Psi = 8
+}
+
+const NUM_SUPERTILES: usize = 9;
+
+const SUPERTILE_SPECTRE_TYPES: [SpectreType; NUM_SUPERTILES] = [
+    SpectreType::Gamma1, SpectreType::Delta, SpectreType::Theta, SpectreType::Lambda, SpectreType::Xi,
+    SpectreType::Pi, SpectreType::Sigma, SpectreType::Phi, SpectreType::Psi
+];
+
+// Which supertile of the previous level goes in each of the eight slots of a new supertile,
+// the Gamma supertile leaves its third slot empty
+const SUPER_RULES: [[Option<Supertile>; 8]; NUM_SUPERTILES] = {
+    use Supertile::*;
+    [
+        /* Gamma */  [Some(Pi),  Some(Delta), None,      Some(Theta), Some(Sigma), Some(Xi),  Some(Phi),    Some(Gamma)],
+        /* Delta */  [Some(Xi),  Some(Delta), Some(Xi),  Some(Phi),   Some(Sigma), Some(Pi),  Some(Phi),    Some(Gamma)],
+        /* Theta */  [Some(Psi), Some(Delta), Some(Pi),  Some(Phi),   Some(Sigma), Some(Pi),  Some(Phi),    Some(Gamma)],
+        /* Lambda */ [Some(Psi), Some(Delta), Some(Xi),  Some(Phi),   Some(Sigma), Some(Pi),  Some(Phi),    Some(Gamma)],
+        /* Xi */     [Some(Psi), Some(Delta), Some(Pi),  Some(Phi),   Some(Sigma), Some(Psi), Some(Phi),    Some(Gamma)],
+        /* Pi */     [Some(Psi), Some(Delta), Some(Xi),  Some(Phi),   Some(Sigma), Some(Psi), Some(Phi),    Some(Gamma)],
+        /* Sigma */  [Some(Xi),  Some(Delta), Some(Xi),  Some(Phi),   Some(Sigma), Some(Pi),  Some(Lambda), Some(Gamma)],
+        /* Phi */    [Some(Psi), Some(Delta), Some(Psi), Some(Phi),   Some(Sigma), Some(Pi),  Some(Phi),    Some(Gamma)],
+        /* Psi */    [Some(Psi), Some(Delta), Some(Psi), Some(Phi),   Some(Sigma), Some(Psi), Some(Phi),    Some(Gamma)]
+    ]
+};
+
+// (turn in degrees, quad corner of the previous slot, quad corner of the new slot) for slots 1 to 7
+const SLOT_RULES: [(f32, usize, usize); 7] = [
+    (60.0, 3, 1), (0.0, 2, 0), (60.0, 3, 1), (60.0, 3, 1),
+    (0.0, 2, 0), (60.0, 3, 1), (-120.0, 3, 3)
+];
+
+#[derive(Clone)]
+enum SpectreGeometry {
+    Spectre(SpectreType),
+    Cluster(Arc<SpectreCluster>)
+}
+
+struct SpectreCluster {
+    quad: [Vec2; 4],
+    children: Vec<(Affine2, SpectreGeometry)>
+}
+
+impl SpectreCluster {
+    fn collect_spectres(&self, transform: Affine2, spectres: &mut Vec<(SpectreType, Affine2)>) {
+        for (child_transform, geometry) in &self.children {
+            let child_transform = transform * *child_transform;
+            match geometry {
+                SpectreGeometry::Spectre(spectre_type) => spectres.push((*spectre_type, child_transform)),
+                SpectreGeometry::Cluster(cluster) => cluster.collect_spectres(child_transform, spectres)
+            }
+        }
+    }
+}
+
+type SupertileSet = [Arc<SpectreCluster>; NUM_SUPERTILES];
+
+// Level 0: every supertile is a single spectre, except Gamma which is the two spectre "mystic"
+fn make_base_supertiles() -> SupertileSet {
+    let make = |label: usize| {
+        let mut children = vec![(Affine2::IDENTITY, SpectreGeometry::Spectre(SUPERTILE_SPECTRE_TYPES[label]))];
+        if label == Supertile::Gamma as usize {
+            let p = SPECTRE_OUTLINE[8];
+            children.push((
+                Affine2::translation(p.x, p.y) * Affine2::rotation(std::f32::consts::PI / 6.0),
+                SpectreGeometry::Spectre(SpectreType::Gamma2)
+            ));
+        }
+        Arc::new(SpectreCluster {
+            quad: *SPECTRE_QUAD,
+            children
+        })
+    };
+
+    [make(0), make(1), make(2), make(3), make(4), make(5), make(6), make(7), make(8)]
+}
+
+fn build_supertiles(supertiles: &SupertileSet) -> SupertileSet {
+    // All supertiles of a level share the same quad, so any of them gives the slot placements
+    let quad = supertiles[Supertile::Delta as usize].quad;
+
+    let mut slots = vec![Affine2::IDENTITY];
+    let mut total_angle = 0.0;
+    let mut rotation = Affine2::IDENTITY;
+    let mut rotated_quad = quad;
+    for (angle, from, to) in SLOT_RULES.iter() {
+        if *angle != 0.0 {
+            total_angle += angle;
+            rotation = Affine2::rotation(f32::to_radians(total_angle));
+            for i in 0..4 {
+                rotated_quad[i] = rotation.transform_point(quad[i]);
+            }
+        }
+
+        let target = slots.last().unwrap().transform_point(quad[*from]);
+        let offset = target - rotated_quad[*to];
+        slots.push(Affine2::translation(offset.x, offset.y) * rotation);
+    }
+
+    // Every level is mirrored relative to the one below it
+    let mirror = Affine2::new([-1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+    for slot in slots.iter_mut() {
+        *slot = mirror * *slot;
+    }
+
+    let super_quad = [
+        slots[6].transform_point(quad[2]),
+        slots[5].transform_point(quad[1]),
+        slots[3].transform_point(quad[2]),
+        slots[0].transform_point(quad[1])
+    ];
+
+    let make = |label: usize| {
+        let mut children = Vec::new();
+        for (slot, substitution) in SUPER_RULES[label].iter().enumerate() {
+            if let Some(substitution) = substitution {
+                children.push((slots[slot], SpectreGeometry::Cluster(supertiles[*substitution as usize].clone())));
+            }
+        }
+        Arc::new(SpectreCluster {
+            quad: super_quad,
+            children
+        })
+    };
+
+    [make(0), make(1), make(2), make(3), make(4), make(5), make(6), make(7), make(8)]
+}
+
+pub struct SpectreTiler {
+    levels: Vec<SupertileSet>,
+    current_level: usize,
+    curved: bool,
+    spectres: Vec<(SpectreType, Affine2)>,
+    entities: Vec<Entity>
+}
+
+impl SpectreTiler {
+    pub fn new(curved: bool) -> Self {
+        SpectreTiler {
+            levels: vec![make_base_supertiles()],
+            current_level: 0,
+            curved,
+            spectres: Vec::new(),
+            entities: Vec::new()
+        }
+    }
+
+    fn ensure_level(&mut self, level: usize) {
+        while self.levels.len() <= level {
+            let next = build_supertiles(self.levels.last().unwrap());
+            self.levels.push(next);
+        }
+    }
+
+    // Replace whatever is on screen with the Delta supertile of the given level
+    fn show_level(&mut self, level: usize, commands: &mut Commands) {
+        self.ensure_level(level);
+        for entity in self.entities.drain(..) {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        self.current_level = level;
+        self.spectres.clear();
+        let delta = &self.levels[level][Supertile::Delta as usize];
+        delta.collect_spectres(Affine2::scale(SPECTRE_WORLD_SCALE), &mut self.spectres);
+
+        for (spectre_type, placement) in &self.spectres {
+            let (tile, transform) = SpectreTile::new(*spectre_type, placement);
+            // The unmirrored outline is curved and mirrored afterwards, so a reflected tile really carries Z-curves
+            let tile = if self.curved { tile.with_drawn_outline(&CURVED_SPECTRE_OUTLINE) } else { tile };
+            let mut entity = commands.spawn();
+            let id = entity.id();
+            tile.insert_shape_component(transform, &mut entity);
+            entity.insert(tile.clone());
+            tile.spawn_dots_entities(id, commands);
+            self.entities.push(id);
+        }
+
+        println!("Level {}: {} spectres ({} edges)", level, self.spectres.len(), if self.curved { "curved" } else { "straight" });
+        for spectre_type in SpectreType::get_all() {
+            let count = self.spectres.iter().filter(|(t, _)| *t == spectre_type).count();
+            println!("  {}: {}", spectre_type, count);
+        }
+    }
+
+    fn count_overlaps(&self) -> usize {
+        let polygons: Vec<Vec<Vec2>> = self.spectres.iter().map(
+            |(_, placement)| placement.transform_points(&SPECTRE_OUTLINE)
+        ).collect();
+
+        let pairs = geometry::find_overlapping_pairs(&polygons);
+        for (i, j) in &pairs {
+            println!("  Spectres {} and {} overlap", i, j);
+        }
+        pairs.len()
+    }
+
+    // The substitution mirrors every level, but within a patch all spectres must share one handedness,
+    // which is what the curved edges rely on
+    fn is_single_handed(&self) -> bool {
+        match self.spectres.first() {
+            Some((_, first)) => self.spectres.iter().all(|(_, placement)| placement.is_reflection() == first.is_reflection()),
+            None => true
+        }
+    }
+}
+
+pub fn setup_spectre(mut commands: Commands, mut tiler: ResMut<SpectreTiler>) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    tiler.show_level(0, &mut commands);
+}
+
+pub fn spectre_controls(
+    mut tiler: ResMut<SpectreTiler>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        let level = tiler.current_level + 1;
+        tiler.show_level(level, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::U) && tiler.current_level > 0 {
+        let level = tiler.current_level - 1;
+        tiler.show_level(level, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::C) {
+        tiler.curved = !tiler.curved;
+        let level = tiler.current_level;
+        tiler.show_level(level, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::V) {
+        let overlaps = tiler.count_overlaps();
+        println!("{} overlapping spectres out of {}, {}", overlaps, tiler.spectres.len(),
+            if tiler.is_single_handed() { "all of one handedness" } else { "MIXED handedness" });
+    }
+}
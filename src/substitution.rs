@@ -0,0 +1,928 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy::ecs::system::EntityCommands;
+use bevy::input::{keyboard::KeyCode, Input};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::affine::Affine2;
+use crate::geometry;
+use crate::girih::GirihType;
+use crate::growth::TypeWeights;
+use crate::tiling::{Tiling, TilingError};
+use crate::{spawn_tile, PenroseEnum, PointList, Tile, TileShape};
+
+// Substitution tilings described as data. A rule declares its prototiles, the inflation factor, and for
+// each prototile the children that cover it once it has been inflated, each given by the affine map from
+// the child prototile into the inflated parent. Maps may reflect: a reflected tile is typed as the mirror
+// image of its prototile, whose corners are listed in reverse so every tile still goes anticlockwise.
+//
+// Patches are generated to a given depth from one prototile and placed in a Tiling the same way grown
+// Penrose patches are. Tiles can meet along part of a side, with a corner of one landing in the middle of a
+// side of the other. Before placing, every side is split at the corners lying on it, so each contact is a
+// whole side of both tiles and the topology sees the patch as edge-to-edge. Tiles placed one at a time are
+// split the same way by the Tiling as they go in. Tiles are placed by affine maps rather than from a fixed
+// set of poses, so they can take any orientation. Children may also overhang their parent as long as the
+// parent on the other side lists the same child, the copies are dropped.
+
+// Size of the generated patch on screen, whatever the depth
+const PATCH_SIZE: f32 = 600.0;
+const MAX_TILES: usize = 4000;
+// Sides of a tile after splitting, one fewer than the side flags hold so the mask of every side fits too
+const MAX_SIDES: usize = 31;
+// Corners closer than this, in prototile units, are the same vertex
+const VERTEX_PRECISION: f32 = 1000.0;
+
+lazy_static! {
+    static ref RULES: Vec<SubstitutionRule> = vec![tuebingen(), chair(), sphinx(), pinwheel(), girih()];
+}
+
+pub struct Prototile {
+    name: &'static str,
+    // Anticlockwise, in prototile units
+    points: Vec<Vec2>,
+    color: Color
+}
+
+pub struct ChildTile {
+    prototile: usize,
+    // From the child prototile into the parent prototile scaled up by the inflation factor
+    transform: Affine2
+}
+
+pub struct SubstitutionRule {
+    name: &'static str,
+    inflation: f32,
+    prototiles: Vec<Prototile>,
+    // Indexed like the prototiles
+    children: Vec<Vec<ChildTile>>
+}
+
+impl SubstitutionRule {
+    // Prototile index and placement of every tile in the patch substituted depth times from the root
+    // prototile, which is inflated depth times so that tiles come out at prototile size
+    pub fn generate(&self, root: usize, depth: u32) -> Vec<(usize, Affine2)> {
+        let deflate = Affine2::scale(1.0 / self.inflation);
+        let mut tiles = vec![(root, Affine2::scale(self.inflation.powi(depth as i32)))];
+        for _ in 0..depth {
+            tiles = tiles.iter().flat_map(|(index, placement)| {
+                self.children[*index].iter().map(move |child| (child.prototile, *placement * deflate * child.transform))
+            }).collect();
+        }
+        tiles
+    }
+
+    // Tiles in the patch for each prototile after depth substitutions, overhanging children counted once for
+    // each parent listing them
+    fn count_tiles(&self, root: usize, depth: u32) -> usize {
+        let mut counts = vec![0; self.prototiles.len()];
+        counts[root] = 1;
+        for _ in 0..depth {
+            let mut next = vec![0; self.prototiles.len()];
+            for (index, count) in counts.iter().enumerate() {
+                for child in &self.children[index] {
+                    next[child.prototile] += count;
+                }
+            }
+            counts = next;
+        }
+        counts.iter().sum()
+    }
+}
+
+fn polar(length: f32, degrees: f32) -> Vec2 {
+    let angle = f32::to_radians(degrees);
+    Vec2::new(angle.cos(), angle.sin()) * length
+}
+
+// Tübingen triangles: golden triangles with the apex first. A has legs of the golden ratio and a unit
+// base, B unit legs and a base of the golden ratio. Children are given by where their corners land,
+// as points on the sides of the inflated parent.
+fn tuebingen() -> SubstitutionRule {
+    let phi = (1.0 + 5f32.sqrt()) / 2.0;
+    let a = vec![Vec2::ZERO, polar(phi, -18.0), polar(phi, 18.0)];
+    let b = vec![Vec2::ZERO, polar(1.0, -54.0), polar(1.0, 54.0)];
+
+    let child = |prototile: usize, from: &[Vec2], to: [Vec2; 3]| ChildTile {
+        prototile,
+        transform: Affine2::map_triangle([from[0], from[1], from[2]], to)
+    };
+    // Corners of an inflated parent and the point 1/phi of the way from p to q
+    let inflated = |points: &[Vec2]| (points[0] * phi, points[1] * phi, points[2] * phi);
+    let along = |p: Vec2, q: Vec2| p + (q - p) / phi;
+
+    let (apex, b1, b2) = inflated(&a);
+    let split_1 = along(b1, apex);
+    let split_2 = along(apex, b2);
+    let a_children = vec![
+        child(1, &b, [split_1, split_2, apex]),
+        child(0, &a, [b1, b2, split_2]),
+        child(0, &a, [b1, split_1, split_2])
+    ];
+
+    let (apex, b1, b2) = inflated(&b);
+    let split = along(b1, b2);
+    let b_children = vec![
+        child(0, &a, [b1, split, apex]),
+        child(1, &b, [split, apex, b2])
+    ];
+
+    SubstitutionRule {
+        name: "tuebingen",
+        inflation: phi,
+        prototiles: vec![
+            Prototile { name: "A", points: a, color: Color::rgb_u8(250, 200, 80) },
+            Prototile { name: "B", points: b, color: Color::rgb_u8(60, 120, 200) }
+        ],
+        children: vec![a_children, b_children]
+    }
+}
+
+// The L-tromino made of three unit squares
+fn chair() -> SubstitutionRule {
+    let points = vec![
+        Vec2::new(0.0, 0.0), Vec2::new(2.0, 0.0), Vec2::new(2.0, 1.0),
+        Vec2::new(1.0, 1.0), Vec2::new(1.0, 2.0), Vec2::new(0.0, 2.0)
+    ];
+    let quarter_turn = f32::to_radians(90.0);
+
+    SubstitutionRule {
+        name: "chair",
+        inflation: 2.0,
+        prototiles: vec![Prototile { name: "chair", points, color: Color::rgb_u8(220, 120, 90) }],
+        children: vec![vec![
+            ChildTile { prototile: 0, transform: Affine2::IDENTITY },
+            ChildTile { prototile: 0, transform: Affine2::translation(1.0, 1.0) },
+            ChildTile { prototile: 0, transform: Affine2::translation(4.0, 0.0) * Affine2::rotation(quarter_turn) },
+            ChildTile { prototile: 0, transform: Affine2::translation(0.0, 4.0) * Affine2::rotation(-quarter_turn) }
+        ]]
+    }
+}
+
+// Six equilateral triangles in the shape of a sphinx. Three of the four children are reflected.
+fn sphinx() -> SubstitutionRule {
+    let h = 3f32.sqrt() / 2.0;
+    let points = vec![
+        Vec2::new(0.0, 0.0), Vec2::new(3.0, 0.0), Vec2::new(2.5, h), Vec2::new(1.5, h), Vec2::new(1.0, 2.0 * h)
+    ];
+    let turn = |degrees: f32| Affine2::rotation(f32::to_radians(degrees));
+
+    SubstitutionRule {
+        name: "sphinx",
+        inflation: 2.0,
+        prototiles: vec![Prototile { name: "sphinx", points, color: Color::rgb_u8(120, 180, 110) }],
+        children: vec![vec![
+            ChildTile { prototile: 0, transform: Affine2::translation(2.0, 2.0 * h) * Affine2::reflect_x() },
+            ChildTile { prototile: 0, transform: Affine2::translation(3.0, 0.0) * turn(180.0) * Affine2::reflect_x() },
+            ChildTile { prototile: 0, transform: Affine2::translation(6.0, 0.0) * turn(180.0) * Affine2::reflect_x() },
+            ChildTile { prototile: 0, transform: Affine2::translation(2.0, 4.0 * h) * turn(240.0) }
+        ]]
+    }
+}
+
+// Conway's pinwheel: the right triangle with legs 1 and 2, inflated by the square root of 5. The altitude
+// onto the hypotenuse cuts off one child and leaves the triangle at twice its size. That splits into two
+// children at the ends of its long leg and a 1 by 2 rectangle between them, cut along the diagonal that
+// gives the two halves the other handedness. Children turn by atan(1/2) against their parent, an irrational
+// fraction of a full turn, and mixed handedness stops the turns cancelling, so tiles keep turning up in new
+// orientations as the depth grows. Corners land in the middle of sides.
+fn pinwheel() -> SubstitutionRule {
+    // Smallest angle first, then the right angle
+    let points = vec![Vec2::new(0.0, 0.0), Vec2::new(2.0, 0.0), Vec2::new(2.0, 1.0)];
+    let inflation = 5f32.sqrt();
+    let corners = [points[0], points[1], points[2]];
+
+    // Children are worked out with the inflated triangle's hypotenuse from (0, 0) to (5, 0) and its right
+    // angle at (1, 2), then mapped onto the inflated prototile
+    let frame = Affine2::map_triangle([Vec2::new(5.0, 0.0), Vec2::new(1.0, 2.0), Vec2::new(0.0, 0.0)],
+        [corners[0] * inflation, corners[1] * inflation, corners[2] * inflation]);
+    let child = |to: [(f32, f32); 3]| ChildTile {
+        prototile: 0,
+        transform: frame * Affine2::map_triangle(corners, [Vec2::from(to[0]), Vec2::from(to[1]), Vec2::from(to[2])])
+    };
+
+    SubstitutionRule {
+        name: "pinwheel",
+        inflation,
+        prototiles: vec![Prototile { name: "pinwheel", points, color: Color::rgb_u8(150, 110, 200) }],
+        children: vec![vec![
+            child([(1.0, 2.0), (1.0, 0.0), (0.0, 0.0)]),
+            child([(5.0, 0.0), (3.0, 0.0), (3.0, 1.0)]),
+            child([(3.0, 1.0), (1.0, 1.0), (1.0, 2.0)]),
+            child([(1.0, 0.0), (3.0, 0.0), (3.0, 1.0)]),
+            child([(3.0, 1.0), (1.0, 1.0), (1.0, 0.0)])
+        ]]
+    }
+}
+
+// The decagonal subdivision of the girih decagon, hexagon and bowtie, the three tiles on the Darb-i Imam
+// shrine, after Lu and Steinhardt, inflated by the square of the golden ratio. Every side of a parent is
+// the long axis of a small hexagon, half in each of the two parents sharing the side. The other children
+// are given by a walk of unit steps from the parent's first corner to one of their corners and the
+// direction of the side leaving that corner, in tenths of a turn, and repeated round the parent's centre.
+// The hexagon can be filled in two ways, mirror images of each other, and this is one of them.
+fn girih() -> SubstitutionRule {
+    let phi = (1.0 + 5f32.sqrt()) / 2.0;
+    let inflation = phi * phi;
+    let unit = |direction: i32| polar(1.0, 36.0 * direction as f32);
+    let girih_types = [GirihType::Decagon, GirihType::Hexagon, GirihType::Bowtie];
+    let outlines: Vec<Vec<Vec2>> = girih_types.iter().map(|girih_type| {
+        girih_type.get_side_directions().iter().scan(Vec2::ZERO, |corner, direction| {
+            let start = *corner;
+            *corner += unit(*direction);
+            Some(start)
+        }).collect()
+    }).collect();
+
+    let place = |prototile: usize, corner: usize, walk: &[i32], direction: i32| {
+        let points = &outlines[prototile];
+        let start = walk.iter().fold(Vec2::ZERO, |sum, step| sum + unit(*step));
+        ChildTile {
+            prototile,
+            transform: Affine2::match_two(points[corner], points[(corner + 1) % points.len()], start, start + unit(direction))
+        }
+    };
+    // The hexagons along the sides, tip to tip, then the given children and their turns about the centre
+    let subdivide = |parent: usize, turns: usize, children: Vec<ChildTile>| {
+        let corners: Vec<Vec2> = outlines[parent].iter().map(|p| *p * inflation).collect();
+        let centre = corners.iter().fold(Vec2::ZERO, |sum, p| sum + *p) / corners.len() as f32;
+        let hexagon = &outlines[1];
+        let mut all: Vec<ChildTile> = (0..corners.len()).map(|side| ChildTile {
+            prototile: 1,
+            transform: Affine2::match_two(hexagon[0], hexagon[3], corners[side], corners[(side + 1) % corners.len()])
+        }).collect();
+        for child in children {
+            for turn in 0..turns {
+                let angle = std::f32::consts::TAU * turn as f32 / turns as f32;
+                all.push(ChildTile {
+                    prototile: child.prototile,
+                    transform: Affine2::rotate_about(centre, angle) * child.transform
+                });
+            }
+        }
+        all
+    };
+
+    // The decagon's own child sits at its centre and is only listed once
+    let mut decagon_children = subdivide(0, 10, vec![place(1, 0, &[], 1), place(2, 0, &[1], 0)]);
+    decagon_children.push(place(0, 0, &[1, 2, 3], 0));
+    let mut hexagon_children = subdivide(1, 2, vec![place(1, 2, &[0, 1], 9), place(2, 3, &[0, 1, 1, 2, 9], 0)]);
+    hexagon_children.push(place(2, 1, &[0, 1, 2, 3], 9));
+    let bowtie_children = subdivide(2, 2, vec![place(2, 0, &[1], 0)]);
+
+    let names = ["decagon", "hexagon", "bowtie"];
+    SubstitutionRule {
+        name: "girih",
+        inflation,
+        prototiles: outlines.iter().zip(girih_types.iter()).zip(names.iter()).map(|((points, girih_type), name)| {
+            Prototile { name, points: points.clone(), color: girih_type.get_color() }
+        }).collect(),
+        children: vec![decagon_children, hexagon_children, bowtie_children]
+    }
+}
+
+pub fn find_rule(name: &str) -> Option<usize> {
+    RULES.iter().position(|rule| rule.name == name)
+}
+
+// A prototile of one of the rules, or its mirror image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PrototileId {
+    rule: usize,
+    index: usize,
+    mirrored: bool
+}
+
+impl PrototileId {
+    fn get_prototile(&self) -> &'static Prototile {
+        &RULES[self.rule].prototiles[self.index]
+    }
+
+    // Anticlockwise, the mirror image's reflected across the x axis and reversed
+    fn get_points(&self) -> PointList {
+        let points = &self.get_prototile().points;
+        if self.mirrored {
+            points.iter().rev().map(|p| Vec2::new(p.x, -p.y)).collect()
+        } else {
+            points.clone()
+        }
+    }
+
+    fn get_closest_side(&self, length: f32) -> usize {
+        let points = self.get_points();
+        (0..points.len()).map(|side| (side, (SubstitutionTile::get_side_length(&points, side) - length).abs()))
+            .fold((0, f32::INFINITY), |best, candidate| if candidate.1 < best.1 { candidate } else { best }).0
+    }
+
+    fn get_color(&self) -> Color {
+        let color = self.get_prototile().color;
+        if self.mirrored {
+            Color::rgb(0.6 * color.r() + 0.4, 0.6 * color.g() + 0.4, 0.6 * color.b() + 0.4)
+        } else {
+            color
+        }
+    }
+}
+
+impl fmt::Display for PrototileId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.get_prototile().name, if self.mirrored { "'" } else { "" })
+    }
+}
+
+impl PrototileId {
+    // The prototiles of one rule and their mirror images
+    fn get_all_for(rule: usize) -> Vec<Self> {
+        let mut all = Vec::new();
+        for index in 0..RULES[rule].prototiles.len() {
+            for mirrored in [false, true].iter() {
+                all.push(PrototileId { rule, index, mirrored: *mirrored });
+            }
+        }
+        all
+    }
+}
+
+impl PenroseEnum for PrototileId {
+    fn get_all() -> Vec<Self> {
+        (0..RULES.len()).flat_map(PrototileId::get_all_for).collect()
+    }
+}
+
+pub type RoundedPoint = (i64, i64);
+
+fn round_point(p: Vec2) -> RoundedPoint {
+    ((p.x * VERTEX_PRECISION).round() as i64, (p.y * VERTEX_PRECISION).round() as i64)
+}
+
+// Vertices of a generated patch. Corners computed along different paths through the substitution differ
+// in the last bits, so a corner takes the key of any vertex already found next to it rather than rounding
+// on its own, which could send the two either way across a rounding boundary.
+#[derive(Default)]
+struct VertexSnapper {
+    keys: HashMap<RoundedPoint, RoundedPoint>
+}
+
+impl VertexSnapper {
+    fn snap(&mut self, p: Vec2) -> RoundedPoint {
+        let (x, y) = round_point(p);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                if let Some(key) = self.keys.get(&(x + dx, y + dy)) {
+                    return *key;
+                }
+            }
+        }
+        self.keys.insert((x, y), (x, y));
+        (x, y)
+    }
+}
+
+// Where a corner of one tile lies inside a side of another, that side is split there. Fails if a tile
+// would end up with more sides than MAX_SIDES.
+fn split_sides(tiles: &mut [SubstitutionTile]) -> Result<(), TilingError> {
+    let mut corners: HashMap<RoundedPoint, Vec2> = HashMap::new();
+    for tile in tiles.iter() {
+        for (vertex, point) in tile.vertices.iter().zip(tile.points.iter()) {
+            corners.insert(*vertex, *point);
+        }
+    }
+    // Unit cells, sides are never much longer than a prototile
+    let mut cells: HashMap<(i32, i32), Vec<(RoundedPoint, Vec2)>> = HashMap::new();
+    for (vertex, point) in &corners {
+        cells.entry((point.x.floor() as i32, point.y.floor() as i32)).or_default().push((*vertex, *point));
+    }
+
+    for tile in tiles.iter_mut() {
+        let (min, max) = tile.points.iter().fold((Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), p| (min.min(*p), max.max(*p)));
+        // A cell further each way for corners that round across a cell boundary
+        let mut nearby = Vec::new();
+        for x in (min.x.floor() as i32 - 1)..=(max.x.floor() as i32 + 1) {
+            for y in (min.y.floor() as i32 - 1)..=(max.y.floor() as i32 + 1) {
+                nearby.extend(cells.get(&(x, y)).into_iter().flatten().copied());
+            }
+        }
+        if let Some(split) = tile.split_at(&nearby)? {
+            *tile = split;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct SubstitutionTile {
+    prototile: PrototileId,
+    // Corners in prototile units, in the order of the prototile's points
+    points: Vec<Vec2>,
+    vertices: Vec<RoundedPoint>,
+    leg_len: f32,
+    used_side_flags: u32
+}
+
+impl SubstitutionTile {
+    fn new(prototile: PrototileId, points: Vec<Vec2>, vertices: Vec<RoundedPoint>, leg_len: f32) -> Self {
+        SubstitutionTile {
+            prototile,
+            points,
+            vertices,
+            leg_len,
+            used_side_flags: 0
+        }
+    }
+
+    // A tile of the rule placed by a child transform. A reflecting placement gives the mirror image, whose
+    // points are the prototile's reflected back before the placement reflects them again.
+    fn from_placement(rule: usize, index: usize, placement: &Affine2, snapper: &mut VertexSnapper, leg_len: f32) -> Self {
+        let mirrored = placement.is_reflection();
+        let prototile = PrototileId { rule, index, mirrored };
+        let placement = if mirrored { *placement * Affine2::reflect_x() } else { *placement };
+        let points = placement.transform_points(&prototile.get_points());
+        let vertices = points.iter().map(|p| snapper.snap(*p)).collect();
+        SubstitutionTile::new(prototile, points, vertices, leg_len)
+    }
+
+    fn get_centre(&self) -> Vec2 {
+        self.points.iter().fold(Vec2::ZERO, |sum, p| sum + *p) / self.points.len() as f32
+    }
+
+    fn get_side_length(points: &[Vec2], side: usize) -> f32 {
+        points[side].distance(points[(side + 1) % points.len()])
+    }
+
+    // The tile with each side split at the corners lying part way along it, or None if none do. Corners
+    // come with their positions in prototile units.
+    fn split_at(&self, corners: &[(RoundedPoint, Vec2)]) -> Result<Option<SubstitutionTile>, TilingError> {
+        let mut points = Vec::new();
+        let mut vertices = Vec::new();
+        for side in 0..self.points.len() {
+            let next = (side + 1) % self.points.len();
+            let (start, end) = (self.points[side], self.points[next]);
+            let along = end - start;
+
+            let mut on_side = Vec::new();
+            for (vertex, point) in corners {
+                if self.vertices.contains(vertex) {
+                    continue;
+                }
+                let t = (*point - start).dot(along) / along.length_squared();
+                let distance = (*point - start).perp_dot(along).abs() / along.length();
+                if t > 0.0 && t < 1.0 && distance * VERTEX_PRECISION < 2.0 {
+                    on_side.push((t, *vertex, *point));
+                }
+            }
+            on_side.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            points.push(start);
+            vertices.push(self.vertices[side]);
+            for (_, vertex, point) in on_side {
+                points.push(point);
+                vertices.push(vertex);
+            }
+        }
+        if points.len() == self.points.len() {
+            return Ok(None);
+        }
+        if points.len() > MAX_SIDES {
+            return Err(TilingError::TooManySides(points.len()));
+        }
+        Ok(Some(SubstitutionTile::new(self.prototile, points, vertices, self.leg_len)))
+    }
+}
+
+impl TileShape<PrototileId> for SubstitutionTile {
+    fn get_points(&self) -> PointList {
+        let centre = self.get_centre();
+        self.points.iter().map(|p| (*p - centre) * self.leg_len).collect()
+    }
+
+    fn get_type(&self) -> PrototileId {
+        self.prototile
+    }
+
+    fn insert_shape_component(&self, transform: Transform, entity_commands: &mut EntityCommands) {
+        entity_commands.insert_bundle(
+            GeometryBuilder::build_as(
+                &shapes::Polygon {
+                    points: self.get_points(),
+                    closed: true
+                },
+                ShapeColors::outlined(self.prototile.get_color(), Color::BLACK),
+                DrawMode::Outlined {
+                    fill_options: FillOptions::default(),
+                    outline_options: StrokeOptions::default().with_line_width(1.0),
+                },
+                transform
+            )
+        );
+    }
+
+    fn spawn_dots_entities(&self, _parent: Entity, _commands: &mut Commands) {
+    }
+}
+
+impl Tile<PrototileId> for SubstitutionTile {
+    type Vertex = RoundedPoint;
+
+    fn new_random(weights: &TypeWeights) -> Self {
+        let candidates: Vec<(PrototileId, usize)> = PrototileId::get_all().into_iter().map(|t| (t, 0)).collect();
+        let prototile = weights.choose(&candidates).unwrap_or(candidates[0].0);
+        let points = prototile.get_points();
+        let vertices = points.iter().map(|p| round_point(*p)).collect();
+        SubstitutionTile::new(prototile, points, vertices, PATCH_SIZE / 20.0)
+    }
+
+    fn get_num_sides() -> usize {
+        MAX_SIDES
+    }
+
+    fn get_side_count(&self) -> usize {
+        self.points.len()
+    }
+
+    fn get_side_flags(&self) -> u32 {
+        self.used_side_flags
+    }
+
+    fn get_side_flags_mut(&mut self) -> &mut u32 {
+        &mut self.used_side_flags
+    }
+
+    // There are no matching rules, any side of the same length will do. The first side of the other type
+    // closest in length is taken.
+    fn get_matching_side(onto_type: PrototileId, onto_side: u8, other_type: PrototileId) -> Result<u8, TilingError> {
+        let onto = onto_type.get_points();
+        if onto_side as usize >= onto.len() {
+            return Err(TilingError::InvalidSide(onto_side));
+        }
+        Ok(other_type.get_closest_side(SubstitutionTile::get_side_length(&onto, onto_side as usize)) as u8)
+    }
+
+    // Tiles of different rules never meet
+    fn get_neighbour_types(&self) -> Vec<PrototileId> {
+        PrototileId::get_all_for(self.prototile.rule)
+    }
+
+    // Sides of different lengths are lined up at this tile's end of the side, tiles are never rescaled. The
+    // partial contact that leaves is split by the Tiling when the tile is placed.
+    fn get_connected_tile(&self, side: u8, other_type: PrototileId) -> Result<Self, TilingError> {
+        if side as usize >= self.points.len() {
+            return Err(TilingError::InvalidSide(side));
+        }
+        // The side may have been split, so its length is taken from this tile rather than its type
+        let other_side = other_type.get_closest_side(SubstitutionTile::get_side_length(&self.points, side as usize));
+        let other = other_type.get_points();
+        let start = self.points[(side as usize + 1) % self.points.len()];
+        let end = self.points[side as usize];
+        let other_start = other[other_side];
+        let other_end = other[(other_side + 1) % other.len()];
+        let direction = (end - start).normalize() * other_start.distance(other_end);
+
+        let placement = Affine2::match_two(other_start, other_end, start, start + direction);
+        let points = placement.transform_points(&other);
+        // Corners landing on this tile's take its keys, which may have been snapped
+        let vertices = points.iter().map(|p| match self.points.iter().position(|q| q.distance(*p) * VERTEX_PRECISION < 2.0) {
+            Some(corner) => self.vertices[corner],
+            None => round_point(*p)
+        }).collect();
+        Ok(SubstitutionTile::new(other_type, points, vertices, self.leg_len))
+    }
+
+    fn get_transform(&self) -> Transform {
+        Transform::from_translation((self.get_centre() * self.leg_len).extend(0.0))
+    }
+
+    fn get_vertices(&self) -> Vec<RoundedPoint> {
+        self.vertices.clone()
+    }
+
+    fn has_partial_contacts() -> bool {
+        true
+    }
+
+    fn split_at_corners(&self, corners: &[RoundedPoint]) -> Result<Option<Self>, TilingError> {
+        let corners: Vec<(RoundedPoint, Vec2)> = corners.iter()
+            .map(|vertex| (*vertex, Vec2::new(vertex.0 as f32, vertex.1 as f32) / VERTEX_PRECISION))
+            .collect();
+        self.split_at(&corners)
+    }
+}
+
+// Distinct directions of the first side of each tile, to a tenth of a degree. Mirror images count apart.
+fn count_orientations<'a>(tiles: impl Iterator<Item = &'a SubstitutionTile>) -> usize {
+    let orientations: HashSet<(PrototileId, i32)> = tiles.map(|tile| {
+        let side = tile.points[1] - tile.points[0];
+        (tile.prototile, ((side.y.atan2(side.x).to_degrees() * 10.0).round() as i32).rem_euclid(3600))
+    }).collect();
+    orientations.len()
+}
+
+// The patch substituted depth times from the first prototile, centred on the origin at PATCH_SIZE
+fn generate_patch(rule_index: usize, depth: u32) -> Vec<SubstitutionTile> {
+    let rule = &RULES[rule_index];
+    let prototile = &rule.prototiles[0];
+    let (min, max) = prototile.points.iter().fold((Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+        |(min, max), p| (min.min(*p), max.max(*p)));
+    let patch_scale = rule.inflation.powi(depth as i32);
+    let leg_len = PATCH_SIZE / ((max - min).max_element() * patch_scale);
+    // Centre the patch on the origin
+    let centre = (min + max) / 2.0 * patch_scale;
+
+    let mut snapper = VertexSnapper::default();
+    let mut tiles: Vec<SubstitutionTile> = rule.generate(0, depth).iter().map(|(index, placement)| {
+        let placement = Affine2::translation(-centre.x, -centre.y) * *placement;
+        SubstitutionTile::from_placement(rule_index, *index, &placement, &mut snapper, leg_len)
+    }).collect();
+    // Children overhanging their parents come once from each side
+    let mut seen = HashSet::new();
+    tiles.retain(|tile| {
+        let mut vertices = tile.vertices.clone();
+        vertices.sort();
+        seen.insert(vertices)
+    });
+    tiles
+}
+
+// The patch with every side split at the corners lying on it up front, which is quicker than leaving the
+// Tiling to find them one tile at a time
+fn build_patch(rule_index: usize, depth: u32) -> Result<Vec<SubstitutionTile>, TilingError> {
+    let mut tiles = generate_patch(rule_index, depth);
+    split_sides(&mut tiles)?;
+    Ok(tiles)
+}
+
+pub struct SubstitutionTiler {
+    rule: usize,
+    depth: u32,
+    tiling: Tiling<PrototileId, SubstitutionTile>,
+    entities: Vec<Entity>
+}
+
+impl SubstitutionTiler {
+    pub fn new(rule_name: &str) -> Result<Self, String> {
+        let rule = find_rule(rule_name).ok_or_else(|| {
+            let names: Vec<&str> = RULES.iter().map(|rule| rule.name).collect();
+            format!("Unknown substitution '{}', expected one of: {}", rule_name, names.join(", "))
+        })?;
+        Ok(SubstitutionTiler {
+            rule,
+            depth: 3,
+            tiling: Tiling::default(),
+            entities: Vec::new()
+        })
+    }
+
+    fn get_rule(&self) -> &'static SubstitutionRule {
+        &RULES[self.rule]
+    }
+
+    fn regenerate(&mut self, commands: &mut Commands) {
+        for entity in self.entities.drain(..) {
+            commands.entity(entity).despawn_recursive();
+        }
+        self.tiling = Tiling::default();
+
+        let rule = self.get_rule();
+        let tiles = match build_patch(self.rule, self.depth) {
+            Ok(tiles) => tiles,
+            Err(error) => {
+                println!("{} substitution depth {} can't be placed: {}", rule.name, self.depth, error);
+                return;
+            }
+        };
+
+        let mut rejected = 0;
+        for tile in tiles {
+            let entity = commands.spawn().id();
+            match self.tiling.place(tile.clone(), entity) {
+                Ok(_) => {
+                    spawn_tile(&tile, entity, commands);
+                    self.entities.push(entity);
+                },
+                Err(error) => {
+                    commands.entity(entity).despawn();
+                    if rejected == 0 {
+                        println!("  Tiling rejected a {}: {}", tile.get_type(), error);
+                    }
+                    rejected += 1;
+                }
+            }
+        }
+
+        println!("{} substitution depth {}, {} tiles", rule.name, self.depth, self.entities.len());
+        for prototile in PrototileId::get_all_for(self.rule) {
+            let count = self.tiling.get_tiles().filter(|(_, tile)| tile.get_type() == prototile).count();
+            if count > 0 {
+                println!("  {}: {}", prototile, count);
+            }
+        }
+        println!("  {} orientations", count_orientations(self.tiling.get_tiles().map(|(_, tile)| tile)));
+        if rejected > 0 {
+            println!("  {} tiles rejected by the tiling", rejected);
+        }
+    }
+
+    fn set_depth(&mut self, depth: u32, commands: &mut Commands) {
+        if self.get_rule().count_tiles(0, depth) > MAX_TILES {
+            println!("Depth {} would take more than {} tiles", depth, MAX_TILES);
+            return;
+        }
+        self.depth = depth;
+        self.regenerate(commands);
+    }
+
+    fn next_rule(&mut self, commands: &mut Commands) {
+        self.rule = (self.rule + 1) % RULES.len();
+        self.regenerate(commands);
+    }
+
+    // Overlaps and the boundary of the patch, which should be a single loop
+    fn verify(&self) {
+        let polygons: Vec<Vec<Vec2>> = self.tiling.get_tiles().map(|(_, tile)| tile.points.clone()).collect();
+        let overlaps = geometry::find_overlapping_pairs(&polygons).len();
+        let open_edges = self.tiling.get_frontier().get_open_edges().count();
+        println!("{} tiles, {} overlapping pairs, {} sides on the boundary", polygons.len(), overlaps, open_edges);
+    }
+}
+
+pub fn setup_substitution(mut commands: Commands, mut tiler: ResMut<SubstitutionTiler>) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    tiler.regenerate(&mut commands);
+}
+
+pub fn substitution_controls(
+    mut tiler: ResMut<SubstitutionTiler>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        let depth = tiler.depth + 1;
+        tiler.set_depth(depth, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::U) && tiler.depth > 0 {
+        let depth = tiler.depth - 1;
+        tiler.set_depth(depth, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::N) {
+        tiler.next_rule(&mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::V) {
+        tiler.verify();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deep enough for children of children to meet across their parents' sides
+    fn get_test_depth(rule: &SubstitutionRule) -> u32 {
+        if rule.name == "girih" { 2 } else { 3 }
+    }
+
+    // Entry i, j is the number of children of prototile j in prototile i
+    fn get_substitution_matrix(rule: &SubstitutionRule) -> Vec<Vec<usize>> {
+        rule.children.iter().map(|children| {
+            let mut row = vec![0; rule.prototiles.len()];
+            for child in children {
+                row[child.prototile] += 1;
+            }
+            row
+        }).collect()
+    }
+
+    fn multiply(a: &[Vec<usize>], b: &[Vec<usize>]) -> Vec<Vec<usize>> {
+        (0..a.len()).map(|i| (0..b[0].len()).map(|j| (0..b.len()).map(|k| a[i][k] * b[k][j]).sum()).collect()).collect()
+    }
+
+    fn power(matrix: &[Vec<usize>], exponent: u32) -> Vec<Vec<usize>> {
+        let mut result: Vec<Vec<usize>> = (0..matrix.len()).map(|i| (0..matrix.len()).map(|j| (i == j) as usize).collect()).collect();
+        for _ in 0..exponent {
+            result = multiply(&result, matrix);
+        }
+        result
+    }
+
+    // Row root of the matrix to the power depth counts each prototile in the patch grown from root
+    #[test]
+    fn counts_match_the_substitution_matrix() {
+        for rule in RULES.iter() {
+            let matrix = get_substitution_matrix(rule);
+            for depth in 0..=get_test_depth(rule) {
+                let expected = power(&matrix, depth);
+                for (root, expected_counts) in expected.iter().enumerate() {
+                    let mut counts = vec![0; rule.prototiles.len()];
+                    for (index, _) in rule.generate(root, depth) {
+                        counts[index] += 1;
+                    }
+                    assert_eq!(&counts, expected_counts, "{} from {} at depth {}", rule.name, rule.prototiles[root].name, depth);
+                    assert_eq!(rule.count_tiles(root, depth), expected_counts.iter().sum::<usize>());
+                }
+            }
+        }
+        // A chair or a sphinx is four of itself, a pinwheel triangle five
+        for (name, children) in [("chair", 4), ("sphinx", 4), ("pinwheel", 5)].iter() {
+            let rule = &RULES[find_rule(name).unwrap()];
+            assert_eq!(get_substitution_matrix(rule).iter().map(|row| row.iter().sum::<usize>()).max(), Some(*children));
+            assert_eq!(rule.count_tiles(0, 3), children * children * children);
+        }
+    }
+
+    // Children cover the inflated parent exactly, except girih children, which overhang where the
+    // neighbouring parent lists them too. Reflected children come out clockwise.
+    #[test]
+    fn children_cover_their_parent() {
+        for rule in RULES.iter().filter(|rule| rule.name != "girih") {
+            for (index, prototile) in rule.prototiles.iter().enumerate() {
+                let area: f32 = rule.generate(index, 1).iter()
+                    .map(|(child, placement)| geometry::signed_area(&placement.transform_points(&rule.prototiles[*child].points)).abs())
+                    .sum();
+                let parent_area = geometry::signed_area(&prototile.points) * rule.inflation * rule.inflation;
+                assert!((area - parent_area).abs() < 0.001 * parent_area, "{} {}: {} vs {}", rule.name, prototile.name, area, parent_area);
+            }
+        }
+    }
+
+    #[test]
+    fn patches_place_without_overlaps() {
+        for (rule_index, rule) in RULES.iter().enumerate() {
+            let tiles = build_patch(rule_index, get_test_depth(rule)).unwrap();
+            assert!(tiles.iter().all(|tile| tile.points.len() <= MAX_SIDES));
+            let polygons: Vec<Vec<Vec2>> = tiles.iter().map(|tile| tile.points.clone()).collect();
+            assert!(geometry::find_overlapping_pairs(&polygons).is_empty(), "{} has overlapping tiles", rule.name);
+
+            let mut tiling: Tiling<PrototileId, SubstitutionTile> = Tiling::default();
+            for (i, tile) in tiles.into_iter().enumerate() {
+                if let Err(error) = tiling.place(tile, Entity::new(i as u32)) {
+                    panic!("{} rejected tile {}: {}", rule.name, i, error);
+                }
+            }
+        }
+    }
+
+    // Placed one at a time without splitting first, the Tiling splits the same sides at the same corners
+    #[test]
+    fn placing_splits_partial_contacts() {
+        for (rule_index, rule) in RULES.iter().enumerate() {
+            let split_sides: usize = build_patch(rule_index, 2).unwrap().iter().map(|tile| tile.get_side_count()).sum();
+            let mut tiling: Tiling<PrototileId, SubstitutionTile> = Tiling::default();
+            for (i, tile) in generate_patch(rule_index, 2).into_iter().enumerate() {
+                if let Err(error) = tiling.place(tile, Entity::new(i as u32)) {
+                    panic!("{} rejected tile {}: {}", rule.name, i, error);
+                }
+            }
+            let placed_sides: usize = tiling.get_tiles().map(|(_, tile)| tile.get_side_count()).sum();
+            assert_eq!(placed_sides, split_sides, "{}", rule.name);
+        }
+    }
+
+    // A chair put under another meets half of its bottom side, which is split there. Removing the new
+    // chair leaves the split and putting it back splits nothing more.
+    #[test]
+    fn partial_contacts_survive_removal() {
+        let rule = find_rule("chair").unwrap();
+        let mut snapper = VertexSnapper::default();
+        let above = SubstitutionTile::from_placement(rule, 0, &Affine2::translation(0.0, 2.0), &mut snapper, 1.0);
+        let below = SubstitutionTile::from_placement(rule, 0, &Affine2::IDENTITY, &mut snapper, 1.0);
+        let (above_entity, below_entity) = (Entity::new(0), Entity::new(1));
+
+        let mut tiling: Tiling<PrototileId, SubstitutionTile> = Tiling::default();
+        tiling.place(above, above_entity).unwrap();
+        let record = tiling.place(below, below_entity).unwrap();
+        assert_eq!(tiling.get_tile(above_entity).unwrap().get_side_count(), 7);
+        assert_eq!(tiling.get_tile(below_entity).unwrap().get_side_count(), 6);
+        assert_eq!(tiling.get_topology().get_neighbours(below_entity).len(), 1);
+
+        let redo = tiling.undo(&record).unwrap();
+        assert!(tiling.get_tile(below_entity).is_none());
+        assert_eq!(tiling.get_tile(above_entity).unwrap().get_side_count(), 7);
+        tiling.undo(&redo).unwrap();
+        assert_eq!(tiling.get_tile(above_entity).unwrap().get_side_count(), 7);
+        assert_eq!(tiling.get_topology().get_neighbours(above_entity).len(), 1);
+    }
+
+    #[test]
+    fn open_edges_only_take_the_rules_own_prototiles() {
+        let rule = find_rule("sphinx").unwrap();
+        let mut tiling: Tiling<PrototileId, SubstitutionTile> = Tiling::default();
+        for (i, tile) in build_patch(rule, 1).unwrap().into_iter().enumerate() {
+            tiling.place(tile, Entity::new(i as u32)).unwrap();
+        }
+        let edges: Vec<_> = tiling.get_frontier().get_open_edges().collect();
+        assert!(!edges.is_empty());
+        for edge in edges {
+            assert!(edge.legal_types.iter().all(|prototile| prototile.rule == rule));
+            let checks = tiling.inspect_side(edge.entity, edge.side).unwrap();
+            assert_eq!(checks.len(), PrototileId::get_all_for(rule).len());
+        }
+    }
+
+    #[test]
+    fn splitting_past_the_side_flags_is_rejected() {
+        let rule = find_rule("chair").unwrap();
+        let mut snapper = VertexSnapper::default();
+        let tile = SubstitutionTile::from_placement(rule, 0, &Affine2::IDENTITY, &mut snapper, 1.0);
+        let corners: Vec<(RoundedPoint, Vec2)> = (1..40).map(|i| {
+            let point = Vec2::new(2.0 * i as f32 / 40.0, 0.0);
+            (round_point(point), point)
+        }).collect();
+        assert!(matches!(tile.split_at(&corners), Err(TilingError::TooManySides(_))));
+    }
+}
@@ -0,0 +1,433 @@
+use bevy::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::dcel::MeshError;
+use crate::frontier::Frontier;
+use crate::{PenroseEnum, PenroseTiler, Tile, TileTopology, TileWithTransform};
+
+// The tiling model: every placed tile with its side state, the topology and the frontier, kept in step.
+// Placing and removing a tile are single operations that update all three together, check the invariants
+// for every tile they touched and roll back if any fail, so the model is never left half updated. Each
+// returns an undo record holding the inverse operation. A rollback that fails itself comes back as
+// RollbackFailed rather than leaving the model half updated without saying so.
+//
+// The topology only sees tiles meeting along whole sides. For sets whose tiles can meet along part of a
+// side, placing a tile first splits every side, of the new tile or of a placed tile next to it, that a
+// corner of the other lands part way along. The placed tiles are swapped for their split versions by
+// Replace operations, which stay in place when the new tile is removed again: the extra corners don't
+// change any shape.
+
+// Id of a tile placed only to look ahead and removed again straight away. Searches that nest count down
+// from it, one id per level.
+pub const SCRATCH_ENTITY: u32 = u32::MAX;
+
+#[derive(Debug)]
+pub enum TilingError {
+    InvalidType(String),
+    InvalidSide(u8),
+    SideInUse(Entity, u8),
+    Topology(MeshError),
+    UnknownTile(Entity),
+    DuplicateTile(Entity),
+    InvariantViolated(String),
+    // Splitting a tile's sides at partial contacts would leave it more sides than the side flags hold
+    TooManySides(usize),
+    // The error that stopped an operation, then the one that stopped it being undone. The model is left
+    // part way through the operation and can't be trusted after this.
+    RollbackFailed(Box<TilingError>, Box<TilingError>)
+}
+
+impl fmt::Display for TilingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TilingError::InvalidType(penrose_type) => write!(f, "{} is not a tile type", penrose_type),
+            TilingError::InvalidSide(side) => write!(f, "{} is not a side", side),
+            TilingError::SideInUse(entity, side) => write!(f, "side {} of {:?} already has a tile on it", side, entity),
+            TilingError::Topology(error) => write!(f, "topology rejected the tile: {:?}", error),
+            TilingError::UnknownTile(entity) => write!(f, "{:?} is not in the tiling", entity),
+            TilingError::DuplicateTile(entity) => write!(f, "{:?} is already in the tiling", entity),
+            TilingError::InvariantViolated(message) => write!(f, "invariant violated: {}", message),
+            TilingError::TooManySides(sides) => write!(f, "splitting would leave a tile with {} sides", sides),
+            TilingError::RollbackFailed(error, rollback_error) => write!(f, "{}, then rolling back failed: {}", error, rollback_error)
+        }
+    }
+}
+
+// Why a tile can or can't go on an open edge
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlacementCheck {
+    Allowed,
+    // The side of the new tile already has a tile on the same side of it
+    EdgeAlreadyShared { side: u8, entity: Entity },
+    // The side of the new tile would meet a side of this tile that the matching rules don't allow
+    MismatchedSide { side: u8, entity: Entity, existing_side: u8 },
+    // The corner of the new tile is linked to a corner of this tile that the matching rules don't allow
+    MismatchedCorner { corner: u8, entity: Entity, other_corner: u8 },
+    Overlaps(Entity),
+    // The corners at this corner of the new tile would add up to more than a full turn
+    IllegalVertex { corner: u8 }
+}
+
+impl PlacementCheck {
+    pub fn is_allowed(&self) -> bool {
+        *self == PlacementCheck::Allowed
+    }
+
+    pub fn get_color(&self) -> Color {
+        match self {
+            PlacementCheck::Allowed => Color::GREEN,
+            PlacementCheck::EdgeAlreadyShared { .. } => Color::YELLOW,
+            PlacementCheck::MismatchedSide { .. } => Color::ORANGE,
+            PlacementCheck::MismatchedCorner { .. } => Color::PINK,
+            PlacementCheck::Overlaps(_) => Color::RED,
+            PlacementCheck::IllegalVertex { .. } => Color::PURPLE
+        }
+    }
+}
+
+impl fmt::Display for PlacementCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlacementCheck::Allowed => write!(f, "allowed"),
+            PlacementCheck::EdgeAlreadyShared { side, entity } => write!(f, "side {} is already shared with {:?}", side, entity),
+            PlacementCheck::MismatchedSide { side, entity, existing_side } =>
+                write!(f, "side {} can't meet side {} of {:?}", side, existing_side, entity),
+            PlacementCheck::MismatchedCorner { corner, entity, other_corner } =>
+                write!(f, "corner {} can't be linked to corner {} of {:?}", corner, other_corner, entity),
+            PlacementCheck::Overlaps(entity) => write!(f, "overlaps {:?}", entity),
+            PlacementCheck::IllegalVertex { corner } => write!(f, "corner {} would take its vertex past 360 degrees", corner)
+        }
+    }
+}
+
+// Circle round a placed tile, tiles whose circles don't meet can't overlap
+fn get_bounding_circle<P: PenroseEnum, T: Tile<P>>(tile: &T, transform: &Transform) -> (Vec2, f32) {
+    let points: Vec<Vec2> = tile.get_points().iter().map(|p| (*transform * p.extend(0.0)).truncate()).collect();
+    let centre = points.iter().fold(Vec2::ZERO, |sum, p| sum + *p) / points.len() as f32;
+    let radius = points.iter().map(|p| p.distance(centre)).fold(0.0, f32::max);
+    (centre, radius)
+}
+
+#[derive(Clone)]
+pub enum Operation<T> {
+    Place(T),
+    Remove,
+    // Swap a placed tile for another covering the same ground, with its sides split at different corners
+    Replace(T)
+}
+
+pub struct UndoRecord<T> {
+    pub entity: Entity,
+    pub inverse: Operation<T>,
+    // Other tiles whose sides were changed by the operation
+    pub touched: Vec<Entity>
+}
+
+#[derive(Default)]
+pub struct UndoStack<T> {
+    pub records: Vec<UndoRecord<T>>
+}
+
+pub struct Tiling<P: PenroseEnum, T: Tile<P>> {
+    tiles: HashMap<Entity, T>,
+    topology: TileTopology<P, T::Vertex>,
+    frontier: Frontier<P, T>
+}
+
+impl<P: PenroseEnum, T: Tile<P>> Default for Tiling<P, T> {
+    fn default() -> Self {
+        Tiling {
+            tiles: HashMap::new(),
+            topology: TileTopology::default(),
+            frontier: Frontier::default()
+        }
+    }
+}
+
+impl<P: PenroseEnum, T: Tile<P>> Tiling<P, T> {
+    pub fn get_tile(&self, entity: Entity) -> Option<&T> {
+        self.tiles.get(&entity)
+    }
+
+    pub fn get_tiles(&self) -> impl Iterator<Item = (&Entity, &T)> {
+        self.tiles.iter()
+    }
+
+    pub fn get_topology(&self) -> &TileTopology<P, T::Vertex> {
+        &self.topology
+    }
+
+    pub fn get_frontier(&self) -> &Frontier<P, T> {
+        &self.frontier
+    }
+
+    pub fn get_frontier_mut(&mut self) -> &mut Frontier<P, T> {
+        &mut self.frontier
+    }
+
+    pub fn place(&mut self, tile: T, entity: Entity) -> Result<UndoRecord<T>, TilingError> {
+        let (tile, splits) = self.split_partial_contacts(tile)?;
+        let mut split_records = Vec::new();
+        for (neighbour, split) in splits {
+            match self.apply(neighbour, Operation::Replace(split)) {
+                Ok(record) => split_records.push(record),
+                Err(error) => return Err(self.undo_splits(split_records, error))
+            }
+        }
+        match self.apply(entity, Operation::Place(tile)) {
+            Ok(record) => Ok(record),
+            Err(error) => Err(self.undo_splits(split_records, error))
+        }
+    }
+
+    // The new tile and the placed tiles next to it with every side that a corner of the other lands part
+    // way along split there, leaving out placed tiles that don't change
+    fn split_partial_contacts(&self, mut tile: T) -> Result<(T, Vec<(Entity, T)>), TilingError> {
+        let mut splits = Vec::new();
+        if !T::has_partial_contacts() {
+            return Ok((tile, splits));
+        }
+        let (centre, radius) = get_bounding_circle(&tile, &tile.get_transform());
+        for (entity, existing) in self.tiles.iter() {
+            let (existing_centre, existing_radius) = get_bounding_circle(existing, &existing.get_transform());
+            if existing_centre.distance(centre) > radius + existing_radius + 0.001 {
+                continue;
+            }
+            if let Some(split) = existing.split_at_corners(&tile.get_vertices())? {
+                splits.push((*entity, split));
+            }
+            if let Some(split) = tile.split_at_corners(&existing.get_vertices())? {
+                tile = split;
+            }
+        }
+        Ok((tile, splits))
+    }
+
+    // Puts back the tiles split for a placement that then failed, passing on the error that stopped it
+    fn undo_splits(&mut self, records: Vec<UndoRecord<T>>, error: TilingError) -> TilingError {
+        for record in records.into_iter().rev() {
+            if let Err(rollback_error) = self.apply(record.entity, record.inverse) {
+                return TilingError::RollbackFailed(Box::new(error), Box::new(rollback_error));
+            }
+        }
+        error
+    }
+
+    // Place a tile of the given type with its matching side on a free side of a placed tile
+    pub fn place_on(&mut self, on_entity: Entity, side: u8, penrose_type: P, entity: Entity) -> Result<UndoRecord<T>, TilingError> {
+        let on_tile = self.tiles.get(&on_entity).ok_or(TilingError::UnknownTile(on_entity))?;
+        if side as usize >= on_tile.get_side_count() {
+            return Err(TilingError::InvalidSide(side));
+        }
+        if on_tile.get_side_used(side) {
+            return Err(TilingError::SideInUse(on_entity, side));
+        }
+
+        let tile = on_tile.get_connected_tile(side, penrose_type)?;
+        self.place(tile, entity)
+    }
+
+    // The verdict for every tile type on a free side of a placed tile, including overlaps with any tile in
+    // the tiling
+    pub fn inspect_side(&self, on_entity: Entity, side: u8) -> Result<Vec<(P, PlacementCheck)>, TilingError> {
+        let on_tile = self.tiles.get(&on_entity).ok_or(TilingError::UnknownTile(on_entity))?;
+        if side as usize >= on_tile.get_side_count() {
+            return Err(TilingError::InvalidSide(side));
+        }
+        if on_tile.get_side_used(side) {
+            return Err(TilingError::SideInUse(on_entity, side));
+        }
+
+        let mut checks = Vec::new();
+        for penrose_type in on_tile.get_neighbour_types() {
+            let mut check = PenroseTiler::check_placement_on_side(on_tile, side, penrose_type, &self.topology)?;
+            if check.is_allowed() {
+                let new_tile = on_tile.get_connected_tile(side, penrose_type)?;
+                let new_transform = new_tile.get_transform();
+                let (new_centre, new_radius) = get_bounding_circle(&new_tile, &new_transform);
+                let new_tile = TileWithTransform::new(&new_tile, &new_transform);
+                for (entity, existing) in &self.tiles {
+                    let transform = existing.get_transform();
+                    let (centre, radius) = get_bounding_circle(existing, &transform);
+                    if centre.distance(new_centre) >= new_radius + radius {
+                        continue;
+                    }
+                    if PenroseTiler::tiles_collide(&new_tile, &TileWithTransform::new(existing, &transform)) {
+                        check = PlacementCheck::Overlaps(*entity);
+                        break;
+                    }
+                }
+            }
+            checks.push((penrose_type, check));
+        }
+        Ok(checks)
+    }
+
+    // Returns the record that redoes the operation
+    pub fn undo(&mut self, record: &UndoRecord<T>) -> Result<UndoRecord<T>, TilingError> {
+        match &record.inverse {
+            // Tiles placed since may meet the one coming back part way along a side
+            Operation::Place(tile) => self.place(tile.clone(), record.entity),
+            operation => self.apply(record.entity, operation.clone())
+        }
+    }
+
+    pub fn apply(&mut self, entity: Entity, operation: Operation<T>) -> Result<UndoRecord<T>, TilingError> {
+        let record = self.apply_unchecked(entity, operation)?;
+        if let Err(error) = self.check_invariants(entity, &record.touched) {
+            if let Err(rollback_error) = self.apply_unchecked(entity, record.inverse) {
+                return Err(TilingError::RollbackFailed(Box::new(error), Box::new(rollback_error)));
+            }
+            return Err(error);
+        }
+        Ok(record)
+    }
+
+    // Everything that can fail is done before the tiles and frontier change. The topology changes first,
+    // since the frontier is worked out from it, and is put back if anything after that fails.
+    fn apply_unchecked(&mut self, entity: Entity, operation: Operation<T>) -> Result<UndoRecord<T>, TilingError> {
+        match operation {
+            Operation::Place(mut tile) => {
+                if self.tiles.contains_key(&entity) {
+                    return Err(TilingError::DuplicateTile(entity));
+                }
+
+                for side in 0..tile.get_side_count() {
+                    tile.set_side_free(side as u8);
+                }
+                self.topology.add_tile(&tile, entity)?;
+
+                let neighbours = self.topology.get_neighbours(entity);
+                for (side, _) in neighbours.iter() {
+                    tile.set_side_used(*side);
+                }
+                let missing = neighbours.iter().find(|(_, data)| !self.tiles.contains_key(&data.entity));
+                let change = match missing {
+                    Some((_, data)) => Err(TilingError::UnknownTile(data.entity)),
+                    None => self.frontier.add_tile(&tile, entity, &self.topology)
+                };
+                let change = match change {
+                    Ok(change) => change,
+                    Err(error) => {
+                        if let Err(rollback_error) = self.topology.remove_entity(entity) {
+                            return Err(TilingError::RollbackFailed(Box::new(error), Box::new(rollback_error)));
+                        }
+                        return Err(error);
+                    }
+                };
+
+                let mut touched = Vec::new();
+                for (_, data) in neighbours {
+                    if let Some(neighbour) = self.tiles.get_mut(&data.entity) {
+                        neighbour.set_side_used(data.side);
+                    }
+                    touched.push(data.entity);
+                }
+                self.frontier.commit(change);
+                self.tiles.insert(entity, tile);
+
+                Ok(UndoRecord {
+                    entity,
+                    inverse: Operation::Remove,
+                    touched
+                })
+            },
+            Operation::Remove => {
+                let tile = self.tiles.get(&entity).ok_or(TilingError::UnknownTile(entity))?.clone();
+                let mut uncovered = Vec::new();
+                for (_, data) in self.topology.get_neighbours(entity) {
+                    let mut neighbour = self.tiles.get(&data.entity).ok_or(TilingError::UnknownTile(data.entity))?.clone();
+                    neighbour.set_side_free(data.side);
+                    uncovered.push((neighbour, data.entity, data.side));
+                }
+
+                self.topology.remove_entity(entity)?;
+                let change = match self.frontier.remove_tile(&tile, entity, &uncovered, &self.topology) {
+                    Ok(change) => change,
+                    Err(error) => {
+                        if let Err(rollback_error) = self.topology.add_tile(&tile, entity) {
+                            return Err(TilingError::RollbackFailed(Box::new(error), Box::new(rollback_error)));
+                        }
+                        return Err(error);
+                    }
+                };
+
+                let mut touched = Vec::new();
+                for (_, neighbour_entity, side) in uncovered {
+                    if let Some(neighbour) = self.tiles.get_mut(&neighbour_entity) {
+                        neighbour.set_side_free(side);
+                    }
+                    touched.push(neighbour_entity);
+                }
+                self.frontier.commit(change);
+                self.tiles.remove(&entity);
+
+                Ok(UndoRecord {
+                    entity,
+                    inverse: Operation::Place(tile),
+                    touched
+                })
+            },
+            Operation::Replace(tile) => {
+                let old_tile = self.tiles.get(&entity).ok_or(TilingError::UnknownTile(entity))?.clone();
+                let removed = self.apply_unchecked(entity, Operation::Remove)?;
+                let placed = match self.apply_unchecked(entity, Operation::Place(tile)) {
+                    Ok(placed) => placed,
+                    Err(error) => {
+                        if let Err(rollback_error) = self.apply_unchecked(entity, removed.inverse) {
+                            return Err(TilingError::RollbackFailed(Box::new(error), Box::new(rollback_error)));
+                        }
+                        return Err(error);
+                    }
+                };
+
+                let mut touched = removed.touched;
+                for neighbour in placed.touched {
+                    if !touched.contains(&neighbour) {
+                        touched.push(neighbour);
+                    }
+                }
+                Ok(UndoRecord {
+                    entity,
+                    inverse: Operation::Replace(old_tile),
+                    touched
+                })
+            }
+        }
+    }
+
+    // A side is used exactly when the topology has a tile on the other side of it, and open in the
+    // frontier exactly when it's free
+    fn check_invariants(&self, entity: Entity, touched: &[Entity]) -> Result<(), TilingError> {
+        if self.tiles.len() != self.topology.get_num_tiles() {
+            return Err(TilingError::InvariantViolated(format!("{} tiles but {} faces in the topology",
+                self.tiles.len(), self.topology.get_num_tiles())));
+        }
+
+        for entity in touched.iter().chain(std::iter::once(&entity)) {
+            let tile = match self.tiles.get(entity) {
+                Some(tile) => tile,
+                None => {
+                    if (0..T::get_num_sides()).any(|side| self.frontier.is_open(*entity, side as u8)) {
+                        return Err(TilingError::InvariantViolated(format!("removed {:?} still has open edges", entity)));
+                    }
+                    continue;
+                }
+            };
+            let neighbours = self.topology.get_neighbours(*entity);
+            for side in 0..tile.get_side_count() {
+                let side = side as u8;
+                let has_neighbour = neighbours.iter().any(|(s, _)| *s == side);
+                if tile.get_side_used(side) != has_neighbour {
+                    return Err(TilingError::InvariantViolated(format!("side {} of {:?} is marked {} but has {} neighbour",
+                        side, entity, if has_neighbour { "free" } else { "used" }, if has_neighbour { "a" } else { "no" })));
+                }
+                if self.frontier.is_open(*entity, side) == has_neighbour {
+                    return Err(TilingError::InvariantViolated(format!("frontier disagrees about side {} of {:?}", side, entity)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
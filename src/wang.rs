@@ -0,0 +1,358 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use bevy::ecs::system::EntityCommands;
+use bevy::input::{keyboard::KeyCode, Input};
+use std::fmt;
+
+use crate::growth::{RowSearch, SearchStep, TypeWeights};
+use crate::tiling::{Tiling, TilingError};
+use crate::{spawn_tile, PenroseEnum, PointList, Tile, TileShape};
+
+// Wang tiles: unit squares with a colour on each side that can't be rotated or reflected, and meet only
+// where the colours on the two sides agree. Every tile sits on a cell of the square lattice, so corners are
+// integer points and the tiling machinery works on them unchanged; the colours are enforced through
+// Tile::sides_agree.
+//
+// The patch is filled by a row search, each cell from the frontier edge of the cell filled before it. The
+// aperiodic sets need a lot of backtracking, Jeandel-Rao far more than Kari's.
+
+const WANG_SIZE: f32 = 30.0;
+const ROW_WIDTH: i32 = 10;
+const GROWTH_STEPS: usize = 100;
+
+// Colours on the south, east, north and west sides, the order the sides run anticlockwise from the
+// bottom left corner
+type WangColors = [&'static str; 4];
+
+struct WangSet {
+    name: &'static str,
+    tiles: &'static [WangColors]
+}
+
+// Jeandel and Rao, "An aperiodic set of 11 Wang tiles" (2021), the smallest aperiodic set there can be
+const JEANDEL_RAO: [WangColors; 11] = [
+    ["1", "2", "4", "2"], ["0", "2", "2", "2"], ["1", "1", "1", "3"], ["2", "1", "2", "3"],
+    ["3", "3", "1", "3"], ["1", "0", "1", "3"], ["1", "0", "0", "0"], ["2", "3", "1", "0"],
+    ["2", "0", "2", "1"], ["4", "1", "2", "1"], ["2", "3", "3", "1"]
+];
+
+// Kari, "A small aperiodic set of Wang tiles" (1996). Each row of tiles multiplies the real number its
+// north sides spell out in balanced representation by 2 or by 2/3, the east and west sides carrying the
+// remainder along the row. The carries of the two kinds of row are kept apart by priming the doubling
+// ones.
+const KARI: [WangColors; 14] = [
+    ["1", "-1'", "0", "0'"], ["1", "0'", "1", "-1'"], ["2", "-1'", "1", "-1'"], ["2", "0'", "1", "0'"],
+    ["0", "1/3", "1", "-1/3"], ["0", "2/3", "1", "0"], ["1", "-1/3", "1", "0"], ["1", "0", "1", "1/3"],
+    ["1", "1/3", "1", "2/3"], ["1", "0", "2", "-1/3"], ["1", "1/3", "2", "0"], ["1", "2/3", "2", "1/3"],
+    ["2", "-1/3", "2", "1/3"], ["2", "0", "2", "2/3"]
+];
+
+// Culik, "An aperiodic set of 13 Wang tiles" (1996), Kari's construction with rows multiplying by 3 and by
+// 1/2, the halving carries primed. These are the tiles the construction gives for numbers in [1/2, 3],
+// tripling below 1 and halving from 1 on. That interval is the smallest where no tile has the same colour
+// north and south with its own colour on both ends, which would tile the plane periodically on its own,
+// and it takes 15 tiles, two more than Culik's printed set.
+const KARI_CULIK: [WangColors; 15] = [
+    ["1", "-1", "0", "0"], ["1", "-2", "0", "-1"], ["1", "0", "1", "-2"], ["2", "-1", "1", "-2"],
+    ["2", "-2", "0", "0"], ["2", "0", "1", "-1"], ["3", "-1", "1", "-1"], ["3", "-2", "1", "-2"],
+    ["3", "0", "1", "0"],
+    ["0", "1/2", "1", "0'"], ["1", "0'", "1", "1/2"], ["1", "0'", "2", "0'"], ["1", "1/2", "2", "1/2"],
+    ["1", "1/2", "3", "0'"], ["2", "0'", "3", "1/2"]
+];
+
+const SETS: [WangSet; 3] = [
+    WangSet { name: "jeandel-rao", tiles: &JEANDEL_RAO },
+    WangSet { name: "kari", tiles: &KARI },
+    WangSet { name: "kari-culik", tiles: &KARI_CULIK }
+];
+
+impl WangSet {
+    // Every colour the set uses, in the order they first appear
+    fn get_palette(&self) -> Vec<&'static str> {
+        let mut palette = Vec::new();
+        for colors in self.tiles {
+            for color in colors {
+                if !palette.contains(color) {
+                    palette.push(*color);
+                }
+            }
+        }
+        palette
+    }
+}
+
+// A tile of one of the sets, named by the set's initials and its index in the set, e.g. JR3
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WangType {
+    set: usize,
+    index: usize
+}
+
+impl WangType {
+    fn get_colors(&self) -> &'static WangColors {
+        &SETS[self.set].tiles[self.index]
+    }
+
+    fn get_set_types(set: usize) -> Vec<Self> {
+        (0..SETS[set].tiles.len()).map(|index| WangType { set, index }).collect()
+    }
+}
+
+impl PenroseEnum for WangType {
+    fn get_all() -> Vec<Self> {
+        (0..SETS.len()).flat_map(WangType::get_set_types).collect()
+    }
+}
+
+impl fmt::Display for WangType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let initials: String = SETS[self.set].name.split('-').filter_map(|word| word.chars().next()).collect();
+        write!(f, "{}{}", initials.to_uppercase(), self.index)
+    }
+}
+
+#[derive(Clone)]
+pub struct WangTile {
+    wang_type: WangType,
+    // Lattice cell the tile covers, by its bottom left corner
+    cell: (i32, i32),
+    used_side_flags: u32
+}
+
+impl WangTile {
+    fn new(wang_type: WangType, cell: (i32, i32)) -> Self {
+        WangTile {
+            wang_type,
+            cell,
+            used_side_flags: 0
+        }
+    }
+
+    // The cell across each side
+    fn get_neighbour_cell(&self, side: u8) -> (i32, i32) {
+        let (x, y) = self.cell;
+        match side {
+            0 => (x, y - 1),
+            1 => (x + 1, y),
+            2 => (x, y + 1),
+            _ => (x - 1, y)
+        }
+    }
+}
+
+impl TileShape<WangType> for WangTile {
+    fn get_points(&self) -> PointList {
+        let half = WANG_SIZE / 2.0;
+        vec![Vec2::new(-half, -half), Vec2::new(half, -half), Vec2::new(half, half), Vec2::new(-half, half)]
+    }
+
+    fn get_type(&self) -> WangType {
+        self.wang_type
+    }
+
+    fn insert_shape_component(&self, transform: Transform, entity_commands: &mut EntityCommands) {
+        entity_commands.insert_bundle(
+            GeometryBuilder::build_as(
+                &shapes::Polygon {
+                    points: self.get_points(),
+                    closed: true
+                },
+                ShapeColors::outlined(Color::WHITE, Color::BLACK),
+                DrawMode::Outlined {
+                    fill_options: FillOptions::default(),
+                    outline_options: StrokeOptions::default().with_line_width(1.0),
+                },
+                transform
+            )
+        );
+    }
+
+    // A triangle from the centre to each side in the side's colour, in place of the matching rule dots
+    fn spawn_dots_entities(&self, parent: Entity, commands: &mut Commands) {
+        let palette = SETS[self.wang_type.set].get_palette();
+        let points = self.get_points();
+        let mut children = Vec::new();
+        for (side, color) in self.wang_type.get_colors().iter().enumerate() {
+            let position = palette.iter().position(|c| c == color).unwrap_or(0);
+            let hue = 360.0 * position as f32 / palette.len() as f32;
+            let mut entity = commands.spawn();
+            entity.insert_bundle(
+                GeometryBuilder::build_as(
+                    &shapes::Polygon {
+                        points: vec![Vec2::ZERO, points[side], points[(side + 1) % points.len()]],
+                        closed: true
+                    },
+                    ShapeColors::outlined(Color::hsl(hue, 0.6, 0.6), Color::BLACK),
+                    DrawMode::Outlined {
+                        fill_options: FillOptions::default(),
+                        outline_options: StrokeOptions::default().with_line_width(0.5),
+                    },
+                    Transform::from_xyz(0.0, 0.0, 1.0)
+                )
+            );
+            children.push(entity.id());
+        }
+        commands.entity(parent).push_children(&children);
+    }
+}
+
+impl Tile<WangType> for WangTile {
+    type Vertex = (i32, i32);
+
+    fn new_random(weights: &TypeWeights) -> Self {
+        let candidates: Vec<(WangType, usize)> = WangType::get_set_types(0).into_iter().map(|t| (t, 0)).collect();
+        WangTile::new(weights.choose(&candidates).unwrap_or(WangType { set: 0, index: 0 }), (0, 0))
+    }
+
+    fn get_num_sides() -> usize {
+        4
+    }
+
+    fn get_side_flags(&self) -> u32 {
+        self.used_side_flags
+    }
+
+    fn get_side_flags_mut(&mut self) -> &mut u32 {
+        &mut self.used_side_flags
+    }
+
+    // Tiles never turn, so a side always meets the opposite side of its neighbour
+    fn get_matching_side(onto_type: WangType, onto_side: u8, other_type: WangType) -> Result<u8, TilingError> {
+        for wang_type in &[onto_type, other_type] {
+            if wang_type.set >= SETS.len() || wang_type.index >= SETS[wang_type.set].tiles.len() {
+                return Err(TilingError::InvalidType(wang_type.to_string()));
+            }
+        }
+        if onto_side >= 4 {
+            return Err(TilingError::InvalidSide(onto_side));
+        }
+        Ok((onto_side + 2) % 4)
+    }
+
+    // Tiles of different sets never meet, even where their colours have the same name
+    fn sides_agree(onto_type: WangType, onto_side: u8, other_type: WangType, other_side: u8) -> bool {
+        onto_type.set == other_type.set && onto_type.get_colors()[onto_side as usize] == other_type.get_colors()[other_side as usize]
+    }
+
+    fn get_connected_tile(&self, side: u8, other_type: WangType) -> Result<Self, TilingError> {
+        WangTile::get_matching_side(self.wang_type, side, other_type)?;
+        Ok(WangTile::new(other_type, self.get_neighbour_cell(side)))
+    }
+
+    fn get_transform(&self) -> Transform {
+        Transform::from_xyz(self.cell.0 as f32 * WANG_SIZE, self.cell.1 as f32 * WANG_SIZE, 0.0)
+    }
+
+    fn get_vertices(&self) -> Vec<(i32, i32)> {
+        let (x, y) = self.cell;
+        vec![(x, y), (x + 1, y), (x + 1, y + 1), (x, y + 1)]
+    }
+}
+
+pub struct WangTiler {
+    set: usize,
+    tiling: Tiling<WangType, WangTile>,
+    weights: TypeWeights,
+    // Rows run east and west in turn, each starting north of the end of the one below
+    search: RowSearch<WangType, WangTile>
+}
+
+impl WangTiler {
+    pub fn new(set_name: &str, weights: TypeWeights) -> Result<Self, String> {
+        let set = SETS.iter().position(|set| set.name == set_name).ok_or_else(|| {
+            let names: Vec<&str> = SETS.iter().map(|set| set.name).collect();
+            format!("Unknown Wang tile set '{}', expected one of: {}", set_name, names.join(", "))
+        })?;
+        Ok(WangTiler {
+            set,
+            tiling: Tiling::default(),
+            weights,
+            search: RowSearch::new(ROW_WIDTH as usize, (1, 3), 2)
+        })
+    }
+
+    fn get_cell(index: usize) -> (i32, i32) {
+        (index as i32 % ROW_WIDTH - ROW_WIDTH / 2, index as i32 / ROW_WIDTH - ROW_WIDTH / 2)
+    }
+
+    fn grow(&mut self, steps: usize, commands: &mut Commands) {
+        for _ in 0..steps {
+            let first_types = WangType::get_set_types(self.set);
+            let first_cell = WangTiler::get_cell(0);
+            let step = self.search.step(&mut self.tiling, &self.weights, &first_types,
+                |wang_type| WangTile::new(wang_type, first_cell), || commands.spawn().id());
+            match step {
+                Ok(SearchStep::Placed(entity)) => {
+                    if let Some(tile) = self.tiling.get_tile(entity) {
+                        spawn_tile(tile, entity, commands);
+                    }
+                },
+                Ok(SearchStep::Removed(entity)) => commands.entity(entity).despawn_recursive(),
+                Ok(SearchStep::Stuck) => {
+                    println!("The {} tiles can't fill a row of {}", SETS[self.set].name, ROW_WIDTH);
+                    break;
+                },
+                Err(error) => {
+                    println!("Placement rejected: {}", error);
+                    break;
+                }
+            }
+        }
+        let placed = self.search.get_num_placed();
+        println!("{} {} tiles, {} full rows, {} backtracks", placed, SETS[self.set].name,
+            placed / ROW_WIDTH as usize, self.search.backtracks);
+    }
+
+    fn restart(&mut self, commands: &mut Commands) {
+        for entity in self.search.get_entities() {
+            commands.entity(entity).despawn_recursive();
+        }
+        self.tiling = Tiling::default();
+        self.search = RowSearch::new(ROW_WIDTH as usize, (1, 3), 2);
+        println!("{} Wang tiles", SETS[self.set].name);
+    }
+
+    fn next_set(&mut self, commands: &mut Commands) {
+        self.set = (self.set + 1) % SETS.len();
+        self.restart(commands);
+    }
+
+    // Every pair of tiles side by side, whichever order they were placed in, should agree on colour
+    fn verify(&self) {
+        let cells: std::collections::HashMap<(i32, i32), &WangTile> = self.tiling.get_tiles().map(|(_, tile)| (tile.cell, tile)).collect();
+        let mut shared = 0;
+        let mut mismatched = 0;
+        for tile in cells.values() {
+            for side in 0..2 {
+                if let Some(other) = cells.get(&tile.get_neighbour_cell(side)) {
+                    shared += 1;
+                    if !WangTile::sides_agree(tile.wang_type, side, other.wang_type, side + 2) {
+                        mismatched += 1;
+                    }
+                }
+            }
+        }
+        println!("{} tiles, {} shared sides, {} with different colours", cells.len(), shared, mismatched);
+    }
+}
+
+pub fn setup_wang(mut commands: Commands, mut tiler: ResMut<WangTiler>) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    tiler.grow(GROWTH_STEPS, &mut commands);
+}
+
+pub fn wang_controls(
+    mut tiler: ResMut<WangTiler>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut commands: Commands
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        tiler.grow(GROWTH_STEPS, &mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::R) {
+        tiler.restart(&mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::N) {
+        tiler.next_set(&mut commands);
+    } else if keyboard_input.just_pressed(KeyCode::V) {
+        tiler.verify();
+    }
+}